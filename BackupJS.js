@@ -81,11 +81,41 @@ var defaultConfig = {
     RecoveryBackupCore: "./plugins/BackupJS",
     serverExe: "bedrock_server_mod.exe",
     upload: {
-        remotePath: '/backup',  // 如果文件上传失败: 403 Forbidden （用户名和密码是正确）可能是没有webdav创建文件夹失败导致的
+        remotePath: '/backup',  // 上传前会逐级创建该目录，仍然 403 Forbidden 时请检查账号在 WebDAV 上的写入权限
         webdavUrl: 'https://xxx.com/webdav',
         username: '123',
         password: '114514',
-        allowInsecure: false   // 是否允许不安全的 HTTPS 连接（忽略证书验证）
+        allowInsecure: false,   // 是否允许不安全的 HTTPS 连接（忽略证书验证）
+        retries: 4,             // 网络错误或 5xx 时最多尝试次数
+        retryDelay: 1000,       // 第一次重试前等待的毫秒数，之后每次翻倍
+        retryMaxDelay: 30000,
+        strategy: 'single',     // 大文件上传方式: single、nextcloud（分块上传 v2）或 ranged（Content-Range PUT），中断后再次上传会续传
+        chunkSize: 10485760,    // 分块大小（字节）
+        concurrency: 4,         // 上传目录时同时上传的文件数，SFTP 会为每个同时上传的文件建立一个连接
+        skipUnchanged: true,    // 远程已有大小和校验和相同的文件时跳过，上传后都会核对远程文件
+        backend: 'webdav',      // 远程存储类型: webdav、s3（MinIO、Garage 等 S3 兼容存储）或 sftp，remotePath 在 S3 中作为键前缀
+        s3: {
+            endpoint: 'https://s3.example.com',
+            region: 'us-east-1',
+            bucket: 'backup',
+            accessKey: '',
+            secretKey: '',
+            pathStyle: false    // 自建服务通常需要 endpoint/bucket/key 形式的地址
+        },
+        sftp: {
+            host: '',
+            port: 22,
+            username: '',
+            password: '',
+            keyFile: '',        // 私钥文件，不为空时使用密钥认证
+            keyPassphrase: '',
+            knownHosts: ''      // 为空时使用 ~/.ssh/known_hosts，主机密钥必须已在其中
+        }
+    },
+    encryption: {
+        enabled: false,         // 上传前使用 AES-256-GCM 加密备份，远程文件名为 "<备份>.enc"，加密后的副本保存在备份旁边，用于重新上传和续传
+        keyFile: '',            // 密钥文件，不为空时优先于 passphrase
+        passphrase: ''          // 也可以通过环境变量 BACKUPJS_ENCRYPTION__PASSPHRASE 设置，恢复时需要同一个密钥
     },
     allowlist: ["114514"],
     Serein:{
@@ -204,7 +234,7 @@ function copyFolder(source, target, deleteAfterCopy = false, callback) {
     if (deleteAfterCopy) {
         command += " --delete";
     }
-    exec(command, (error, stdout, stderr) => {
+    exec(command, { maxBuffer: 64 * 1024 * 1024 }, (error, stdout, stderr) => { // 输出包含逐个文件的复制报告
         if (error) {
             sendMessage(null, `exec error: ${error}`, 'error');
             callback(false);
@@ -214,23 +244,6 @@ function copyFolder(source, target, deleteAfterCopy = false, callback) {
     });
 }
 
-function cleanupOldBackups(BackupPath, maxAgeDays, callback) {
-    const exePath = path.join(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe'); // Rust 程序路径
-    const format = config.format;
-    // 构建执行命令
-    const command = `"${exePath}" cleanup "${BackupPath}" "${maxAgeDays}" "${format}"`;
-
-    // 执行命令
-    exec(command, (error, stdout, stderr) => {
-        if (error) {
-            sendMessage(null, `exec error: ${error.message}`, 'error');
-            callback(false);
-            return;
-        }
-        callback(true);
-    });
-}
-
 
 function formatSize(bytes) {
     if (bytes >= 1024 ** 3) {
@@ -254,79 +267,6 @@ function formatDuration(ms) {
     }
 }
 
-function compressFolder(source, target, callback) {
-    const exePath = path.join(config["7za"], '7za.exe');
-    const compressLevel = config.Compress;
-    const maxWaitForZip = config.MaxWaitForZip;
-    const format = config.format;
-
-    // 检查7za.exe路径是否存在
-    if (!fs.existsSync(exePath)) {
-        sendMessage(null, `7za.exe not found at path: ${exePath}`, 'error');
-        callback(false, 0);
-        return;
-    }
-
-    // 根据格式选择相应的命令选项
-    let formatOption;
-    switch (format.toLowerCase()) {
-        case 'zip':
-            formatOption = '-tzip';
-            break;
-        case '7z':
-            formatOption = '-t7z';
-            break;
-        case 'tar':
-            formatOption = '-ttar';
-            break;
-        case 'gzip':
-            formatOption = '-tgzip';
-            break;
-        case 'bzip2':
-            formatOption = '-tbzip2';
-            break;
-        case 'xz':
-            formatOption = '-txz';
-            break;
-        default:
-            sendMessage(null, `不支持的压缩格式: ${format}`, 'error');
-            callback(false, 0);
-            return;
-    }
-
-    target = `${target}.${format}`;
-    
-    const command = `"${exePath}" a ${formatOption} -mx=${compressLevel} "${target}" "${source}\\*"`; // 使用通配符
-    //console.log(`Running command: ${command}`);
-
-
-    // 执行压缩命令
-    const compressProcess = exec(command, (error, stdout, stderr) => {
-        clearTimeout(timeout);
-        if (error) {
-            sendMessage(null, `exec error: ${error.message}`, 'error');
-            callback(false, 0);
-            return;
-        }
-        // 获取压缩文件的大小
-        fs.stat(target, (err, stats) => {
-            if (err) {
-                sendMessage(null, `Failed to get file size: ${err.message}`, 'error');
-                callback(false, 0);
-            } else {
-                callback(true, stats.size);
-            }
-        });
-    });
-
-    // 设置超时处理
-    const timeout = setTimeout(() => {
-        compressProcess.kill();
-        sendMessage(null, `压缩超时 (${maxWaitForZip} s)`, 'error');
-        callback(false, 0);
-    }, maxWaitForZip * 1000);
-}
-
 function getExtensionByFormat(format) {
     switch (format) {
         case 'zip':
@@ -346,27 +286,6 @@ function getExtensionByFormat(format) {
     }
 }
 
-function copydb(source, target, db, callback) {
-    const exePath = path.resolve(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe'); // Rust 程序路径
-
-    const tempDbFile = path.resolve(backup_tmp, 'db_list.txt');
-    fs.writeFileSync(tempDbFile, db + '\n', 'utf8'); 
-
-    // 将 db 列表的文件路径传递给命令行
-    let command = `"${exePath}" copy_db "${source}" "${target}" "${tempDbFile}"`;
-    //console.log(`${command}`);
-    
-    exec(command, (error, stdout, stderr) => {
-        if (error) {
-            sendMessage(null, `exec error: ${error}`, 'error');
-            callback(false);
-            return;
-        }
-        callback(true);
-    });
-}
-
-
 let isBackupInProgress = false;
 
 function backup(player, output, isPermanent = false) {
@@ -390,21 +309,6 @@ function backup(player, output, isPermanent = false) {
     }
     sendMessage(player, "开始执行备份...", 'info');
 
-	if (isPermanent) {
-	} else if (maxAgeDays !== -1) {
-		// 如果 maxAgeDays 有效且不是永久备份，执行旧备份清理
-		cleanupOldBackups(config.BackupPath, maxAgeDays, (cleanupResult) => {
-			if (cleanupResult) {
-				sendMessage(player, "旧备份清理完成", 'info');
-			} else {
-				sendMessage(player, "清理失败", 'error');
-			}
-		});
-	} else {
-		sendMessage(player, "跳过旧备份清理", 'info');
-	}
-
-
     const holdResult = mc.runcmdEx("save hold");
     if (!holdResult.success) {
         mc.runcmdEx("save resume");
@@ -428,30 +332,37 @@ function backup(player, output, isPermanent = false) {
 			//console.log(`DB 文件信息: ${db}`);  // 记录日志
             sendMessage(player, "数据已保存，可以开始复制。", 'info');
 
-            copydb(worldPath, backup_tmp, db, (result) => {
-                if (result) {
-					mc.runcmdEx("save resume");
-                    sendMessage(player, "数据复制完成。", 'info');
-
-                    compressFolder(backup_tmp, zipFileName, (compressResult, fileSize) => {
-                        const endTime = new Date();
-                        const duration = endTime - startTime;
-
-                        if (compressResult) {
-                            const formattedSize = formatSize(fileSize);
-                            const formattedDuration = formatDuration(duration);
-                            sendMessage(player, `备份完成，总耗时 ${formattedDuration}，文件大小 ${formattedSize}`, 'info');
-                            resettmp();
-                        } else {
-                            sendMessage(player, "压缩失败", 'error');
-                        }
-
-                        isBackupInProgress = false;
-                    });
+            const dbListFile = path.resolve(__dirname, 'db_list.txt');
+            fs.writeFileSync(dbListFile, db + '\n', 'utf8');
+            // 复制、压缩、写清单和清理旧备份由一个 backup 请求完成
+            const request = {
+                operation: 'backup',
+                source_world: path.resolve(worldPath),
+                staging_dir: path.resolve(backup_tmp),
+                db_list_file: dbListFile,
+                destination: zipFileName,
+                format: config.format,
+                level: config.Compress,
+                world_name: worldName,
+                trigger: isPermanent ? 'permanent' : (player ? 'manual' : 'scheduled'),
+                // 永久备份或 MaxStorageTime 为 -1 时不清理旧备份
+                cleanup: !isPermanent && maxAgeDays !== -1 ? { path: path.resolve(config.BackupPath), max_age_days: maxAgeDays } : null,
+            };
+            runBackupRequest(request, () => {
+                mc.runcmdEx("save resume");
+                sendMessage(player, "数据复制完成。", 'info');
+            }, (reply) => {
+                const duration = new Date() - startTime;
+                if (reply && reply.success) {
+                    const compressStage = reply.data.stages.find(stage => stage.name === 'compress');
+                    const formattedSize = formatSize(compressStage ? compressStage.bytes : 0);
+                    const formattedDuration = formatDuration(duration);
+                    sendMessage(player, `备份完成，总耗时 ${formattedDuration}，文件大小 ${formattedSize}`, 'info');
+                    resettmp();
                 } else {
-                    sendMessage(player, "数据复制失败。", 'error');
-                    isBackupInProgress = false;
+                    sendMessage(player, `备份失败: ${reply ? reply.error : '未知错误'}`, 'error');
                 }
+                isBackupInProgress = false;
             });
         } else {
             sendMessage(player, `查询保存状态失败或返回结果不匹配，重试第 ${attempt} 次。`, 'error');
@@ -462,6 +373,67 @@ function backup(player, output, isPermanent = false) {
     setTimeout(() => tryQuerySaveState(1), delayBeforeFirstQuery);
 }
 
+// 执行 backup 请求，copy_db 阶段结束后调用 onCopied 以便尽早恢复存档，最后以应答 (失败时可能为 null) 调用 callback
+function runBackupRequest(request, onCopied, callback) {
+    const exePath = path.resolve(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe');
+    if (!fs.existsSync(exePath)) {
+        sendMessage(null, `恢复程序不存在: ${exePath}`, 'error');
+        onCopied();
+        callback(null);
+        return;
+    }
+
+    let copied = false;
+    let reply = null;
+    let buffer = '';
+    const markCopied = () => {
+        if (!copied) {
+            copied = true;
+            onCopied();
+        }
+    };
+
+    // 进度事件和最终应答都是 stdout 上的一行 JSON，日志在 stderr 中
+    const parseLine = (line) => {
+        let event;
+        try {
+            event = JSON.parse(line);
+        } catch (e) {
+            return;
+        }
+        if (event.event === 'stage_finished' && event.stage === 'copy_db') {
+            markCopied();
+        } else if (event.event === 'result') {
+            reply = event;
+        }
+    };
+
+    const child = exec(`"${exePath}" --events ndjson request -`, { maxBuffer: 64 * 1024 * 1024 }, (error, stdout, stderr) => {
+        clearTimeout(timeout);
+        parseLine(buffer.trim());
+        markCopied();
+        if (!reply && error) {
+            sendMessage(null, `exec error: ${error.message}`, 'error');
+        }
+        callback(reply);
+    });
+    child.stdout.on('data', (chunk) => {
+        buffer += chunk;
+        let newline;
+        while ((newline = buffer.indexOf('\n')) !== -1) {
+            parseLine(buffer.slice(0, newline).trim());
+            buffer = buffer.slice(newline + 1);
+        }
+    });
+    child.stdin.end(JSON.stringify(request));
+
+    // 设置超时处理
+    const timeout = setTimeout(() => {
+        child.kill();
+        sendMessage(null, `备份超时 (${config.MaxWaitForZip} s)`, 'error');
+    }, config.MaxWaitForZip * 1000);
+}
+
 function getLangFromProperties() {
     const serverPropertiesPath = path.resolve('./server.properties');
     let lang = 'en_US'; // 默认语言
@@ -557,9 +529,6 @@ function recoverBackup(player, output, backupFilename,isPermanent = false) {
 	
 	}
 	
-    // 将路径转换为 Base64 编码 绕过中文路径
-    const base64BackupFilePath = Buffer.from(backupFilePath).toString('base64');
-
 	const exePath = path.resolve(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe');
 
     // 检查备份文件是否存在
@@ -574,9 +543,24 @@ function recoverBackup(player, output, backupFilename,isPermanent = false) {
         return;
     }
 
+	// 参数写入 JSON 请求文件，中文路径不再需要 Base64 编码
+	const request = {
+		operation: 'recover',
+		backup_file: backupFilePath,
+		target_dir: serverDir,
+		world_name: worldName,
+		server_exe: serverExe,
+		seven_zip: sevenZipPath,
+		url: url || null,
+		auth: auth || null,
+		encryption: encryptionSource(),
+	};
+	const requestFilePath = path.resolve(__dirname, 'recover_request.json');
+	fs.writeFileSync(requestFilePath, JSON.stringify(request), { encoding: 'utf8' });
+
 const batchContent = `
 @echo off
-"${exePath}" recover "${base64BackupFilePath}" "${serverDir}" "${worldName}" "${serverExe}" "${sevenZipPath}"${url ? ` "${url}"` : ''}${auth ? ` "${auth}"` : ''}`;
+"${exePath}" request "${requestFilePath}"`;
 
 
 	// 使用 UTF-8 编码写入批处理文件
@@ -612,6 +596,92 @@ function listBackups(player, output, isPermanent = false) {
         });
 }
 
+// backend 为 s3 时请求中使用的 S3 设置，否则为 null
+function remoteS3() {
+    const s3 = config.upload.s3;
+    if (config.upload.backend !== 's3' || !s3) {
+        return null;
+    }
+    return {
+        endpoint: s3.endpoint,
+        region: s3.region || 'us-east-1',
+        bucket: s3.bucket,
+        access_key: s3.accessKey,
+        secret_key: s3.secretKey,
+        path_style: !!s3.pathStyle,
+    };
+}
+
+// backend 为 sftp 时请求中使用的 SFTP 设置，否则为 null
+function remoteSftp() {
+    const sftp = config.upload.sftp;
+    if (config.upload.backend !== 'sftp' || !sftp) {
+        return null;
+    }
+    return {
+        host: sftp.host,
+        port: sftp.port || 22,
+        username: sftp.username,
+        password: sftp.password || '',
+        key_file: sftp.keyFile || null,
+        key_passphrase: sftp.keyPassphrase || null,
+        known_hosts: sftp.knownHosts || null,
+    };
+}
+
+// 请求中使用的加密设置，没有设置密钥时为 null（仍会使用环境变量中的密钥）
+function encryptionSource() {
+    const encryption = config.encryption;
+    if (!encryption || (!encryption.keyFile && !encryption.passphrase)) {
+        return null;
+    }
+    return {
+        key_file: encryption.keyFile || null,
+        passphrase: encryption.passphrase || null,
+    };
+}
+
+// 列出远程存储上已有的备份
+function listRemoteBackups(player, output) {
+    const exePath = path.join(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe');
+    const request = {
+        operation: 'remote_list',
+        remote_path: config.upload.remotePath,
+        webdav_url: config.upload.webdavUrl,
+        username: config.upload.username,
+        password: config.upload.password,
+        allow_insecure: !!config.upload.allowInsecure,
+        s3: remoteS3(),
+        sftp: remoteSftp(),
+    };
+
+    const child = exec(`"${exePath}" request -`, (error, stdout, stderr) => {
+        let reply;
+        try {
+            reply = JSON.parse(stdout.trim());
+        } catch (e) {
+            sendMessage(player, `获取远程备份列表时出错: ${error ? error.message : stderr}`, 'error');
+            return;
+        }
+        if (!reply.success) {
+            sendMessage(player, `获取远程备份列表时出错: ${reply.error}`, 'error');
+            return;
+        }
+        const files = reply.data.entries.filter(entry => !entry.is_collection);
+        if (files.length === 0) {
+            sendMessage(player, '远程目录中没有任何备份文件。', 'info');
+            return;
+        }
+        sendMessage(player, '远程备份文件:', 'info');
+        files.forEach(entry => {
+            const size = entry.size === null ? '?' : `${(entry.size / 1024 / 1024).toFixed(2)} MB`;
+            const time = entry.last_modified ? new Date(entry.last_modified).toLocaleString() : '';
+            sendMessage(player, `${entry.path}  ${size}  ${time}`, 'info');
+        });
+    });
+    child.stdin.end(JSON.stringify(request));
+}
+
 function uploadBackup(player, output, backupName, isPermanent = false) {
     const exePath = path.join(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe');
     const BackupDir = isPermanent ? config.PermanentBackupPath : config.BackupPath;
@@ -622,7 +692,7 @@ function uploadBackup(player, output, backupName, isPermanent = false) {
     const webdavUrl = config.upload.webdavUrl;
     const username = config.upload.username;
     const password = config.upload.password;
-    const allowInsecure = config.upload.allowInsecure ? 'true' : 'false'; // 转换为字符串 'true' 或 'false'
+    const allowInsecure = !!config.upload.allowInsecure;
     
     // 检查备份文件是否存在
     if (!fs.existsSync(backupFilePath)) {
@@ -630,31 +700,48 @@ function uploadBackup(player, output, backupName, isPermanent = false) {
         return;
     }
 
-    // 构建命令
-    const command = `"${exePath}" upload "${backupFilePath}" "${remotePath}" "${webdavUrl}" "${username}" "${password}" ${allowInsecure}`;
+    // 通过 stdin 传入 JSON 请求，密码不会出现在命令行中
+    const request = {
+        operation: 'upload',
+        backup_file: backupFilePath,
+        remote_path: remotePath,
+        webdav_url: webdavUrl,
+        username: username,
+        password: password,
+        allow_insecure: allowInsecure,
+        s3: remoteS3(),
+        sftp: remoteSftp(),
+        retry: {
+            max_attempts: config.upload.retries,
+            initial_delay_ms: config.upload.retryDelay,
+            max_delay_ms: config.upload.retryMaxDelay,
+        },
+        strategy: config.upload.strategy,
+        chunk_size: config.upload.chunkSize,
+        concurrency: config.upload.concurrency,
+        skip_unchanged: config.upload.skipUnchanged !== false,
+        encryption: config.encryption && config.encryption.enabled ? (encryptionSource() || {}) : null,
+    };
+    const command = `"${exePath}" request -`;
 
-    //console.log(`${command}`);
     // 执行上传命令
      sendMessage(player, "正在上传中...", 'info');
-    exec(command, (error, stdout, stderr) => {
-        if (error) {
-            sendMessage(player, `上传时出错: ${error.message}`, 'error');
+    const child = exec(command, (error, stdout, stderr) => {
+        // stdout 只包含一行 JSON 应答，日志在 stderr 中
+        let reply;
+        try {
+            reply = JSON.parse(stdout.trim());
+        } catch (e) {
+            sendMessage(player, `上传时出错: ${error ? error.message : stderr}`, 'error');
             return;
         }
-
-        if (stderr) {
-            sendMessage(player, `上传警告: ${stderr}`, 'warning');
+        if (!reply.success) {
+            sendMessage(player, `上传时出错: ${reply.error}`, 'error');
             return;
         }
-        const cleanedOutput = stdout
-		  // 去除日期时间戳和 [INFO] 部分
-		  .replace(/\[\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}\] \[INFO\] /g, '')
-		  // 去除 ANSI 颜色控制符
-		  .replace(/\x1b\[[0-9;]*m/g, '')
-		  .trim();
-
-        sendMessage(player, `上传成功: ${cleanedOutput}`, 'info');
+        sendMessage(player, `上传成功: ${backupFilePath}`, 'info');
     });
+    child.stdin.end(JSON.stringify(request));
 }
 
 
@@ -991,24 +1078,6 @@ function listPermanentBackupsGUI(player, output) {
     });
 }
 
-function cleanupOldBackups(BackupPath, maxAgeDays, callback) {
-    const exePath = path.join(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe'); // Rust 程序路径
-    const format = config.format;
-    // 构建执行命令
-    const command = `"${exePath}" cleanup "${BackupPath}" "${maxAgeDays}" "${format}"`;
-
-    // 执行命令
-    exec(command, (error, stdout, stderr) => {
-        if (error) {
-            sendMessage(null, `exec error: ${error.message}`, 'error');
-            callback(false);
-            return;
-        }
-        callback(true);
-    });
-}
-
-
 function getBackupStats(callback) {
     const exePath = path.join(config.RecoveryBackupCore, 'Recovery_Backup_Core.exe');
     const worldPath = path.resolve(`./worlds/${worldName}`);
@@ -1196,6 +1265,7 @@ function registerCommands() {
 	cmd.setEnum("GUIAction", ["gui"]);
     cmd.setEnum("ListAction", ["list"]);
     cmd.setEnum("StatsAction", ["stats"]);
+    cmd.setEnum("RemoteAction", ["remote"]);
     
     cmd.setEnum("RemoveAction", ["remove"]);
     cmd.setEnum("UploadAction", ["upload"]);
@@ -1221,6 +1291,7 @@ function registerCommands() {
     cmd.mandatory("action", ParamType.Enum, "GUIAction", 1);
     cmd.mandatory("action", ParamType.Enum, "ListAction", 1);
     cmd.mandatory("action", ParamType.Enum, "StatsAction", 1)
+    cmd.mandatory("action", ParamType.Enum, "RemoteAction", 1);
     
     
     cmd.mandatory("action", ParamType.Enum, "RemoveAction", 1);
@@ -1248,6 +1319,7 @@ function registerCommands() {
     cmd.overload(["GUIAction"]);
     cmd.overload(["ListAction"]);
     cmd.overload(["StatsAction"]);
+    cmd.overload(["RemoteAction"]);
     
     cmd.overload(["RemoveAction","filename"]);
     cmd.overload(["UploadAction","filename"]);
@@ -1328,6 +1400,9 @@ function registerCommands() {
                     case "stats":
                         showBackupStats(player, output);
                         break;
+                    case "remote":
+                        listRemoteBackups(player, output);
+                        break;
                     case "rename":
                     const newname = results.newname;
                             renameBackup(player, output, filename, newname);
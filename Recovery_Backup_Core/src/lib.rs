@@ -1 +1,3 @@
+#![allow(non_snake_case)]
+
 pub mod utils;
\ No newline at end of file
@@ -1,22 +1,290 @@
+#![allow(non_snake_case)]
+
+mod request;
+
 use std::env;
-use std::path::{Path};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use base64::Engine;
 use base64::engine::general_purpose;
-use rayon::prelude::*;
+use serde::Deserialize;
 use tracing::{error, info};
+use Recovery_Backup_Core::utils::archive::{archive_path_for, compress_dir, ArchiveFormat, CompressOptions};
+use Recovery_Backup_Core::utils::backup::{read_backup_request, run_backup};
 use Recovery_Backup_Core::utils::cleanup::delete_old_backups;
 use Recovery_Backup_Core::utils::copy::copy_dir_recursive;
+use Recovery_Backup_Core::utils::config::{read_level_name, Config, ENV_CONFIG};
 use Recovery_Backup_Core::utils::copy_db::copy_db;
+use Recovery_Backup_Core::utils::crypto::{is_encrypted, EncryptionKey, EncryptionSource, ENCRYPTED_SUFFIX};
+use Recovery_Backup_Core::utils::download::download_backup;
+use Recovery_Backup_Core::utils::events::{emit_reply, emit_result, init_events, EventMode};
 use Recovery_Backup_Core::utils::logger::init_logger;
+use Recovery_Backup_Core::utils::manifest::{latest_backup, Trigger};
+use Recovery_Backup_Core::utils::process::{LaunchCommand, ServerController};
 use Recovery_Backup_Core::utils::recover::recover_backup;
+use Recovery_Backup_Core::utils::report::ErrorMode;
+use Recovery_Backup_Core::utils::repository::{Repository, SnapshotInfo};
+use Recovery_Backup_Core::utils::remote_retention::apply_remote_retention;
+use Recovery_Backup_Core::utils::retention::{apply_retention, RetentionPolicy};
 use Recovery_Backup_Core::utils::stats::{get_directory_stats_sync, DirectoryStats};
-use Recovery_Backup_Core::utils::upload::upload_backup;
-use Recovery_Backup_Core::utils::utils::{is_base64_encoded, send_request};
+use Recovery_Backup_Core::utils::chunked::UploadStrategy;
+use Recovery_Backup_Core::utils::upload::{upload_backup, UploadOptions};
+use Recovery_Backup_Core::utils::utils::{is_base64_encoded, parse_size, send_request};
+use Recovery_Backup_Core::utils::verify::verify_backup;
+use Recovery_Backup_Core::utils::storage::{list_remote, Depth, RemoteSource};
+
+// 记录错误，输出失败结果后退出
+fn fail(message: String) -> ! {
+    error!("{}", message);
+    emit_result::<()>(false, Some(message), None);
+    std::process::exit(1);
+}
+
+// 取出 `--name value` 或 `--name=value` 形式的选项，其余参数保持原有位置
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let prefix = format!("{}=", name);
+    let Some(pos) = args.iter().position(|arg| arg == name || arg.starts_with(&prefix)) else {
+        return Ok(None);
+    };
+    let arg = args.remove(pos);
+    match arg.strip_prefix(&prefix) {
+        Some(value) => Ok(Some(value.to_string())),
+        None if pos < args.len() => Ok(Some(args.remove(pos))),
+        None => Err(format!("{} requires a value", name)),
+    }
+}
+
+// 取出不带值的开关选项
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(pos) => {
+            args.remove(pos);
+            true
+        }
+        None => false,
+    }
+}
+
+// 取出可以重复出现的选项
+fn take_options(args: &mut Vec<String>, name: &str) -> Result<Vec<String>, String> {
+    let mut values = Vec::new();
+    while let Some(value) = take_option(args, name)? {
+        values.push(value);
+    }
+    Ok(values)
+}
+
+// 取出全局的 --events <mode> 参数
+fn take_event_mode(args: &mut Vec<String>) -> Result<EventMode, String> {
+    match take_option(args, "--events")? {
+        Some(name) => EventMode::from_name(&name).ok_or_else(|| format!("Unknown events mode: {}", name)),
+        None => Ok(EventMode::None),
+    }
+}
+
+// copy 和 copy_db 的 --on-error 选项
+fn take_error_mode(args: &mut Vec<String>) -> Result<ErrorMode, String> {
+    match take_option(args, "--on-error")? {
+        Some(name) => ErrorMode::from_name(&name).ok_or_else(|| format!("Invalid --on-error value: {}", name)),
+        None => Ok(ErrorMode::FailFast),
+    }
+}
+
+// retention 的保留策略选项
+fn take_retention_policy(args: &mut Vec<String>) -> Result<RetentionPolicy, String> {
+    let mut take_count = |name: &str| -> Result<usize, String> {
+        match take_option(args, name)? {
+            Some(value) => value.parse().map_err(|_| format!("Invalid {} value: {}", name, value)),
+            None => Ok(0),
+        }
+    };
+    let mut policy = RetentionPolicy {
+        keep_last: take_count("--keep-last")?,
+        keep_daily: take_count("--keep-daily")?,
+        keep_weekly: take_count("--keep-weekly")?,
+        keep_monthly: take_count("--keep-monthly")?,
+        min_keep: take_count("--min-keep")?,
+        max_total_size: None,
+        max_age_days: None,
+    };
+    if let Some(value) = take_option(args, "--max-size")? {
+        policy.max_total_size = Some(parse_size(&value).ok_or_else(|| format!("Invalid --max-size value: {}", value))?);
+    }
+    if let Some(value) = take_option(args, "--max-age")? {
+        policy.max_age_days = Some(value.parse().map_err(|_| format!("Invalid --max-age value: {}", value))?);
+    }
+    Ok(policy)
+}
+
+// upload 的重试和分块选项，未指定的项使用配置或默认值
+fn take_upload_options(args: &mut Vec<String>, config: Option<&Config>) -> Result<UploadOptions, String> {
+    let mut options = config.map(Config::upload_options).unwrap_or_default();
+    let policy = &mut options.retry;
+    if let Some(value) = take_option(args, "--retries")? {
+        policy.max_attempts = value.parse().map_err(|_| format!("Invalid --retries value: {}", value))?;
+    }
+    if let Some(value) = take_option(args, "--retry-delay")? {
+        policy.initial_delay_ms = value.parse().map_err(|_| format!("Invalid --retry-delay value: {}", value))?;
+    }
+    if let Some(value) = take_option(args, "--retry-max-delay")? {
+        policy.max_delay_ms = value.parse().map_err(|_| format!("Invalid --retry-max-delay value: {}", value))?;
+    }
+    if let Some(value) = take_option(args, "--strategy")? {
+        options.strategy = UploadStrategy::from_name(&value).ok_or_else(|| format!("Invalid --strategy value: {}", value))?;
+    }
+    if let Some(value) = take_option(args, "--chunk-size")? {
+        options.chunk_size = parse_size(&value).filter(|size| *size > 0).ok_or_else(|| format!("Invalid --chunk-size value: {}", value))?;
+    }
+    if let Some(value) = take_option(args, "--concurrency")? {
+        options.concurrency = value.parse().ok().filter(|count| *count > 0).ok_or_else(|| format!("Invalid --concurrency value: {}", value))?;
+    }
+    Ok(options)
+}
+
+// 命令行 --key-file 或 --passphrase 指定的密钥，都没有时为 None
+fn take_encryption_key(args: &mut Vec<String>) -> Result<Option<EncryptionKey>, String> {
+    let key_file = take_option(args, "--key-file")?;
+    let passphrase = take_option(args, "--passphrase")?;
+    let key = match (key_file, passphrase) {
+        (Some(_), Some(_)) => return Err("--key-file 和 --passphrase 只能给出一个".to_string()),
+        (Some(path), None) => EncryptionKey::from_file(Path::new(&path)).map(Some),
+        (None, Some(passphrase)) => EncryptionKey::from_passphrase(&passphrase).map(Some),
+        (None, None) => Ok(None),
+    };
+    key.map_err(|e| e.to_string())
+}
+
+// 命令行没有给出密钥时，只有需要加密或解密 (wanted) 才使用配置中的 encryption 或 BACKUPJS_ENCRYPTION__ 环境变量
+fn encryption_key(given: Option<EncryptionKey>, config: Option<&Config>, wanted: bool) -> Result<Option<EncryptionKey>, String> {
+    if given.is_some() || !wanted {
+        return Ok(given);
+    }
+    let key = match config {
+        Some(config) => config.encryption_key(),
+        None => EncryptionSource::default().key(),
+    };
+    key.map_err(|e| e.to_string())
+}
+
+// 配置启用了加密
+fn encryption_enabled(config: Option<&Config>) -> bool {
+    config.is_some_and(|config| config.encryption.enabled)
+}
+
+// 取出全局的 --config <file> 和 --set key=value 参数，两者都没有且未设置 BACKUPJS_CONFIG 时不加载配置
+fn take_config(args: &mut Vec<String>) -> Result<Option<Config>, String> {
+    let path = take_option(args, "--config")?.or_else(|| env::var(ENV_CONFIG).ok());
+    let overrides = take_options(args, "--set")?;
+    if path.is_none() && overrides.is_empty() {
+        return Ok(None);
+    }
+    Config::load(path.as_deref().map(Path::new), &overrides).map(Some).map_err(|e| e.to_string())
+}
+
+// 使用配置时，只给出前 given 个参数的命令由配置补全其余的位置参数
+fn fill_from_config<F>(args: &mut Vec<String>, config: Option<&Config>, given: usize, fill: F)
+where
+    F: FnOnce(&Config) -> Result<Vec<String>, String>,
+{
+    if let Some(config) = config {
+        if args.len() == given {
+            args.extend(fill(config).unwrap_or_else(|e| fail(e)));
+        }
+    }
+}
+
+// 只给出前 given 个参数且有配置时使用配置中的远程存储（WebDAV 或 S3），
+// 否则从第 given 个参数起取出 <remote_path> <webdav_url> <username> <password> <allow_insecure>，参数不足时返回 None
+fn take_remote(args: &mut Vec<String>, config: Option<&Config>, given: usize) -> Result<Option<RemoteSource>, String> {
+    if let Some(config) = config.filter(|_| args.len() == given) {
+        return config.remote_source().map(Some).ok_or_else(|| "配置中没有设置远程存储 (upload.webdavUrl、upload.s3 或 upload.sftp)".to_string());
+    }
+    if args.len() < given + 5 {
+        return Ok(None);
+    }
+    let values: Vec<String> = args.drain(given..given + 5).collect();
+    Ok(Some(RemoteSource {
+        remote_path: values[0].clone(),
+        webdav_url: values[1].clone(),
+        username: values[2].clone(),
+        password: values[3].clone(),
+        allow_insecure: values[4].parse().unwrap_or(false),
+        s3: None,
+        sftp: None,
+    }))
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+// 当前世界名取自服务器目录下的 server.properties
+fn level_name() -> Result<String, String> {
+    read_level_name(Path::new(".")).map_err(|e| format!("读取世界名失败: {}", e))
+}
+
+// recover 的服务器进程控制选项，JSON 请求中使用同名字段
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ServerOptions {
+    workdir: Option<String>,
+    start_command: Option<String>,
+    // NAME=VALUE 形式
+    env: Vec<String>,
+    pidfile: Option<String>,
+    // 秒
+    grace: Option<u64>,
+}
+
+impl ServerOptions {
+    fn take(args: &mut Vec<String>) -> Result<Self, String> {
+        let grace = match take_option(args, "--grace")? {
+            Some(grace) => Some(grace.parse().map_err(|_| format!("Invalid --grace value: {}", grace))?),
+            None => None,
+        };
+        Ok(ServerOptions {
+            workdir: take_option(args, "--workdir")?,
+            start_command: take_option(args, "--start-command")?,
+            env: take_options(args, "--env")?,
+            pidfile: take_option(args, "--pidfile")?,
+            grace,
+        })
+    }
+
+    fn into_controller(self, executable: PathBuf, target_dir: &Path) -> Result<ServerController, String> {
+        let working_dir = self.workdir.map(PathBuf::from).unwrap_or_else(|| target_dir.to_path_buf());
+        let mut server = ServerController::new(executable, &working_dir);
+
+        if let Some(command_line) = self.start_command {
+            server.launch = LaunchCommand::parse(&command_line, &working_dir).map_err(|e| e.to_string())?;
+        }
+        for assignment in self.env {
+            let (name, value) = assignment
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid --env value (expected NAME=VALUE): {}", assignment))?;
+            server.launch.env.push((name.to_string(), value.to_string()));
+        }
+        server.pidfile = self.pidfile.map(|pidfile| working_dir.join(pidfile));
+        if let Some(seconds) = self.grace {
+            server.grace_period = Duration::from_secs(seconds);
+        }
+        Ok(server)
+    }
+}
 
 #[tokio::main]
 async fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let event_mode = take_event_mode(&mut args);
     init_logger();
-    let args: Vec<String> = env::args().collect();
+    let event_mode = event_mode.unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    });
+    init_events(event_mode);
+    let config = take_config(&mut args).unwrap_or_else(|e| fail(e));
+    let config = config.as_ref();
+
     if args.len() < 2 {
         error!("Usage: {} <operation> [additional arguments...]", args[0]);
         std::process::exit(1);
@@ -26,8 +294,17 @@ async fn main() {
 
     match operation.as_str() {
         "copy_db" => {
+            let mut args = args.clone();
+            let base_dir = take_option(&mut args, "--base").unwrap_or_else(|e| fail(e));
+            let mode = take_error_mode(&mut args).unwrap_or_else(|e| fail(e));
+            // 与插件相同，暂存到 backup_tmp
+            fill_from_config(&mut args, config, 2, |_| {
+                let level_name = level_name()?;
+                Ok(vec![format!("worlds/{}", level_name), format!("backup_tmp/{}", level_name), "backup_tmp/db_list.txt".to_string()])
+            });
+
             if args.len() != 5 {
-                error!("Usage for copy_db: {} copy_db <source_world> <destination_world> <db_files>", args[0]);
+                error!("Usage for copy_db: {} copy_db <source_world> <destination_world> <db_files> [--base <previous_staging_dir>] [--on-error fail-fast|best-effort]", args[0]);
                 std::process::exit(1);
             }
 
@@ -35,19 +312,64 @@ async fn main() {
             let destination_world = Path::new(&args[3]);
             let db_list_file = Path::new(&args[4]);
 
-            match copy_db(&source_world, &destination_world, &db_list_file) {
-                Ok(_) => info!("数据文件复制成功。"),
-                Err(e) => {
-                    error!("复制数据文件时出错: {}", e);
+            match copy_db(source_world, destination_world, db_list_file, base_dir.as_deref().map(Path::new), mode) {
+                Ok(report) if report.success => {
+                    info!("数据文件复制成功。");
+                    emit_result(true, None, Some(&report));
+                }
+                Ok(report) => {
+                    error!("复制数据文件时出错: {}", report.summary());
+                    emit_result(false, Some(report.summary()), Some(&report));
                     std::process::exit(1);
                 }
+                Err(e) => fail(format!("复制数据文件时出错: {}", e)),
             }
         }
 
 
+        "request" => {
+            if args.len() > 3 {
+                error!("Usage for request: {} request [<request_file>|-]", args[0]);
+                std::process::exit(1);
+            }
+
+            let request = request::read_request(args.get(2).map(String::as_str)).unwrap_or_else(|e| {
+                error!("读取请求失败: {}", e);
+                emit_reply(false, Some(e.to_string()), None);
+                std::process::exit(1);
+            });
+            let reply = request::execute(request).await;
+            emit_reply(reply.success, reply.error, reply.data);
+            if !reply.success {
+                std::process::exit(1);
+            }
+        }
+
+        "backup" => {
+            if args.len() != 3 {
+                error!("Usage for backup: {} backup <request_file>", args[0]);
+                std::process::exit(1);
+            }
+
+            let request = read_backup_request(Path::new(&args[2])).unwrap_or_else(|e| {
+                error!("读取备份请求失败: {}", e);
+                std::process::exit(1);
+            });
+
+            let result = run_backup(&request).await;
+            let error = result.stages.iter().find_map(|stage| stage.error.clone());
+            emit_result(result.success, error, Some(&result));
+            if !result.success {
+                std::process::exit(1);
+            }
+        }
+
         "copy" => {
+            let mut args = args.clone();
+            let mode = take_error_mode(&mut args).unwrap_or_else(|e| fail(e));
+
             if args.len() < 4 || args.len() > 5 {
-                error!("Usage for copy: {} copy <source> <destination> [--delete]", args[0]);
+                error!("Usage for copy: {} copy <source> <destination> [--delete] [--on-error fail-fast|best-effort]", args[0]);
                 std::process::exit(1);
             }
 
@@ -57,16 +379,58 @@ async fn main() {
             // 判断是否有 --delete 参数
             let delete_after_copy = args.len() == 5 && args[4] == "--delete";
 
-            match copy_dir_recursive(&source, &destination, delete_after_copy) {
-                Ok(_) => info!("复制已成功完成。"),
-                Err(e) => {
-                    error!("Error during copy: {}", e);
+            match copy_dir_recursive(source, destination, delete_after_copy, mode) {
+                Ok(report) if report.success => {
+                    info!("复制已成功完成。");
+                    emit_result(true, None, Some(&report));
+                }
+                Ok(report) => {
+                    error!("Error during copy: {}", report.summary());
+                    emit_result(false, Some(report.summary()), Some(&report));
                     std::process::exit(1);
                 }
+                Err(e) => fail(format!("Error during copy: {}", e)),
+            }
+        }
+
+        "compress" => {
+            let mut args = args.clone();
+            fill_from_config(&mut args, config, 4, |config| Ok(vec![config.format.clone(), config.compress.to_string()]));
+
+            if args.len() < 5 || args.len() > 7 {
+                error!("Usage for compress: {} compress <source> <destination> <format> [level] [threads]", args[0]);
+                std::process::exit(1);
+            }
+
+            let source = Path::new(&args[2]);
+            let format = ArchiveFormat::from_name(&args[4]).unwrap_or_else(|| {
+                error!("不支持的压缩格式: {}", args[4]);
+                std::process::exit(1);
+            });
+            let destination = archive_path_for(Path::new(&args[3]), format);
+
+            let level: u32 = args.get(5).map_or(Ok(5), |s| s.parse()).unwrap_or_else(|_| {
+                error!("Invalid level value");
+                std::process::exit(1);
+            });
+            let threads: usize = args.get(6).map_or(Ok(rayon::current_num_threads()), |s| s.parse()).unwrap_or_else(|_| {
+                error!("Invalid threads value");
+                std::process::exit(1);
+            });
+
+            let options = CompressOptions { format, level, threads };
+            match compress_dir(source, &destination, &options) {
+                Ok(stats) => emit_result(true, None, Some(&stats)),
+                Err(e) => fail(format!("压缩时出错: {}", e)),
             }
         }
 
         "cleanup" => {
+            let mut args = args.clone();
+            fill_from_config(&mut args, config, 2, |config| {
+                Ok(vec![path_arg(&config.backup_path), config.max_storage_time.to_string(), config.archive_format().extension().to_string()])
+            });
+
             if args.len() != 5 {
                 error!("Usage for cleanup: {} cleanup <path> <max_age_days> <extension>", args[0]);
                 std::process::exit(1);
@@ -80,14 +444,145 @@ async fn main() {
 
             let extension = &args[4];
 
-            if let Err(e) = delete_old_backups(&path, max_age_days, extension) {
-                error!("Error during old backup cleanup: {}", e);
+            match delete_old_backups(path, max_age_days, extension) {
+                Ok(deleted) => emit_result(true, None, Some(&serde_json::json!({ "deleted": deleted }))),
+                Err(e) => fail(format!("Error during old backup cleanup: {}", e)),
+            }
+        }
+        "retention" => {
+            let mut args = args.clone();
+            let dry_run = take_flag(&mut args, "--dry-run");
+            let policy = take_retention_policy(&mut args).unwrap_or_else(|e| fail(e));
+            fill_from_config(&mut args, config, 2, |config| {
+                Ok(vec![path_arg(&config.backup_path), config.archive_format().extension().to_string()])
+            });
+
+            if args.len() != 4 {
+                error!("Usage for retention: {} retention <path> <extension> [--keep-last N] [--keep-daily N] [--keep-weekly N] [--keep-monthly N] [--max-size SIZE] [--max-age DAYS] [--min-keep N] [--dry-run]", args[0]);
+                std::process::exit(1);
+            }
+
+            match apply_retention(Path::new(&args[2]), &args[3], &policy, dry_run) {
+                Ok(report) if report.failed == 0 => emit_result(true, None, Some(&report)),
+                Ok(report) => {
+                    emit_result(false, Some(format!("Error during backup retention: {}", report.summary())), Some(&report));
+                    std::process::exit(1);
+                }
+                Err(e) => fail(format!("Error during backup retention: {}", e)),
+            }
+        }
+
+        "remote-retention" => {
+            let mut args = args.clone();
+            let dry_run = take_flag(&mut args, "--dry-run");
+            let mut policy = take_retention_policy(&mut args).unwrap_or_else(|e| fail(e));
+            let options = take_upload_options(&mut args, config).unwrap_or_else(|e| fail(e));
+            let key = take_encryption_key(&mut args).unwrap_or_else(|e| fail(e));
+            let source = take_remote(&mut args, config, 2).unwrap_or_else(|e| fail(e));
+            if let Some(config) = config.filter(|_| args.len() == 2) {
+                // 没有指定任何规则时与本地 cleanup 一样按 MaxStorageTime 清理
+                if policy.is_empty() {
+                    policy.max_age_days = Some(config.max_storage_time);
+                }
+                // 启用加密时远程的备份名为 "<压缩包>.enc"
+                let suffix = if config.encryption.enabled { ENCRYPTED_SUFFIX } else { "" };
+                args.push(format!("{}{}", config.archive_format().extension(), suffix));
+            }
+
+            let Some(source) = source.filter(|_| args.len() == 3) else {
+                error!("Usage for remote-retention: {} remote-retention [<remote_path> <webdav_url> <username> <password> <allow_insecure>] <extension> [--keep-last N] [--keep-daily N] [--keep-weekly N] [--keep-monthly N] [--max-size SIZE] [--max-age DAYS] [--min-keep N] [--dry-run] [--key-file <file> | --passphrase <pass>]", args[0]);
                 std::process::exit(1);
+            };
+            // 加密备份的清单也是加密的，读取创建时间和是否永久保留需要密钥
+            let wanted = encryption_enabled(config) || args[2].ends_with(ENCRYPTED_SUFFIX);
+            let key = encryption_key(key, config, wanted).unwrap_or_else(|e| fail(e));
+
+            let backend = source.open(&options.retry).unwrap_or_else(|e| fail(format!("Error during remote retention: {}", e)));
+            match apply_remote_retention(backend.as_ref(), &args[2], &policy, dry_run, key.as_ref()).await {
+                Ok(report) if report.failed == 0 => emit_result(true, None, Some(&report)),
+                Ok(report) => {
+                    emit_result(false, Some(format!("Error during remote retention: {}", report.summary())), Some(&report));
+                    std::process::exit(1);
+                }
+                Err(e) => fail(format!("Error during remote retention: {}", e)),
+            }
+        }
+
+        "repo" => {
+            let mut args = args.clone();
+            let dry_run = take_flag(&mut args, "--dry-run");
+            let policy = take_retention_policy(&mut args).unwrap_or_else(|e| fail(e));
+            let world = take_option(&mut args, "--world").unwrap_or_else(|e| fail(e));
+            let trigger = take_option(&mut args, "--trigger").unwrap_or_else(|e| fail(e));
+
+            let usage = || -> ! {
+                error!("Usage for repo: {0} repo backup <repo> <source_dir> [--world <name>] [--trigger manual|scheduled|permanent]\n  {0} repo restore <repo> <snapshot_id|latest> <target_dir>\n  {0} repo list <repo>\n  {0} repo prune <repo> [--keep-last N] [--keep-daily N] [--keep-weekly N] [--keep-monthly N] [--max-size SIZE] [--max-age DAYS] [--min-keep N] [--dry-run]", args[0]);
+                std::process::exit(1);
+            };
+            if args.len() < 4 {
+                usage();
+            }
+            let repo_path = Path::new(&args[3]);
+
+            match (args[2].as_str(), args.len()) {
+                ("backup", 5) => {
+                    let source = Path::new(&args[4]);
+                    let world_name = world.unwrap_or_else(|| source.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default());
+                    let trigger = match trigger.as_deref() {
+                        Some(name) => serde_json::from_value(serde_json::Value::String(name.to_string()))
+                            .unwrap_or_else(|_| fail(format!("Invalid --trigger value: {}", name))),
+                        None => Trigger::Manual,
+                    };
+                    match Repository::open_or_init(repo_path).and_then(|repo| repo.backup(source, &world_name, trigger)) {
+                        Ok(snapshot) => emit_result(true, None, Some(&SnapshotInfo::from(&snapshot))),
+                        Err(e) => fail(format!("Error during repository backup: {}", e)),
+                    }
+                }
+                ("restore", 6) => {
+                    match Repository::open(repo_path).and_then(|repo| repo.restore(&args[4], Path::new(&args[5]))) {
+                        Ok(snapshot) => emit_result(true, None, Some(&SnapshotInfo::from(&snapshot))),
+                        Err(e) => fail(format!("Error during repository restore: {}", e)),
+                    }
+                }
+                ("list", 4) => {
+                    match Repository::open(repo_path).and_then(|repo| repo.snapshots()) {
+                        Ok(snapshots) => {
+                            let infos: Vec<SnapshotInfo> = snapshots.iter().map(SnapshotInfo::from).collect();
+                            emit_result(true, None, Some(&infos));
+                        }
+                        Err(e) => fail(format!("Error listing repository: {}", e)),
+                    }
+                }
+                ("prune", 4) => {
+                    match Repository::open(repo_path).and_then(|repo| repo.prune(&policy, dry_run)) {
+                        Ok(report) => emit_result(true, None, Some(&report)),
+                        Err(e) => fail(format!("Error during repository prune: {}", e)),
+                    }
+                }
+                _ => usage(),
             }
         }
+
         "recover" => {
+            let mut args = args.clone();
+            let server_options = ServerOptions::take(&mut args).unwrap_or_else(|e| fail(e));
+            let force = take_flag(&mut args, "--force");
+            let from_remote = take_flag(&mut args, "--from-remote");
+            if from_remote && config.is_none() {
+                fail("--from-remote 需要 --config 提供远程存储设置".to_string());
+            }
+            let download_dir = take_option(&mut args, "--download-dir").unwrap_or_else(|e| fail(e));
+            let key = take_encryption_key(&mut args).unwrap_or_else(|e| fail(e));
+            fill_from_config(&mut args, config, 3, |config| {
+                let mut values = vec![".".to_string(), level_name()?, config.server_exe.clone(), path_arg(&config.seven_zip_exe())];
+                if let Some((url, auth)) = config.serein_recover() {
+                    values.extend([url, auth]);
+                }
+                Ok(values)
+            });
+
             if args.len() < 7 || args.len() > 9 {
-                error!("Usage for recover: {} recover <backup_file> <target_dir> <world_name> <server_exe> <7za_exe>", args[0]);
+                error!("Usage for recover: {} recover <backup_file> <target_dir> <world_name> <server_exe> <7za_exe|-> [url] [auth] [--start-command <cmd>] [--workdir <dir>] [--env NAME=VALUE]... [--pidfile <file>] [--grace <seconds>] [--force] [--from-remote [--download-dir <dir>]] [--key-file <file> | --passphrase <pass>]", args[0]);
                 std::process::exit(1);
             }
 
@@ -113,41 +608,151 @@ async fn main() {
                 decoded_backup_file = backup_file_arg.clone();
             }
 
-            let backup_file = Path::new(&decoded_backup_file);
-            let target_dir = Path::new(&args[3]);
-            let world_name = &args[4];
-            let server_exe = &args[5];
-            let seven_zip_path = Path::new(&args[6]);
+            let mut backup_file = PathBuf::from(&decoded_backup_file);
+            let wanted = encryption_enabled(config) || decoded_backup_file.ends_with(ENCRYPTED_SUFFIX) || is_encrypted(&backup_file).unwrap_or(false);
+            let key = encryption_key(key, config, wanted).unwrap_or_else(|e| fail(e));
+            // --from-remote 时 backup_file 是远程目录中的备份名，先下载到本地再按正常流程恢复
+            if let Some(config) = config.filter(|_| from_remote) {
+                let source = config.remote_source().unwrap_or_else(|| fail("--from-remote 需要在配置中设置远程存储 (upload.webdavUrl、upload.s3 或 upload.sftp)".to_string()));
+                let backend = source.open(&config.upload_options().retry).unwrap_or_else(|e| fail(format!("Error downloading backup: {}", e)));
+                let download_dir = download_dir.map(PathBuf::from).unwrap_or_else(|| config.backup_path.clone());
+                match download_backup(backend.as_ref(), &decoded_backup_file, &download_dir, key.as_ref()).await {
+                    Ok(summary) => backup_file = PathBuf::from(summary.archive),
+                    Err(e) => fail(format!("Error downloading backup: {}", e)),
+                }
+            }
+            let target_dir = PathBuf::from(&args[3]);
+            let world_name = args[4].clone();
+            let server = server_options.into_controller(target_dir.join(&args[5]), &target_dir).unwrap_or_else(|e| fail(e));
+            // 7za 只在原生解压不支持该格式时使用，传入空字符串或 "-" 表示不使用
+            let seven_zip_path = match args[6].as_str() {
+                "" | "-" => None,
+                path => Some(PathBuf::from(path)),
+            };
 
-            let mut url = if args.len() > 7 { Some(args[7].as_str()) } else { None };
-            let mut auth = if args.len() > 8 { Some(args[8].as_str()) } else { None };
+            let url = args.get(7).cloned();
+            let auth = args.get(8).cloned();
 
+            // 恢复过程中会发出阻塞的 HTTP 请求，不能直接在异步运行时中执行
+            let result = tokio::task::spawn_blocking(move || {
+                recover_backup(&backup_file, &target_dir, &world_name, &server, seven_zip_path.as_deref(), url.as_deref(), auth.as_deref(), force, key.as_ref())
+            })
+            .await
+            .unwrap_or_else(|e| Err(std::io::Error::other(e)));
 
-            if let Err(e) = recover_backup(&backup_file, &target_dir, world_name, server_exe, &seven_zip_path,url,auth) {
-                error!("Error during backup recovery: {}", e);
+            match result {
+                Ok(_) => emit_result::<()>(true, None, None),
+                Err(e) => fail(format!("Error during backup recovery: {}", e)),
+            }
+        }
+
+        "verify" => {
+            let mut args = args.clone();
+            let key = take_encryption_key(&mut args).unwrap_or_else(|e| fail(e));
+            if args.len() != 3 {
+                error!("Usage for verify: {} verify <backup_file> [--key-file <file> | --passphrase <pass>]", args[0]);
                 std::process::exit(1);
             }
+            let wanted = encryption_enabled(config) || is_encrypted(Path::new(&args[2])).unwrap_or(false);
+            let key = encryption_key(key, config, wanted).unwrap_or_else(|e| fail(e));
+
+            match verify_backup(Path::new(&args[2]), key.as_ref()) {
+                Ok(report) if report.valid => emit_result(true, None, Some(&report)),
+                Ok(report) => {
+                    emit_result(false, Some(report.summary()), Some(&report));
+                    std::process::exit(1);
+                }
+                Err(e) => fail(format!("校验备份时出错: {}", e)),
+            }
         }
 
         "upload" => {
-            if args.len() < 8 {
-                error!("Usage for upload: {} upload <backup_file> <remote_path> <webdav_url> <username> <password> <allow_insecure>", args[0]);
+            let mut args = args.clone();
+            let mut options = take_upload_options(&mut args, config).unwrap_or_else(|e| fail(e));
+            // --force 时不检查远程文件，总是重新上传
+            if take_flag(&mut args, "--force") {
+                options.skip_unchanged = false;
+            }
+            // 配置启用了加密、给出 --encrypt、--key-file 或 --passphrase 时上传前加密
+            let key = take_encryption_key(&mut args).unwrap_or_else(|e| fail(e));
+            let encrypt = take_flag(&mut args, "--encrypt") || key.is_some() || encryption_enabled(config);
+            if encrypt {
+                let key = encryption_key(key, config, true).unwrap_or_else(|e| fail(e));
+                options.encryption = Some(key.unwrap_or_else(|| fail("加密需要 --key-file、--passphrase、配置中的 encryption.keyFile 或 encryption.passphrase".to_string())));
+            }
+            let source = take_remote(&mut args, config, 3).unwrap_or_else(|e| fail(e));
+
+            let Some(source) = source.filter(|_| args.len() == 3) else {
+                error!("Usage for upload: {} upload <backup_file> [<remote_path> <webdav_url> <username> <password> <allow_insecure>] [--retries N] [--retry-delay MS] [--retry-max-delay MS] [--strategy single|nextcloud|ranged] [--chunk-size SIZE] [--concurrency N] [--force] [--encrypt] [--key-file <file> | --passphrase <pass>]", args[0]);
                 std::process::exit(1);
+            };
+
+            let backend = source.open(&options.retry).unwrap_or_else(|e| fail(format!("Error during file upload: {}", e)));
+            match upload_backup(Path::new(&args[2]), backend.as_ref(), &options).await {
+                Ok(summary) => emit_result(true, None, Some(&summary)),
+                Err(e) => {
+                    let message = format!("Error during file upload: {}", e);
+                    error!("{}", message);
+                    emit_result(false, Some(message), Some(&e.report()));
+                    std::process::exit(1);
+                }
             }
+        }
+        "download" => {
+            let mut args = args.clone();
+            let options = take_upload_options(&mut args, config).unwrap_or_else(|e| fail(e));
+            let key = take_encryption_key(&mut args).unwrap_or_else(|e| fail(e));
+            let source = take_remote(&mut args, config, 4).unwrap_or_else(|e| fail(e));
+
+            let Some(source) = source.filter(|_| args.len() == 4) else {
+                error!("Usage for download: {} download <name> <destination_dir> [<remote_path> <webdav_url> <username> <password> <allow_insecure>] [--retries N] [--retry-delay MS] [--retry-max-delay MS] [--key-file <file> | --passphrase <pass>]", args[0]);
+                std::process::exit(1);
+            };
+            let wanted = encryption_enabled(config) || args[2].ends_with(ENCRYPTED_SUFFIX);
+            let key = encryption_key(key, config, wanted).unwrap_or_else(|e| fail(e));
 
-            let backup_file = Path::new(&args[2]);
-            let remote_path = &args[3];
-            let webdav_url = &args[4];
-            let username = &args[5];
-            let password = &args[6];
-            let allow_insecure: bool = args[7].parse().unwrap_or(false);
+            let backend = source.open(&options.retry).unwrap_or_else(|e| fail(format!("Error downloading backup: {}", e)));
+            match download_backup(backend.as_ref(), &args[2], Path::new(&args[3]), key.as_ref()).await {
+                Ok(summary) => emit_result(true, None, Some(&summary)),
+                Err(e) => {
+                    let message = format!("Error downloading backup: {}", e);
+                    error!("{}", message);
+                    emit_result(false, Some(message), Some(&serde_json::json!({ "attempts": e.attempts() })));
+                    std::process::exit(1);
+                }
+            }
+        }
+        "remote-list" => {
+            let mut args = args.clone();
+            let options = take_upload_options(&mut args, config).unwrap_or_else(|e| fail(e));
+            let depth = match take_option(&mut args, "--depth").unwrap_or_else(|e| fail(e)) {
+                Some(name) => Depth::from_name(&name).unwrap_or_else(|| fail(format!("Invalid --depth value: {}", name))),
+                None => Depth::One,
+            };
+            let source = take_remote(&mut args, config, 2).unwrap_or_else(|e| fail(e));
 
-            if let Err(e) = upload_backup(backup_file, webdav_url, remote_path, username, password, allow_insecure).await {
-               error!("Error during file upload: {}", e);
+            let Some(source) = source.filter(|_| args.len() == 2) else {
+                error!("Usage for remote-list: {} remote-list [<remote_path> <webdav_url> <username> <password> <allow_insecure>] [--depth 1|infinity]", args[0]);
                 std::process::exit(1);
+            };
+
+            let backend = source.open(&options.retry).unwrap_or_else(|e| fail(format!("Error listing remote backups: {}", e)));
+            match list_remote(backend.as_ref(), depth).await {
+                Ok(listing) => emit_result(true, None, Some(&listing)),
+                Err(e) => fail(format!("Error listing remote backups: {}", e)),
             }
         }
+
         "stats" => {
+            let mut args = args.clone();
+            fill_from_config(&mut args, config, 2, |config| {
+                let mut values = vec![format!("worlds/{}", level_name()?), path_arg(&config.backup_path), path_arg(&config.permanent_backup_path)];
+                if let Some((url, auth)) = config.serein_health() {
+                    values.extend([url, auth]);
+                }
+                Ok(values)
+            });
+
             if args.len() < 5 || args.len() > 7 {
                 error!("Usage for stats: {} stats <worldPath> <BackupPath> <PermanentBackupPath> [url] [auth]", args[0]);
                 std::process::exit(1);
@@ -157,8 +762,8 @@ async fn main() {
             let backup_path = Path::new(&args[3]);
             let permanent_backup_path = Path::new(&args[4]);
 
-            let mut url = if args.len() > 5 { Some(args[5].as_str()) } else { None };
-            let mut auth = if args.len() > 6 { Some(args[6].as_str()) } else { None };
+            let url = if args.len() > 5 { Some(args[5].as_str()) } else { None };
+            let auth = if args.len() > 6 { Some(args[6].as_str()) } else { None };
 
             // 检查参数规则
             if url.is_some() && auth.is_none() {
@@ -201,9 +806,10 @@ async fn main() {
                 let json_output = serde_json::json!({
             "directories": stats,
             "api_status": api_status,
+            "latest_backup": latest_backup(&[backup_path, permanent_backup_path]),
         });
 
-                println!("{}", serde_json::to_string_pretty(&json_output).unwrap());
+                emit_result(true, None, Some(&json_output));
         }
 
 
@@ -0,0 +1,574 @@
+use std::io::{self, Read};
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{error, info};
+use Recovery_Backup_Core::utils::archive::{archive_path_for, compress_dir, ArchiveFormat, CompressOptions};
+use Recovery_Backup_Core::utils::backup::{run_backup, BackupRequest};
+use Recovery_Backup_Core::utils::cleanup::delete_old_backups;
+use Recovery_Backup_Core::utils::copy::copy_dir_recursive;
+use Recovery_Backup_Core::utils::copy_db::copy_db;
+use Recovery_Backup_Core::utils::crypto::{is_encrypted, EncryptionKey, EncryptionSource, ENCRYPTED_SUFFIX};
+use Recovery_Backup_Core::utils::download::download_backup;
+use Recovery_Backup_Core::utils::manifest::{latest_backup, Trigger};
+use Recovery_Backup_Core::utils::recover::recover_backup;
+use Recovery_Backup_Core::utils::report::ErrorMode;
+use Recovery_Backup_Core::utils::repository::{Repository, SnapshotInfo};
+use Recovery_Backup_Core::utils::remote_retention::apply_remote_retention;
+use Recovery_Backup_Core::utils::retention::{apply_retention, RetentionPolicy, RetentionReport};
+use Recovery_Backup_Core::utils::s3::S3Target;
+use Recovery_Backup_Core::utils::sftp::SftpTarget;
+use Recovery_Backup_Core::utils::stats::{get_directory_stats_sync, DirectoryStats};
+use Recovery_Backup_Core::utils::storage::{list_remote, Depth, RemoteSource};
+use Recovery_Backup_Core::utils::chunked::{UploadStrategy, DEFAULT_CHUNK_SIZE};
+use Recovery_Backup_Core::utils::upload::{upload_backup, RetryPolicy, UploadOptions, DEFAULT_CONCURRENCY};
+use Recovery_Backup_Core::utils::utils::send_request;
+use Recovery_Backup_Core::utils::verify::verify_backup;
+use crate::ServerOptions;
+
+// request 模式下的一次操作，operation 字段决定其余字段的含义，路径和密码不再经过命令行
+#[derive(Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    CopyDb {
+        source_world: PathBuf,
+        destination_world: PathBuf,
+        db_list_file: PathBuf,
+        #[serde(default)]
+        base_dir: Option<PathBuf>,
+        #[serde(default)]
+        on_error: ErrorMode,
+    },
+    Copy {
+        source: PathBuf,
+        destination: PathBuf,
+        #[serde(default)]
+        delete: bool,
+        #[serde(default)]
+        on_error: ErrorMode,
+    },
+    Compress {
+        source: PathBuf,
+        // 不含后缀
+        destination: PathBuf,
+        #[serde(default = "default_format")]
+        format: String,
+        #[serde(default = "default_level")]
+        level: u32,
+        #[serde(default)]
+        threads: Option<usize>,
+    },
+    // 字段与 backup 请求文件相同
+    Backup(Box<BackupRequest>),
+    Cleanup {
+        path: PathBuf,
+        max_age_days: u64,
+        extension: String,
+    },
+    Retention {
+        path: PathBuf,
+        extension: String,
+        #[serde(default)]
+        policy: RetentionPolicy,
+        #[serde(default)]
+        dry_run: bool,
+    },
+    Stats {
+        world_path: PathBuf,
+        backup_path: PathBuf,
+        permanent_backup_path: PathBuf,
+        #[serde(default)]
+        url: Option<String>,
+        #[serde(default)]
+        auth: Option<String>,
+    },
+    Verify {
+        backup_file: PathBuf,
+        // 加密的备份使用的密钥，不填时使用 BACKUPJS_ENCRYPTION__ 环境变量
+        #[serde(default)]
+        encryption: Option<EncryptionSource>,
+    },
+    Recover {
+        backup_file: PathBuf,
+        target_dir: PathBuf,
+        world_name: String,
+        // 相对于 target_dir
+        server_exe: String,
+        #[serde(default)]
+        seven_zip: Option<PathBuf>,
+        #[serde(default)]
+        url: Option<String>,
+        #[serde(default)]
+        auth: Option<String>,
+        #[serde(default)]
+        force: bool,
+        #[serde(default)]
+        server: ServerOptions,
+        // 设置时 backup_file 是远程目录中的备份名，先下载到 download_dir
+        #[serde(default)]
+        from_remote: Option<Box<RemoteSource>>,
+        #[serde(default)]
+        download_dir: Option<PathBuf>,
+        #[serde(default)]
+        encryption: Option<EncryptionSource>,
+    },
+    Download {
+        name: String,
+        destination_dir: PathBuf,
+        #[serde(default)]
+        remote_path: String,
+        #[serde(default)]
+        webdav_url: String,
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password: String,
+        #[serde(default)]
+        allow_insecure: bool,
+        // 设置时使用 S3 兼容存储或 SFTP，忽略 webdav_url、username 和 password
+        #[serde(default)]
+        s3: Option<S3Target>,
+        #[serde(default)]
+        sftp: Option<SftpTarget>,
+        #[serde(default)]
+        retry: RetryPolicy,
+        #[serde(default)]
+        encryption: Option<EncryptionSource>,
+    },
+    Upload {
+        backup_file: PathBuf,
+        #[serde(default)]
+        remote_path: String,
+        #[serde(default)]
+        webdav_url: String,
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password: String,
+        #[serde(default)]
+        allow_insecure: bool,
+        // 设置时使用 S3 兼容存储或 SFTP，忽略 webdav_url、username 和 password
+        #[serde(default)]
+        s3: Option<S3Target>,
+        #[serde(default)]
+        sftp: Option<SftpTarget>,
+        #[serde(default)]
+        retry: RetryPolicy,
+        #[serde(default)]
+        strategy: UploadStrategy,
+        #[serde(default = "default_chunk_size")]
+        chunk_size: u64,
+        #[serde(default = "default_concurrency")]
+        concurrency: usize,
+        // 远程已有相同的文件时跳过
+        #[serde(default = "default_skip_unchanged")]
+        skip_unchanged: bool,
+        // 设置时上传前加密
+        #[serde(default)]
+        encryption: Option<EncryptionSource>,
+    },
+    RemoteList {
+        #[serde(default)]
+        remote_path: String,
+        #[serde(default)]
+        webdav_url: String,
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password: String,
+        #[serde(default)]
+        allow_insecure: bool,
+        // 设置时使用 S3 兼容存储或 SFTP，忽略 webdav_url、username 和 password
+        #[serde(default)]
+        s3: Option<S3Target>,
+        #[serde(default)]
+        sftp: Option<SftpTarget>,
+        #[serde(default)]
+        depth: Depth,
+        #[serde(default)]
+        retry: RetryPolicy,
+    },
+    RemoteRetention {
+        #[serde(default)]
+        remote_path: String,
+        #[serde(default)]
+        webdav_url: String,
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password: String,
+        #[serde(default)]
+        allow_insecure: bool,
+        // 设置时使用 S3 兼容存储或 SFTP，忽略 webdav_url、username 和 password
+        #[serde(default)]
+        s3: Option<S3Target>,
+        #[serde(default)]
+        sftp: Option<SftpTarget>,
+        extension: String,
+        #[serde(default)]
+        policy: RetentionPolicy,
+        #[serde(default)]
+        dry_run: bool,
+        #[serde(default)]
+        retry: RetryPolicy,
+        // 读取加密备份的清单使用的密钥
+        #[serde(default)]
+        encryption: Option<EncryptionSource>,
+    },
+    RepoBackup {
+        repo: PathBuf,
+        source: PathBuf,
+        #[serde(default)]
+        world_name: Option<String>,
+        #[serde(default)]
+        trigger: Trigger,
+    },
+    RepoRestore {
+        repo: PathBuf,
+        #[serde(default = "default_snapshot")]
+        snapshot: String,
+        target: PathBuf,
+    },
+    RepoList {
+        repo: PathBuf,
+    },
+    RepoPrune {
+        repo: PathBuf,
+        #[serde(default)]
+        policy: RetentionPolicy,
+        #[serde(default)]
+        dry_run: bool,
+    },
+}
+
+fn default_format() -> String {
+    "zip".to_string()
+}
+
+fn default_level() -> u32 {
+    5
+}
+
+fn default_chunk_size() -> u64 {
+    DEFAULT_CHUNK_SIZE
+}
+
+fn default_concurrency() -> usize {
+    DEFAULT_CONCURRENCY
+}
+
+// 解密使用的密钥，请求中没有给出时只在备份已加密 (encrypted) 时使用 BACKUPJS_ENCRYPTION__ 环境变量
+fn decryption_key(encryption: Option<EncryptionSource>, encrypted: bool) -> io::Result<Option<EncryptionKey>> {
+    match encryption {
+        Some(source) => source.key(),
+        None if encrypted => EncryptionSource::default().key(),
+        None => Ok(None),
+    }
+}
+
+fn default_skip_unchanged() -> bool {
+    true
+}
+
+fn default_snapshot() -> String {
+    "latest".to_string()
+}
+
+// request 模式的应答，整个进程只输出这一个 JSON 对象
+pub struct Reply {
+    pub success: bool,
+    pub error: Option<String>,
+    pub data: Option<Value>,
+}
+
+impl Reply {
+    fn ok<T: Serialize>(data: &T) -> Self {
+        Reply { success: true, error: None, data: serde_json::to_value(data).ok() }
+    }
+
+    fn failed<T: Serialize>(error: String, data: Option<&T>) -> Self {
+        error!("{}", error);
+        Reply { success: false, error: Some(error), data: data.and_then(|data| serde_json::to_value(data).ok()) }
+    }
+
+    fn from_result<T: Serialize, E: std::fmt::Display>(result: Result<T, E>, context: &str) -> Self {
+        match result {
+            Ok(data) => Self::ok(&data),
+            Err(e) => Self::failed::<()>(format!("{}: {}", context, e), None),
+        }
+    }
+
+    // 有备份删除失败时应答失败，报告仍放在 data 中
+    fn from_retention<E: std::fmt::Display>(result: Result<RetentionReport, E>, context: &str) -> Self {
+        match result {
+            Ok(report) if report.failed > 0 => Self::failed(format!("{}: {}", context, report.summary()), Some(&report)),
+            result => Self::from_result(result, context),
+        }
+    }
+}
+
+// 从文件读取请求，path 为 None 或 "-" 时读取 stdin
+pub fn read_request(path: Option<&str>) -> io::Result<Request> {
+    let text = match path {
+        None | Some("-") => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        Some(path) => std::fs::read_to_string(path)?,
+    };
+    serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("请求格式错误: {}", e)))
+}
+
+pub async fn execute(request: Request) -> Reply {
+    match request {
+        Request::CopyDb { source_world, destination_world, db_list_file, base_dir, on_error } => {
+            match copy_db(&source_world, &destination_world, &db_list_file, base_dir.as_deref(), on_error) {
+                Ok(report) if report.success => {
+                    info!("数据文件复制成功。");
+                    Reply::ok(&report)
+                }
+                Ok(report) => Reply::failed(format!("复制数据文件时出错: {}", report.summary()), Some(&report)),
+                Err(e) => Reply::failed::<()>(format!("复制数据文件时出错: {}", e), None),
+            }
+        }
+
+        Request::Copy { source, destination, delete, on_error } => match copy_dir_recursive(&source, &destination, delete, on_error) {
+            Ok(report) if report.success => Reply::ok(&report),
+            Ok(report) => Reply::failed(format!("Error during copy: {}", report.summary()), Some(&report)),
+            Err(e) => Reply::failed::<()>(format!("Error during copy: {}", e), None),
+        },
+
+        Request::Compress { source, destination, format: format_name, level, threads } => {
+            let Some(format) = ArchiveFormat::from_name(&format_name) else {
+                return Reply::failed::<()>(format!("不支持的压缩格式: {}", format_name), None);
+            };
+            let destination = archive_path_for(&destination, format);
+            let options = CompressOptions { format, level, threads: threads.unwrap_or_else(rayon::current_num_threads) };
+            Reply::from_result(compress_dir(&source, &destination, &options), "压缩时出错")
+        }
+
+        Request::Backup(request) => {
+            let result = run_backup(&request).await;
+            let error = result.stages.iter().find_map(|stage| stage.error.clone());
+            Reply { success: result.success, error, data: serde_json::to_value(&result).ok() }
+        }
+
+        Request::Cleanup { path, max_age_days, extension } => Reply::from_result(
+            delete_old_backups(&path, max_age_days, &extension).map(|deleted| serde_json::json!({ "deleted": deleted })),
+            "Error during old backup cleanup",
+        ),
+
+        Request::Retention { path, extension, policy, dry_run } => {
+            Reply::from_retention(apply_retention(&path, &extension, &policy, dry_run), "Error during backup retention")
+        }
+
+        Request::Stats { world_path, backup_path, permanent_backup_path, url, auth } => {
+            if url.is_some() && auth.is_none() {
+                return Reply::failed::<()>("If url is provided, auth must also be provided.".to_string(), None);
+            }
+            // 检查 API 状态使用阻塞请求，放到单独的线程中
+            let result = tokio::task::spawn_blocking(move || -> io::Result<Value> {
+                let mut stats = Vec::new();
+                for path in [&world_path, &backup_path, &permanent_backup_path] {
+                    let (size, file_count) = get_directory_stats_sync(path)?;
+                    stats.push(DirectoryStats { path: path.to_string_lossy().into_owned(), size, file_count });
+                }
+                let api_status = match url {
+                    Some(url) => send_request(&url, auth.as_deref()).unwrap_or_else(|e| {
+                        error!("Failed to check API status: {}", e);
+                        "unknown".to_string()
+                    }),
+                    None => "not checked".to_string(),
+                };
+                Ok(serde_json::json!({
+                    "directories": stats,
+                    "api_status": api_status,
+                    "latest_backup": latest_backup(&[backup_path.as_path(), permanent_backup_path.as_path()]),
+                }))
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+            Reply::from_result(result, "统计目录时出错")
+        }
+
+        Request::Verify { backup_file, encryption } => match decryption_key(encryption, is_encrypted(&backup_file).unwrap_or(false)).and_then(|key| verify_backup(&backup_file, key.as_ref())) {
+            Ok(report) if report.valid => Reply::ok(&report),
+            Ok(report) => Reply { success: false, error: Some(report.summary()), data: serde_json::to_value(&report).ok() },
+            Err(e) => Reply::failed::<()>(format!("校验备份时出错: {}", e), None),
+        },
+
+        Request::Recover { mut backup_file, target_dir, world_name, server_exe, seven_zip, url, auth, force, server, from_remote, download_dir, encryption } => {
+            let encrypted = backup_file.to_string_lossy().ends_with(ENCRYPTED_SUFFIX) || is_encrypted(&backup_file).unwrap_or(false);
+            let key = match decryption_key(encryption, encrypted) {
+                Ok(key) => key,
+                Err(e) => return Reply::failed::<()>(format!("Error during backup recovery: {}", e), None),
+            };
+            if let Some(source) = from_remote {
+                let backend = match source.open(&RetryPolicy::default()) {
+                    Ok(backend) => backend,
+                    Err(e) => return Reply::failed::<()>(format!("Error downloading backup: {}", e), None),
+                };
+                let download_dir = download_dir.unwrap_or_else(|| PathBuf::from("."));
+                match download_backup(backend.as_ref(), &backup_file.to_string_lossy(), &download_dir, key.as_ref()).await {
+                    Ok(summary) => backup_file = PathBuf::from(summary.archive),
+                    Err(e) => return Reply::failed(format!("Error downloading backup: {}", e), Some(&serde_json::json!({ "attempts": e.attempts() }))),
+                }
+            }
+            let server = match server.into_controller(target_dir.join(&server_exe), &target_dir) {
+                Ok(server) => server,
+                Err(e) => return Reply::failed::<()>(e, None),
+            };
+            // 恢复过程中会发出阻塞的 HTTP 请求，不能直接在异步运行时中执行
+            let result = tokio::task::spawn_blocking(move || {
+                recover_backup(&backup_file, &target_dir, &world_name, &server, seven_zip.as_deref(), url.as_deref(), auth.as_deref(), force, key.as_ref())
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+            Reply::from_result(result.map(|_| Value::Null), "Error during backup recovery")
+        }
+
+        Request::Download { name, destination_dir, remote_path, webdav_url, username, password, allow_insecure, s3, sftp, retry, encryption } => {
+            let key = match decryption_key(encryption, name.ends_with(ENCRYPTED_SUFFIX)) {
+                Ok(key) => key,
+                Err(e) => return Reply::failed::<()>(format!("Error downloading backup: {}", e), None),
+            };
+            let source = RemoteSource { remote_path, webdav_url, username, password, allow_insecure, s3, sftp };
+            let backend = match source.open(&retry) {
+                Ok(backend) => backend,
+                Err(e) => return Reply::failed::<()>(format!("Error downloading backup: {}", e), None),
+            };
+            match download_backup(backend.as_ref(), &name, &destination_dir, key.as_ref()).await {
+                Ok(summary) => Reply::ok(&summary),
+                Err(e) => Reply::failed(format!("Error downloading backup: {}", e), Some(&serde_json::json!({ "attempts": e.attempts() }))),
+            }
+        }
+
+        Request::Upload { backup_file, remote_path, webdav_url, username, password, allow_insecure, s3, sftp, retry, strategy, chunk_size, concurrency, skip_unchanged, encryption } => {
+            let encryption = match encryption.map(|source| source.key()) {
+                None => None,
+                Some(Ok(Some(key))) => Some(key),
+                Some(Ok(None)) => return Reply::failed::<()>("加密需要 key_file 或 passphrase".to_string(), None),
+                Some(Err(e)) => return Reply::failed::<()>(format!("Error during file upload: {}", e), None),
+            };
+            let source = RemoteSource { remote_path, webdav_url, username, password, allow_insecure, s3, sftp };
+            let backend = match source.open(&retry) {
+                Ok(backend) => backend,
+                Err(e) => return Reply::failed::<()>(format!("Error during file upload: {}", e), None),
+            };
+            let options = UploadOptions { retry, strategy, chunk_size, concurrency, skip_unchanged, encryption };
+            match upload_backup(&backup_file, backend.as_ref(), &options).await {
+                Ok(summary) => Reply::ok(&summary),
+                Err(e) => Reply::failed(format!("Error during file upload: {}", e), Some(&e.report())),
+            }
+        }
+
+        Request::RemoteList { remote_path, webdav_url, username, password, allow_insecure, s3, sftp, depth, retry } => {
+            let source = RemoteSource { remote_path, webdav_url, username, password, allow_insecure, s3, sftp };
+            let backend = match source.open(&retry) {
+                Ok(backend) => backend,
+                Err(e) => return Reply::failed::<()>(format!("Error listing remote backups: {}", e), None),
+            };
+            Reply::from_result(list_remote(backend.as_ref(), depth).await, "Error listing remote backups")
+        }
+
+        Request::RemoteRetention { remote_path, webdav_url, username, password, allow_insecure, s3, sftp, extension, policy, dry_run, retry, encryption } => {
+            let key = match decryption_key(encryption, extension.ends_with(ENCRYPTED_SUFFIX)) {
+                Ok(key) => key,
+                Err(e) => return Reply::failed::<()>(format!("Error during remote retention: {}", e), None),
+            };
+            let source = RemoteSource { remote_path, webdav_url, username, password, allow_insecure, s3, sftp };
+            let backend = match source.open(&retry) {
+                Ok(backend) => backend,
+                Err(e) => return Reply::failed::<()>(format!("Error during remote retention: {}", e), None),
+            };
+            Reply::from_retention(apply_remote_retention(backend.as_ref(), &extension, &policy, dry_run, key.as_ref()).await, "Error during remote retention")
+        }
+
+        Request::RepoBackup { repo, source, world_name, trigger } => {
+            let world_name = world_name.unwrap_or_else(|| source.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default());
+            let result = Repository::open_or_init(&repo).and_then(|repo| repo.backup(&source, &world_name, trigger));
+            Reply::from_result(result.as_ref().map(SnapshotInfo::from), "Error during repository backup")
+        }
+
+        Request::RepoRestore { repo, snapshot, target } => {
+            let result = Repository::open(&repo).and_then(|repo| repo.restore(&snapshot, &target));
+            Reply::from_result(result.as_ref().map(SnapshotInfo::from), "Error during repository restore")
+        }
+
+        Request::RepoList { repo } => {
+            let result = Repository::open(&repo).and_then(|repo| repo.snapshots());
+            Reply::from_result(
+                result.map(|snapshots| snapshots.iter().map(SnapshotInfo::from).collect::<Vec<_>>()),
+                "Error listing repository",
+            )
+        }
+
+        Request::RepoPrune { repo, policy, dry_run } => {
+            Reply::from_result(Repository::open(&repo).and_then(|repo| repo.prune(&policy, dry_run)), "Error during repository prune")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<Request, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    // Request 没有实现 Debug，不能直接 unwrap_err
+    fn parse_error(json: &str) -> String {
+        match parse(json) {
+            Ok(_) => panic!("请求应当被拒绝: {}", json),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn parses_operations_with_defaults() {
+        let request = parse(r#"{"operation":"copy_db","source_world":"w","destination_world":"out","db_list_file":"list.txt"}"#).unwrap();
+        assert!(matches!(request, Request::CopyDb { base_dir: None, on_error: ErrorMode::FailFast, .. }));
+
+        let request = parse(r#"{"operation":"compress","source":"w","destination":"w_1","threads":2}"#).unwrap();
+        let Request::Compress { format, level, threads, .. } = request else { panic!("不是 compress 请求") };
+        assert_eq!((format.as_str(), level, threads), ("zip", 5, Some(2)));
+
+        // backup 的字段在 BackupRequest 中，operation 不算未知字段
+        let request = parse(r#"{"operation":"backup","source_world":"w","staging_dir":"tmp","db_list_file":"l","destination":"b","cleanup":{"path":"b","max_age_days":3}}"#).unwrap();
+        assert!(matches!(request, Request::Backup(backup) if backup.cleanup.as_ref().is_some_and(|cleanup| cleanup.max_age_days == Some(3))));
+
+        let request = parse(r#"{"operation":"repo_restore","repo":"r","target":"out"}"#).unwrap();
+        assert!(matches!(request, Request::RepoRestore { snapshot, .. } if snapshot == "latest"));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        // 拼错的字段不能被忽略，否则 force、dry_run 之类的选项会悄悄失效
+        let e = parse_error(r#"{"operation":"recover","backup_file":"b.zip","target_dir":".","world_name":"w","server_exe":"bedrock_server","forced":true}"#);
+        assert!(e.contains("unknown field `forced`"), "{}", e);
+        let e = parse_error(r#"{"operation":"repo_prune","repo":"r","dryrun":true}"#);
+        assert!(e.contains("unknown field `dryrun`"), "{}", e);
+        let e = parse_error(r#"{"operation":"backup","source_world":"w","staging_dir":"tmp","db_list_file":"l","destination":"b","encrypt":true}"#);
+        assert!(e.contains("unknown field `encrypt`"), "{}", e);
+        let e = parse_error(r#"{"operation":"backup","source_world":"w","staging_dir":"tmp","db_list_file":"l","destination":"b","cleanup":{"path":"b","max_age":3}}"#);
+        assert!(e.contains("unknown field `max_age`"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_wrong_operations() {
+        for (json, expected) in [
+            (r#"{"source":"w","destination":"out"}"#, "missing field `operation`"),
+            (r#"{"operation":"CopyDb","source":"w"}"#, "unknown variant `CopyDb`"),
+            (r#"{"operation":"unzip","source":"w"}"#, "unknown variant `unzip`"),
+            (r#"{"operation":1}"#, "invalid type"),
+        ] {
+            let e = parse_error(json);
+            assert!(e.contains(expected), "{}: {}", json, e);
+        }
+        // 缺少必需字段
+        let e = parse_error(r#"{"operation":"cleanup","path":"backups","extension":"zip"}"#);
+        assert!(e.contains("missing field `max_age_days`"), "{}", e);
+    }
+}
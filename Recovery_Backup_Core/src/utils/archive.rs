@@ -0,0 +1,678 @@
+use std::{fs, io};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use rayon::prelude::*;
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use crate::utils::events::Stage;
+
+// 支持的压缩格式，名称与 config.json 中的 format 保持一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    SevenZ,
+    Tar,
+    TarGz,
+    TarBz2,
+    TarXz,
+}
+
+impl ArchiveFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().trim_start_matches('.').to_ascii_lowercase().as_str() {
+            "zip" => Some(Self::Zip),
+            "7z" => Some(Self::SevenZ),
+            "tar" => Some(Self::Tar),
+            "gzip" | "gz" | "tgz" | "tar.gz" => Some(Self::TarGz),
+            "bzip2" | "bz2" | "tbz2" | "tar.bz2" => Some(Self::TarBz2),
+            "xz" | "txz" | "tar.xz" => Some(Self::TarXz),
+            _ => None,
+        }
+    }
+
+    // 根据文件名后缀判断格式，旧版本插件用 7za 生成的 .gzip、.bzip2 不是 tar，不在其中
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        [".tar.gz", ".tar.bz2", ".tar.xz", ".tgz", ".tbz2", ".txz", ".zip", ".7z", ".tar", ".gz", ".bz2", ".xz"]
+            .iter()
+            .find(|suffix| name.ends_with(*suffix))
+            .and_then(|suffix| Self::from_name(suffix))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::SevenZ => "7z",
+            Self::Tar => "tar",
+            Self::TarGz => "gzip",
+            Self::TarBz2 => "bzip2",
+            Self::TarXz => "xz",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::SevenZ => "7z",
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+            Self::TarBz2 => "tar.bz2",
+            Self::TarXz => "tar.xz",
+        }
+    }
+}
+
+// 备份文件名需要匹配的后缀，格式名 (gzip) 和扩展名 (tar.gz) 都会换成完整的扩展名，其他值按原样使用
+pub fn backup_suffix(extension: &str) -> String {
+    let extension = extension.trim().trim_start_matches('.');
+    match ArchiveFormat::from_name(extension) {
+        Some(format) => format!(".{}", format.extension()),
+        None => format!(".{}", extension),
+    }
+}
+
+pub struct CompressOptions {
+    pub format: ArchiveFormat,
+    // 压缩等级 0-9，与 7za 的 -mx 含义相同
+    pub level: u32,
+    // 压缩线程数，zip 按文件分组并行，xz 使用多线程流
+    pub threads: usize,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveStats {
+    pub path: String,
+    pub format: String,
+    pub size: u64,
+    pub source_size: u64,
+    pub file_count: u64,
+    pub duration_ms: u128,
+}
+
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    // 压缩包内的名称，统一使用 '/' 分隔
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+// 为目标路径补上格式对应的后缀
+pub fn archive_path_for(destination: &Path, format: ArchiveFormat) -> PathBuf {
+    if ArchiveFormat::from_path(destination) == Some(format) {
+        return destination.to_path_buf();
+    }
+    let mut name = destination.as_os_str().to_os_string();
+    name.push(".");
+    name.push(format.extension());
+    PathBuf::from(name)
+}
+
+// 递归收集目录下的所有条目，按名称排序保证输出稳定
+pub fn collect_entries(root: &Path) -> io::Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+    collect_entries_into(root, root, &mut entries)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+fn collect_entries_into(root: &Path, dir: &Path, entries: &mut Vec<ArchiveEntry>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = fs::metadata(&path)?;
+        let name = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if metadata.is_dir() {
+            entries.push(ArchiveEntry { path: path.clone(), name, is_dir: true, size: 0 });
+            collect_entries_into(root, &path, entries)?;
+        } else {
+            entries.push(ArchiveEntry { path, name, is_dir: false, size: metadata.len() });
+        }
+    }
+    Ok(())
+}
+
+// 将 source 目录中的内容（不含目录本身）写入压缩包
+pub fn compress_dir(source: &Path, destination: &Path, options: &CompressOptions) -> io::Result<ArchiveStats> {
+    if !source.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("源目录不存在: {}", source.display())));
+    }
+
+    let start = Instant::now();
+    let entries = collect_entries(source)?;
+    let level = options.level.min(9);
+    let threads = options.threads.max(1);
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let files: Vec<_> = entries.iter().filter(|e| !e.is_dir).collect();
+    let source_size = files.iter().map(|e| e.size).sum();
+    let stage = Stage::start("compress", source_size, files.len() as u64);
+
+    // 先写入临时文件，完成后再改名，避免留下不完整的压缩包
+    let partial = partial_path(destination);
+    let result = write_archive(&entries, &partial, options.format, level, threads, &stage)
+        .and_then(|_| fs::rename(&partial, destination))
+        .and_then(|_| fs::metadata(destination));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    stage.finish(&result);
+
+    Ok(ArchiveStats {
+        path: destination.to_string_lossy().into_owned(),
+        format: options.format.name().to_string(),
+        size: result?.len(),
+        source_size,
+        file_count: files.len() as u64,
+        duration_ms: start.elapsed().as_millis(),
+    })
+}
+
+fn write_archive(entries: &[ArchiveEntry], path: &Path, format: ArchiveFormat, level: u32, threads: usize, stage: &Stage) -> io::Result<()> {
+    match format {
+        ArchiveFormat::Zip => write_zip(entries, path, level, threads, stage),
+        ArchiveFormat::SevenZ => write_7z(entries, path, level, stage),
+        ArchiveFormat::Tar => write_tar(entries, BufWriter::new(File::create(path)?), stage).and_then(|mut writer| writer.flush()),
+        ArchiveFormat::TarGz => {
+            let encoder = flate2::write::GzEncoder::new(BufWriter::new(File::create(path)?), flate2::Compression::new(level));
+            write_tar(entries, encoder, stage).and_then(|encoder| encoder.finish()?.flush())
+        }
+        ArchiveFormat::TarBz2 => {
+            let encoder = bzip2::write::BzEncoder::new(BufWriter::new(File::create(path)?), bzip2::Compression::new(level.max(1)));
+            write_tar(entries, encoder, stage).and_then(|encoder| encoder.finish()?.flush())
+        }
+        ArchiveFormat::TarXz => {
+            let stream = xz2::stream::MtStreamBuilder::new()
+                .preset(level)
+                .threads(threads as u32)
+                .check(xz2::stream::Check::Crc64)
+                .encoder()
+                .map_err(io::Error::other)?;
+            let encoder = xz2::write::XzEncoder::new_stream(BufWriter::new(File::create(path)?), stream);
+            write_tar(entries, encoder, stage).and_then(|encoder| encoder.finish()?.flush())
+        }
+    }
+}
+
+fn partial_path(destination: &Path) -> PathBuf {
+    let mut name = destination.as_os_str().to_os_string();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+fn zip_options(level: u32, size: u64, modified: Option<SystemTime>) -> SimpleFileOptions {
+    let mut options = SimpleFileOptions::default().large_file(size >= u32::MAX as u64);
+    // zip 使用本地时间记录修改时间
+    if let Some(modified) = modified {
+        let time = DateTime::<Local>::from(modified);
+        if let Ok(time) = zip::DateTime::from_date_and_time(
+            time.year().clamp(1980, 2107) as u16,
+            time.month() as u8,
+            time.day() as u8,
+            time.hour() as u8,
+            time.minute() as u8,
+            time.second() as u8,
+        ) {
+            options = options.last_modified_time(time);
+        }
+    }
+    if level == 0 {
+        options.compression_method(CompressionMethod::Stored)
+    } else {
+        options
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(level as i64))
+    }
+}
+
+fn write_zip_part(entries: &[&ArchiveEntry], path: &Path, level: u32, stage: &Stage) -> io::Result<()> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    for entry in entries {
+        if entry.is_dir {
+            zip.add_directory(entry.name.as_str(), zip_options(0, 0, None))?;
+        } else {
+            let mut file = File::open(&entry.path)?;
+            let modified = file.metadata()?.modified().ok();
+            zip.start_file(entry.name.as_str(), zip_options(level, entry.size, modified))?;
+            io::copy(&mut file, &mut zip)?;
+            stage.file_done(&entry.name, entry.size);
+        }
+    }
+    zip.finish()?.flush()
+}
+
+// 把文件按大小分成若干组并行压缩成分卷 zip，最后原样合并，不需要重新压缩
+fn write_zip(entries: &[ArchiveEntry], destination: &Path, level: u32, threads: usize, stage: &Stage) -> io::Result<()> {
+    let mut groups: Vec<(u64, Vec<&ArchiveEntry>)> = (0..threads).map(|_| (0, Vec::new())).collect();
+    let mut by_size: Vec<&ArchiveEntry> = entries.iter().collect();
+    by_size.sort_by_key(|e| std::cmp::Reverse(e.size));
+    for entry in by_size {
+        let group = groups.iter_mut().min_by_key(|(size, _)| *size).unwrap();
+        group.0 += entry.size;
+        group.1.push(entry);
+    }
+    let groups: Vec<Vec<&ArchiveEntry>> = groups
+        .into_iter()
+        .map(|(_, mut group)| {
+            group.sort_by(|a, b| a.name.cmp(&b.name));
+            group
+        })
+        .filter(|group| !group.is_empty())
+        .collect();
+
+    if groups.len() <= 1 {
+        return write_zip_part(&groups.concat(), destination, level, stage);
+    }
+
+    let part_paths: Vec<PathBuf> = (0..groups.len())
+        .map(|i| {
+            let mut name = destination.as_os_str().to_os_string();
+            name.push(format!(".{}", i));
+            PathBuf::from(name)
+        })
+        .collect();
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(io::Error::other)?;
+
+    let result = pool
+        .install(|| {
+            groups
+                .par_iter()
+                .zip(part_paths.par_iter())
+                .try_for_each(|(group, path)| write_zip_part(group, path, level, stage))
+        })
+        .and_then(|_| {
+            let mut zip = ZipWriter::new(BufWriter::new(File::create(destination)?));
+            for path in &part_paths {
+                zip.merge_archive(ZipArchive::new(File::open(path)?)?)?;
+            }
+            zip.finish()?.flush()
+        });
+
+    for path in &part_paths {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn write_7z(entries: &[ArchiveEntry], destination: &Path, level: u32, stage: &Stage) -> io::Result<()> {
+    let mut writer = sevenz_rust::SevenZWriter::create(destination).map_err(io::Error::other)?;
+    writer.set_content_methods(vec![sevenz_rust::lzma::LZMA2Options::with_preset(level).into()]);
+    for entry in entries {
+        let archive_entry = sevenz_rust::SevenZArchiveEntry::from_path(&entry.path, entry.name.clone());
+        let reader = if entry.is_dir { None } else { Some(File::open(&entry.path)?) };
+        writer
+            .push_archive_entry(archive_entry, reader)
+            .map_err(|e| io::Error::other(format!("{}: {}", entry.name, e)))?;
+        if !entry.is_dir {
+            stage.file_done(&entry.name, entry.size);
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_tar<W: Write>(entries: &[ArchiveEntry], writer: W, stage: &Stage) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    for entry in entries {
+        if entry.is_dir {
+            builder.append_dir(&entry.name, &entry.path)?;
+        } else {
+            builder.append_path_with_name(&entry.path, &entry.name)?;
+            stage.file_done(&entry.name, entry.size);
+        }
+    }
+    builder.into_inner()
+}
+
+#[derive(Serialize)]
+pub struct ExtractStats {
+    pub path: String,
+    pub format: String,
+    pub size: u64,
+    pub file_count: u64,
+    pub duration_ms: u128,
+}
+
+// 当前可以原生解压的格式，其余格式交给 7za 处理
+pub fn can_extract_natively(format: ArchiveFormat) -> bool {
+    format != ArchiveFormat::SevenZ
+}
+
+// 把压缩包内的条目名称安全地拼接到目标目录，拒绝绝对路径和 ".." 等越界路径
+pub fn safe_join(target: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut path = target.to_path_buf();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("条目路径越界: {}", name)));
+            }
+            part if part.contains(':') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("条目路径非法: {}", name)));
+            }
+            part => path.push(part),
+        }
+    }
+    if path == target {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("条目路径为空: {}", name)));
+    }
+    Ok(path)
+}
+
+// 给错误附上出错的条目名称，保留原本的错误类型
+fn entry_error(name: &str, e: impl std::fmt::Display, kind: io::ErrorKind) -> io::Error {
+    io::Error::new(kind, format!("解压条目 {} 失败: {}", name, e))
+}
+
+// 将压缩包逐条流式解压到 target 目录
+pub fn extract_archive(archive: &Path, target: &Path) -> io::Result<ExtractStats> {
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::Unsupported, format!("无法识别的压缩格式: {}", archive.display()))
+    })?;
+    if !can_extract_natively(format) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("不支持原生解压的格式: {}", format.name())));
+    }
+
+    let start = Instant::now();
+    fs::create_dir_all(target)?;
+    let file = io::BufReader::new(File::open(archive)?);
+    // tar 是流式读取，总量未知，只报告已完成的数量
+    let stage = Stage::start("extract", 0, 0);
+    let result = match format {
+        ArchiveFormat::Zip => extract_zip(file, target, &stage),
+        ArchiveFormat::Tar => extract_tar(file, target, &stage),
+        ArchiveFormat::TarGz => extract_tar(flate2::read::MultiGzDecoder::new(file), target, &stage),
+        ArchiveFormat::TarBz2 => extract_tar(bzip2::read::MultiBzDecoder::new(file), target, &stage),
+        ArchiveFormat::TarXz => extract_tar(xz2::read::XzDecoder::new_multi_decoder(file), target, &stage),
+        ArchiveFormat::SevenZ => unreachable!(),
+    };
+    stage.finish(&result);
+    let (size, file_count) = result?;
+
+    Ok(ExtractStats {
+        path: target.to_string_lossy().into_owned(),
+        format: format.name().to_string(),
+        size,
+        file_count,
+        duration_ms: start.elapsed().as_millis(),
+    })
+}
+
+fn extract_zip<R: io::Read + io::Seek>(reader: R, target: &Path, stage: &Stage) -> io::Result<(u64, u64)> {
+    let mut zip = ZipArchive::new(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let (mut size, mut file_count) = (0, 0);
+    let files_total = zip.file_names().filter(|name| !name.ends_with('/')).count() as u64;
+    stage.set_totals(zip.decompressed_size().unwrap_or(0) as u64, files_total);
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| entry_error(&format!("#{}", i), e, io::ErrorKind::InvalidData))?;
+        let name = entry.name().to_string();
+        let path = safe_join(target, &name)?;
+
+        if entry.is_dir() {
+            fs::create_dir_all(&path).map_err(|e| entry_error(&name, &e, e.kind()))?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| entry_error(&name, &e, e.kind()))?;
+        }
+        let mut output = File::create(&path).map_err(|e| entry_error(&name, &e, e.kind()))?;
+        let written = io::copy(&mut entry, &mut output).map_err(|e| entry_error(&name, &e, e.kind()))?;
+        stage.file_done(&name, written);
+        size += written;
+        file_count += 1;
+    }
+
+    Ok((size, file_count))
+}
+
+fn extract_tar<R: io::Read>(reader: R, target: &Path, stage: &Stage) -> io::Result<(u64, u64)> {
+    let mut archive = tar::Archive::new(reader);
+    let (mut size, mut file_count) = (0, 0);
+
+    for entry in archive.entries()? {
+        let mut entry = entry.map_err(|e| entry_error("?", &e, e.kind()))?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let path = safe_join(target, &name)?;
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => {
+                fs::create_dir_all(&path).map_err(|e| entry_error(&name, &e, e.kind()))?;
+            }
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| entry_error(&name, &e, e.kind()))?;
+                }
+                entry.unpack(&path).map_err(|e| entry_error(&name, &e, e.kind()))?;
+                stage.file_done(&name, entry.size());
+                size += entry.size();
+                file_count += 1;
+            }
+            // 备份中不会出现链接等特殊条目，直接拒绝以免写到目标目录之外
+            other => {
+                return Err(entry_error(&name, format!("不支持的条目类型 {:?}", other), io::ErrorKind::InvalidData));
+            }
+        }
+    }
+
+    Ok((size, file_count))
+}
+
+// 不解压到磁盘，逐个读取压缩包中的普通文件，目录条目会被跳过
+pub fn read_archive<F>(archive: &Path, mut visit: F) -> io::Result<()>
+where
+    F: FnMut(&str, &mut dyn io::Read) -> io::Result<()>,
+{
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::Unsupported, format!("无法识别的压缩格式: {}", archive.display()))
+    })?;
+    let file = io::BufReader::new(File::open(archive)?);
+    match format {
+        ArchiveFormat::Zip => read_zip(file, &mut visit),
+        ArchiveFormat::SevenZ => read_7z(archive, &mut visit),
+        ArchiveFormat::Tar => read_tar(file, &mut visit),
+        ArchiveFormat::TarGz => read_tar(flate2::read::MultiGzDecoder::new(file), &mut visit),
+        ArchiveFormat::TarBz2 => read_tar(bzip2::read::MultiBzDecoder::new(file), &mut visit),
+        ArchiveFormat::TarXz => read_tar(xz2::read::XzDecoder::new_multi_decoder(file), &mut visit),
+    }
+}
+
+fn read_zip<R, F>(reader: R, visit: &mut F) -> io::Result<()>
+where
+    R: io::Read + io::Seek,
+    F: FnMut(&str, &mut dyn io::Read) -> io::Result<()>,
+{
+    let mut zip = ZipArchive::new(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| entry_error(&format!("#{}", i), e, io::ErrorKind::InvalidData))?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        visit(&name, &mut entry)?;
+    }
+    Ok(())
+}
+
+fn read_tar<R, F>(reader: R, visit: &mut F) -> io::Result<()>
+where
+    R: io::Read,
+    F: FnMut(&str, &mut dyn io::Read) -> io::Result<()>,
+{
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry.map_err(|e| entry_error("?", &e, e.kind()))?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => continue,
+            tar::EntryType::Regular | tar::EntryType::Continuous => visit(&name, &mut entry)?,
+            other => {
+                return Err(entry_error(&name, format!("不支持的条目类型 {:?}", other), io::ErrorKind::InvalidData));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_7z<F>(archive: &Path, visit: &mut F) -> io::Result<()>
+where
+    F: FnMut(&str, &mut dyn io::Read) -> io::Result<()>,
+{
+    let mut reader = sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    // 回调只能返回 sevenz 的错误类型，先把 visit 的错误保存下来再中止遍历
+    let mut visit_error = None;
+    let result = reader.for_each_entries(|entry, data| {
+        if entry.is_directory() {
+            return Ok(true);
+        }
+        match visit(entry.name(), data) {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                visit_error = Some(e);
+                Ok(false)
+            }
+        }
+    });
+    if let Some(e) = visit_error {
+        return Err(e);
+    }
+    result.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 写入一个带子目录和空目录的小世界
+    fn sample_world(root: &Path) {
+        fs::create_dir_all(root.join("db")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::write(root.join("level.dat"), b"level").unwrap();
+        fs::write(root.join("levelname.txt"), "世界").unwrap();
+        for index in 0..8 {
+            let data: Vec<u8> = (0..(index + 1) * 1000).map(|byte| (byte % 251) as u8).collect();
+            fs::write(root.join("db").join(format!("00000{}.ldb", index)), data).unwrap();
+        }
+    }
+
+    fn assert_same_tree(expected: &Path, actual: &Path) {
+        let (expected, actual) = (collect_entries(expected).unwrap(), collect_entries(actual).unwrap());
+        let names = |entries: &[ArchiveEntry]| entries.iter().map(|e| (e.name.clone(), e.is_dir)).collect::<Vec<_>>();
+        assert_eq!(names(&expected), names(&actual));
+        for (expected, actual) in expected.iter().zip(&actual).filter(|(e, _)| !e.is_dir) {
+            assert_eq!(fs::read(&expected.path).unwrap(), fs::read(&actual.path).unwrap(), "{}", expected.name);
+        }
+    }
+
+    fn round_trip(format: ArchiveFormat, threads: usize) {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target) = (dir.path().join("world"), dir.path().join("out"));
+        sample_world(&source);
+        let archive = archive_path_for(&dir.path().join("w_1"), format);
+
+        let stats = compress_dir(&source, &archive, &CompressOptions { format, level: 1, threads }).unwrap();
+        assert_eq!(stats.file_count, 10);
+        assert!(!partial_path(&archive).exists());
+        let extracted = extract_archive(&archive, &target).unwrap();
+        assert_eq!((extracted.size, extracted.file_count), (stats.source_size, stats.file_count));
+        assert_same_tree(&source, &target);
+    }
+
+    #[test]
+    fn round_trips_zip() {
+        round_trip(ArchiveFormat::Zip, 1);
+    }
+
+    #[test]
+    fn round_trips_tar_formats() {
+        round_trip(ArchiveFormat::TarGz, 1);
+        round_trip(ArchiveFormat::TarBz2, 1);
+        round_trip(ArchiveFormat::TarXz, 2);
+    }
+
+    #[test]
+    fn merges_parallel_zip_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("world");
+        sample_world(&source);
+        let archive = dir.path().join("w_1.zip");
+        round_trip(ArchiveFormat::Zip, 4);
+
+        // 分卷合并后是一个完整的 zip，条目按名称齐全，临时分卷已删除
+        compress_dir(&source, &archive, &CompressOptions { format: ArchiveFormat::Zip, level: 1, threads: 4 }).unwrap();
+        let zip = ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+        let mut names: Vec<_> = zip.file_names().map(str::to_string).collect();
+        names.sort();
+        let expected: Vec<_> = collect_entries(&source).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names.len(), expected.len());
+        for name in &expected {
+            assert!(names.iter().any(|n| n.trim_end_matches('/') == name), "{}", name);
+        }
+        assert!((0..4).all(|i| !dir.path().join(format!("w_1.zip.{}", i)).exists()));
+    }
+
+    #[test]
+    fn safe_join_rejects_escaping_paths() {
+        let target = Path::new("/srv/worlds/w");
+        assert_eq!(safe_join(target, "db/000001.ldb").unwrap(), target.join("db").join("000001.ldb"));
+        assert_eq!(safe_join(target, "./db\\CURRENT").unwrap(), target.join("db").join("CURRENT"));
+        // 开头的分隔符被忽略，绝对路径仍然落在目标目录内
+        assert_eq!(safe_join(target, "/etc/passwd").unwrap(), target.join("etc").join("passwd"));
+        for name in ["../evil", "db/../../evil", "..\\evil", "C:\\Windows\\evil", "C:evil", "", "./"] {
+            let e = safe_join(target, name).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_zip_entries_outside_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("evil.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("../evil.txt", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+
+        let e = extract_archive(&archive, &dir.path().join("out")).map(|_| ()).unwrap_err();
+        assert!(e.to_string().contains("越界"), "{}", e);
+        assert!(!dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn rejects_tar_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("evil.tar");
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "db", "/etc").unwrap();
+        builder.into_inner().unwrap();
+
+        let target = dir.path().join("out");
+        let e = extract_archive(&archive, &target).map(|_| ()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("不支持的条目类型"), "{}", e);
+        assert!(fs::symlink_metadata(target.join("db")).is_err());
+        let e = read_archive(&archive, |_, _| Ok(())).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use crate::utils::archive::{archive_path_for, compress_dir, ArchiveFormat, CompressOptions};
+use crate::utils::cleanup::delete_old_backups;
+use crate::utils::copy_db::copy_db;
+use crate::utils::crypto::{encrypt_file, encrypted_path_for, EncryptionSource};
+use crate::utils::manifest::{manifest_path_for, BackupManifest, DbFileEntry, Trigger};
+use crate::utils::report::ErrorMode;
+use crate::utils::retention::{apply_retention, RetentionPolicy};
+use crate::utils::save_query::SaveQueryList;
+use crate::utils::storage::RemoteSource;
+use crate::utils::upload::{file_sha256, upload_backup, UploadOptions};
+
+// 一次完整备份的请求，由 JSON 文件传入，拼错的字段直接报错
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupRequest {
+    pub source_world: PathBuf,
+    // 暂存目录，对应插件中的 backup_tmp
+    pub staging_dir: PathBuf,
+    // save query 输出所在的文件
+    pub db_list_file: PathBuf,
+    // 压缩包路径，不含后缀
+    pub destination: PathBuf,
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(default = "default_level")]
+    pub level: u32,
+    #[serde(default)]
+    pub threads: Option<usize>,
+    // 复制世界时遇到错误的处理方式，默认第一个错误就停止
+    #[serde(default)]
+    pub on_error: ErrorMode,
+    // 上一次备份的暂存目录，未变化的表文件从这里链接而不是重新复制
+    #[serde(default)]
+    pub base_dir: Option<PathBuf>,
+    // 写入清单的世界名称，不填时使用 source_world 的目录名
+    #[serde(default)]
+    pub world_name: Option<String>,
+    #[serde(default)]
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub cleanup: Option<CleanupRequest>,
+    #[serde(default)]
+    pub upload: Option<UploadRequest>,
+    // 设置时上传前加密，key_file 和 passphrase 都没有时使用环境变量
+    #[serde(default)]
+    pub encryption: Option<EncryptionSource>,
+    // 本地也只保留加密后的压缩包 "<压缩包>.enc"，否则只在上传时加密
+    #[serde(default)]
+    pub encrypt_at_rest: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CleanupRequest {
+    pub path: PathBuf,
+    // 按天数清理，设置了 retention 时忽略
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+    #[serde(default)]
+    pub retention: Option<RetentionPolicy>,
+    // 不填时使用压缩格式对应的后缀
+    #[serde(default)]
+    pub extension: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UploadRequest {
+    // remote_path、webdav_url、username、password、allow_insecure 和 s3
+    #[serde(flatten)]
+    pub source: RemoteSource,
+    // retry、strategy 和 chunk_size
+    #[serde(flatten)]
+    pub options: UploadOptions,
+}
+
+fn default_format() -> String {
+    "zip".to_string()
+}
+
+fn default_level() -> u32 {
+    5
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StageStatus {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+#[derive(Serialize)]
+pub struct StageResult {
+    pub name: &'static str,
+    pub status: StageStatus,
+    pub duration_ms: u128,
+    pub bytes: u64,
+    pub files: u64,
+    pub error: Option<String>,
+    // 上传阶段的请求尝试次数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct BackupResult {
+    pub success: bool,
+    pub archive: Option<String>,
+    pub duration_ms: u128,
+    pub stages: Vec<StageResult>,
+}
+
+impl BackupResult {
+    // 执行一个阶段，前面有阶段失败时直接跳过
+    fn run_stage<F>(&mut self, name: &'static str, stage: F)
+    where
+        F: FnOnce() -> Result<(u64, u64), String>,
+    {
+        if !self.success {
+            self.skip(name);
+            return;
+        }
+        let start = Instant::now();
+        let result = stage();
+        self.record(name, start, result);
+    }
+
+    fn skip(&mut self, name: &'static str) {
+        self.stages.push(StageResult { name, status: StageStatus::Skipped, duration_ms: 0, bytes: 0, files: 0, error: None, attempts: None });
+    }
+
+    fn record(&mut self, name: &'static str, start: Instant, result: Result<(u64, u64), String>) {
+        let duration_ms = start.elapsed().as_millis();
+        match result {
+            Ok((bytes, files)) => {
+                info!("阶段 {} 完成，耗时 {} ms", name, duration_ms);
+                self.stages.push(StageResult { name, status: StageStatus::Ok, duration_ms, bytes, files, error: None, attempts: None });
+            }
+            Err(e) => {
+                error!("阶段 {} 失败: {}", name, e);
+                self.success = false;
+                self.stages.push(StageResult { name, status: StageStatus::Failed, duration_ms, bytes: 0, files: 0, error: Some(e), attempts: None });
+            }
+        }
+    }
+}
+
+pub fn read_backup_request(path: &Path) -> io::Result<BackupRequest> {
+    let data = fs::read_to_string(path)?;
+    serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// 按 copy_db -> compress -> manifest -> encrypt -> cleanup -> upload 的顺序执行备份，任一阶段失败后后续阶段全部跳过
+pub async fn run_backup(request: &BackupRequest) -> BackupResult {
+    let start = Instant::now();
+    let mut result = BackupResult { success: true, archive: None, duration_ms: 0, stages: Vec::new() };
+
+    let format = ArchiveFormat::from_name(&request.format);
+    let mut archive_path = format.map(|format| archive_path_for(&request.destination, format));
+
+    result.run_stage("copy_db", || {
+        let report = copy_db(&request.source_world, &request.staging_dir, &request.db_list_file, request.base_dir.as_deref(), request.on_error)
+            .map_err(|e| e.to_string())?;
+        if !report.success {
+            return Err(report.summary());
+        }
+        Ok((report.bytes_copied + report.bytes_linked, report.copied + report.truncated + report.linked))
+    });
+
+    result.run_stage("compress", || {
+        let format = format.ok_or_else(|| format!("不支持的压缩格式: {}", request.format))?;
+        let options = CompressOptions {
+            format,
+            level: request.level,
+            threads: request.threads.unwrap_or_else(rayon::current_num_threads),
+        };
+        let stats = compress_dir(&request.staging_dir, archive_path.as_ref().unwrap(), &options).map_err(|e| e.to_string())?;
+        Ok((stats.size, stats.file_count))
+    });
+    if result.success {
+        result.archive = archive_path.as_ref().map(|path| path.to_string_lossy().into_owned());
+    }
+
+    result.run_stage("manifest", || {
+        let world_name = match &request.world_name {
+            Some(world_name) => world_name.clone(),
+            None => request.source_world.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        };
+        let mut db_files: Vec<DbFileEntry> = SaveQueryList::read(&request.source_world, &request.db_list_file)
+            .map_err(|e| e.to_string())?
+            .entries
+            .into_iter()
+            .map(|entry| DbFileEntry { path: entry.path, length: entry.length })
+            .collect();
+        db_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let archive = archive_path.as_ref().unwrap();
+        let manifest = BackupManifest::create(&world_name, request.trigger, db_files, &request.staging_dir, archive, &request.format)
+            .map_err(|e| e.to_string())?;
+        let manifest_path = manifest.write(archive).map_err(|e| e.to_string())?;
+        Ok((fs::metadata(manifest_path).map(|m| m.len()).unwrap_or(0), manifest.files.len() as u64))
+    });
+
+    let mut key = None;
+    match &request.encryption {
+        Some(source) => result.run_stage("encrypt", || {
+            let loaded = source.key().map_err(|e| e.to_string())?.ok_or("encryption 中没有设置 key_file 或 passphrase，也没有对应的环境变量")?;
+            key = Some(loaded);
+            if !request.encrypt_at_rest {
+                return Ok((0, 0));
+            }
+            // 清单描述的是加密前的压缩包，与加密后的压缩包放在一起
+            let archive = archive_path.as_ref().unwrap();
+            let encrypted = encrypted_path_for(archive);
+            let sha256 = file_sha256(archive).map_err(|e| e.to_string())?;
+            let size = encrypt_file(archive, &encrypted, key.as_ref().unwrap(), &sha256).map_err(|e| e.to_string())?;
+            fs::rename(manifest_path_for(archive), manifest_path_for(&encrypted)).map_err(|e| e.to_string())?;
+            fs::remove_file(archive).map_err(|e| e.to_string())?;
+            Ok((size, 1))
+        }),
+        None => result.skip("encrypt"),
+    }
+    if result.success && request.encrypt_at_rest && key.is_some() {
+        archive_path = archive_path.as_deref().map(encrypted_path_for);
+        result.archive = archive_path.as_ref().map(|path| path.to_string_lossy().into_owned());
+    }
+
+    match &request.cleanup {
+        Some(cleanup) => result.run_stage("cleanup", || {
+            let extension = match (&cleanup.extension, format) {
+                (Some(extension), _) => extension.trim_start_matches('.').to_string(),
+                (None, Some(_)) if request.encrypt_at_rest && key.is_some() => "enc".to_string(),
+                (None, Some(format)) => format.extension().rsplit('.').next().unwrap().to_string(),
+                (None, None) => return Err("无法确定要清理的备份后缀".to_string()),
+            };
+            match (&cleanup.retention, cleanup.max_age_days) {
+                (Some(policy), _) => {
+                    let report = apply_retention(&cleanup.path, &extension, policy, false).map_err(|e| e.to_string())?;
+                    if report.failed > 0 {
+                        return Err(report.summary());
+                    }
+                    Ok((report.pruned_bytes, report.pruned))
+                }
+                (None, Some(max_age_days)) => {
+                    let deleted = delete_old_backups(&cleanup.path, max_age_days, &extension).map_err(|e| e.to_string())?;
+                    Ok((0, deleted))
+                }
+                (None, None) => Err("清理需要设置 max_age_days 或 retention".to_string()),
+            }
+        }),
+        None => result.skip("cleanup"),
+    }
+
+    match &request.upload {
+        Some(upload) if result.success => {
+            let stage_start = Instant::now();
+            let archive = archive_path.as_ref().unwrap();
+            let mut upload_result = Ok((0, 0));
+            let mut attempts = 0;
+            let options = UploadOptions { encryption: key.clone(), ..upload.options.clone() };
+            let backend = upload.source.open(&options.retry);
+            if let Err(e) = &backend {
+                upload_result = Err(e.to_string());
+            }
+            // 清单与压缩包一起上传，远端也能校验
+            for path in [archive.clone(), manifest_path_for(archive)] {
+                let Ok(backend) = &backend else {
+                    break;
+                };
+                match upload_backup(&path, backend.as_ref(), &options).await {
+                    Ok(summary) => {
+                        attempts += summary.attempts;
+                        upload_result = upload_result.map(|(bytes, files)| (bytes + summary.bytes, files + summary.files));
+                    }
+                    Err(e) => {
+                        attempts += e.attempts();
+                        upload_result = Err(e.to_string());
+                        break;
+                    }
+                }
+            }
+            result.record("upload", stage_start, upload_result);
+            if let Some(stage) = result.stages.last_mut() {
+                stage.attempts = Some(attempts);
+            }
+        }
+        _ => result.skip("upload"),
+    }
+
+    result.duration_ms = start.elapsed().as_millis();
+    result
+}
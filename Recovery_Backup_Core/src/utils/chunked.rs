@@ -0,0 +1,288 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use crate::utils::events::Stage;
+use crate::utils::repository::write_atomic;
+use crate::utils::upload::{with_retry, UploadError, UploadOptions};
+
+// 分块上传进度文件的后缀，与压缩包放在同一目录
+pub const UPLOAD_STATE_SUFFIX: &str = ".upload.json";
+pub const DEFAULT_CHUNK_SIZE: u64 = 10 * 1024 * 1024;
+
+// 大文件的上传方式，文件不超过一个分块时总是整体 PUT
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum UploadStrategy {
+    // 一次 PUT 整个文件
+    #[default]
+    Single,
+    // Nextcloud/ownCloud 分块上传 v2: MKCOL 上传目录，PUT 编号分块，MOVE .file 合并
+    Nextcloud,
+    // 带 Content-Range 的 PUT 逐段写入目标文件，需要服务器支持
+    Ranged,
+}
+
+impl UploadStrategy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "single" => Some(Self::Single),
+            "nextcloud" => Some(Self::Nextcloud),
+            "ranged" => Some(Self::Ranged),
+            _ => None,
+        }
+    }
+}
+
+// 本地保存的上传进度，文件、目标或分块设置变化后不再续传
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct UploadState {
+    destination: String,
+    strategy: UploadStrategy,
+    size: u64,
+    // 文件修改时间，秒
+    modified: u64,
+    chunk_size: u64,
+    transfer_id: String,
+    // 服务器已确认的分块数
+    acknowledged: u64,
+}
+
+impl UploadState {
+    fn same_upload(&self, other: &UploadState) -> bool {
+        self.destination == other.destination
+            && self.strategy == other.strategy
+            && self.size == other.size
+            && self.modified == other.modified
+            && self.chunk_size == other.chunk_size
+    }
+}
+
+pub fn upload_state_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_os_string();
+    name.push(UPLOAD_STATE_SUFFIX);
+    PathBuf::from(name)
+}
+
+pub fn is_upload_state_path(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(UPLOAD_STATE_SUFFIX))
+}
+
+fn load_state(path: &Path) -> Option<UploadState> {
+    let data = fs::read(path).ok()?;
+    serde_json::from_slice(&data)
+        .map_err(|e| warn!("上传进度文件 {} 无效，重新上传: {}", path.display(), e))
+        .ok()
+}
+
+fn save_state(path: &Path, state: &UploadState) -> io::Result<()> {
+    write_atomic(path, &serde_json::to_vec_pretty(state).map_err(io::Error::other)?)
+}
+
+pub(crate) fn read_chunk(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+// 由 remote.php/dav/files/<用户>/... 形式的目标地址得到 uploads/<用户>/<transfer_id>
+fn nextcloud_upload_dir(destination: &Url, transfer_id: &str) -> io::Result<String> {
+    const FILES: &str = "/remote.php/dav/files/";
+    let path = destination.path();
+    let start = path.find(FILES).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "Nextcloud 分块上传需要 .../remote.php/dav/files/<用户名> 形式的 WebDAV 地址")
+    })?;
+    let rest = &path[start + FILES.len()..];
+    let user = rest.split('/').next().filter(|user| !user.is_empty()).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "WebDAV 地址中缺少用户名"))?;
+    let mut upload_dir = destination.clone();
+    upload_dir.set_path(&format!("{}/remote.php/dav/uploads/{}/{}", &path[..start], user, transfer_id));
+    upload_dir.set_query(None);
+    Ok(upload_dir.to_string())
+}
+
+// 分块发送请求，成功时返回响应状态
+async fn send(request: RequestBuilder, url: &str, accept: &[StatusCode]) -> Result<StatusCode, UploadError> {
+    match request.send().await {
+        Ok(res) if res.status().is_success() || accept.contains(&res.status()) => Ok(res.status()),
+        Ok(res) => Err(UploadError::status(url, res).await),
+        Err(e) => Err(UploadError::network(url, e)),
+    }
+}
+
+// 按分块上传文件，每个分块确认后写入进度文件，再次运行时从下一个分块继续，返回请求的尝试次数
+// checksum 在合并分块时作为 OC-Checksum 发送，Content-Range 方式不支持
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn upload_chunked(client: &Client, file_path: &Path, url: &str, username: &str, password: &str, checksum: Option<&str>, options: &UploadOptions, stage: &Stage) -> Result<u32, UploadError> {
+    let metadata = fs::metadata(file_path)?;
+    let size = metadata.len();
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let chunk_size = options.chunk_size.max(1);
+    let destination = Url::parse(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的上传地址 {}: {}", url, e)))?;
+    let transfer_id = format!("backupjs-{}", &blake3::hash(format!("{}|{}|{}", destination, size, modified).as_bytes()).to_hex()[..16]);
+
+    let state_path = upload_state_path(file_path);
+    let fresh = UploadState {
+        destination: destination.to_string(),
+        strategy: options.strategy,
+        size,
+        modified,
+        chunk_size,
+        transfer_id,
+        acknowledged: 0,
+    };
+    let mut state = match load_state(&state_path) {
+        Some(saved) if saved.same_upload(&fresh) => saved,
+        _ => fresh,
+    };
+    let chunk_count = size.div_ceil(chunk_size);
+    let total_length = size.to_string();
+    let mut attempts = 0;
+
+    let upload_dir = match options.strategy {
+        UploadStrategy::Nextcloud => Some(nextcloud_upload_dir(&destination, &state.transfer_id)?),
+        _ => None,
+    };
+    if let Some(upload_dir) = &upload_dir {
+        // 405 表示上传目录仍在，可以续传；新建了目录说明服务器已丢弃之前的分块
+        let mkcol = Method::from_bytes(b"MKCOL").expect("valid method");
+        let (status, count) = with_retry(&options.retry, upload_dir, || {
+            let request = client.request(mkcol.clone(), upload_dir).basic_auth(username, Some(password)).header("Destination", destination.as_str());
+            send(request, upload_dir, &[StatusCode::METHOD_NOT_ALLOWED])
+        })
+        .await?;
+        attempts += count;
+        if status != StatusCode::METHOD_NOT_ALLOWED && state.acknowledged > 0 {
+            warn!("服务器上没有之前上传的分块，从头开始上传");
+            state.acknowledged = 0;
+        }
+    }
+
+    let acknowledged = state.acknowledged.min(chunk_count);
+    if acknowledged > 0 {
+        info!("从第 {}/{} 个分块继续上传 {}", acknowledged + 1, chunk_count, file_path.display());
+        stage.add_bytes((acknowledged * chunk_size).min(size));
+    }
+
+    let mut file = File::open(file_path)?;
+    for index in acknowledged..chunk_count {
+        let offset = index * chunk_size;
+        let len = chunk_size.min(size - offset);
+        let data = read_chunk(&mut file, offset, len)?;
+        let chunk_url = match &upload_dir {
+            Some(upload_dir) => format!("{}/{:05}", upload_dir, index + 1),
+            None => url.to_string(),
+        };
+
+        let (_, count) = with_retry(&options.retry, &chunk_url, || {
+            let mut request = client.put(&chunk_url).basic_auth(username, Some(password)).body(data.clone());
+            request = match options.strategy {
+                UploadStrategy::Ranged => request.header("Content-Range", format!("bytes {}-{}/{}", offset, offset + len - 1, size)),
+                _ => request.header("Destination", destination.as_str()).header("OC-Total-Length", &total_length),
+            };
+            send(request, &chunk_url, &[])
+        })
+        .await?;
+        attempts += count;
+
+        state.acknowledged = index + 1;
+        save_state(&state_path, &state)?;
+        stage.add_bytes(len);
+    }
+
+    if let Some(upload_dir) = &upload_dir {
+        let assemble_url = format!("{}/.file", upload_dir);
+        let method = Method::from_bytes(b"MOVE").expect("valid method");
+        let (_, count) = with_retry(&options.retry, &assemble_url, || {
+            let mut request = client
+                .request(method.clone(), &assemble_url)
+                .basic_auth(username, Some(password))
+                .header("Destination", destination.as_str())
+                .header("OC-Total-Length", &total_length);
+            if let Some(checksum) = checksum {
+                request = request.header("OC-Checksum", checksum);
+            }
+            send(request, &assemble_url, &[])
+        })
+        .await?;
+        attempts += count;
+    }
+
+    if let Err(e) = fs::remove_file(&state_path) {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("删除上传进度文件 {} 失败: {}", state_path.display(), e);
+        }
+    }
+    info!("分块上传完成: {} ({} 个分块)", file_path.display(), chunk_count);
+    Ok(attempts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> UploadState {
+        UploadState {
+            destination: "https://cloud.example.com/remote.php/dav/files/alice/backups/w_1.zip".to_string(),
+            strategy: UploadStrategy::Nextcloud,
+            size: 25 * 1024 * 1024,
+            modified: 1_700_000_000,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            transfer_id: "rbc-1".to_string(),
+            acknowledged: 2,
+        }
+    }
+
+    #[test]
+    fn resumes_only_the_same_upload() {
+        let saved = state();
+        // 传输编号和进度不影响是否续传
+        assert!(saved.same_upload(&UploadState { transfer_id: "rbc-2".to_string(), acknowledged: 0, ..state() }));
+
+        let changed = [
+            UploadState { destination: "https://cloud.example.com/remote.php/dav/files/alice/other/w_1.zip".to_string(), ..state() },
+            UploadState { strategy: UploadStrategy::Ranged, ..state() },
+            UploadState { size: saved.size + 1, ..state() },
+            UploadState { modified: saved.modified + 1, ..state() },
+            UploadState { chunk_size: saved.chunk_size * 2, ..state() },
+        ];
+        for fresh in &changed {
+            assert!(!saved.same_upload(fresh), "{:?}", fresh);
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = upload_state_path(&dir.path().join("w_1.zip"));
+        assert!(path.ends_with("w_1.zip.upload.json") && is_upload_state_path(&path));
+        assert_eq!(load_state(&path), None);
+
+        save_state(&path, &state()).unwrap();
+        assert_eq!(load_state(&path), Some(state()));
+        // 损坏的进度文件当作没有进度，重新上传
+        fs::write(&path, b"{").unwrap();
+        assert_eq!(load_state(&path), None);
+    }
+
+    #[test]
+    fn derives_nextcloud_upload_dir() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert_eq!(
+            nextcloud_upload_dir(&url("https://cloud.example.com/remote.php/dav/files/alice/backups/w_1.zip"), "rbc-1").unwrap(),
+            "https://cloud.example.com/remote.php/dav/uploads/alice/rbc-1"
+        );
+        // 安装在子路径下，查询参数不带到上传目录
+        assert_eq!(
+            nextcloud_upload_dir(&url("http://host:8080/nextcloud/remote.php/dav/files/bob%20b/w.zip?x=1"), "id").unwrap(),
+            "http://host:8080/nextcloud/remote.php/dav/uploads/bob%20b/id"
+        );
+        for bad in ["https://cloud.example.com/webdav/w_1.zip", "https://cloud.example.com/remote.php/dav/files//w_1.zip"] {
+            let e = nextcloud_upload_dir(&url(bad), "id").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", bad);
+        }
+    }
+}
@@ -1,41 +1,73 @@
 use std::{fs, io, thread};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::SystemTime;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use crate::utils::archive::backup_suffix;
+use crate::utils::crypto::encrypted_path_for;
+use crate::utils::events::Stage;
+use crate::utils::manifest::{manifest_path_for, BackupManifest, Trigger};
 
-pub fn delete_old_backups(backup_path: &Path, max_age_days: u64, extension: &str) -> io::Result<()> {
+// 删除超过保留天数的备份，返回删除的文件数量
+pub fn delete_old_backups(backup_path: &Path, max_age_days: u64, extension: &str) -> io::Result<u64> {
     let now = SystemTime::now();
     let cutoff_time = now - std::time::Duration::from_secs(max_age_days * 24 * 60 * 60);
 
+    let suffix = backup_suffix(extension);
     let mut files = Vec::new();
     for entry in fs::read_dir(backup_path)? {
         let entry = entry?;
         let path = entry.path();
 
-        if path.is_file() {
-            let metadata = fs::metadata(&path)?;
-            let modified_time = metadata.modified()?;
+        // 检查文件后缀是否匹配，tar.gz 等格式需要比较完整的文件名后缀
+        let matches = path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(&suffix));
+        if path.is_file() && matches {
+            // 有清单时以清单中的创建时间为准，永久备份不会被清理
+            let created_time = match BackupManifest::read(&path) {
+                Ok(Some(manifest)) if manifest.trigger == Trigger::Permanent => continue,
+                Ok(Some(manifest)) => SystemTime::from(manifest.created_at),
+                Ok(None) => fs::metadata(&path)?.modified()?,
+                Err(e) => {
+                    warn!("{}", e);
+                    fs::metadata(&path)?.modified()?
+                }
+            };
 
-            // 检查文件后缀是否匹配
-            if modified_time < cutoff_time && path.extension().and_then(|ext| ext.to_str()) == Some(extension) {
+            if created_time < cutoff_time {
                 files.push(path);
             }
         }
     }
 
+    let bytes_total = files.iter().filter_map(|path| fs::metadata(path).ok()).map(|m| m.len()).sum();
+    let stage = Stage::start("cleanup", bytes_total, files.len() as u64);
     let files = Arc::new(Mutex::new(files));
+    let deleted = Arc::new(AtomicU64::new(0));
     let mut handles = vec![];
     const NUM_THREADS: usize = 4;
 
     for _ in 0..NUM_THREADS {
         let files = Arc::clone(&files);
+        let deleted = Arc::clone(&deleted);
+        let stage = stage.clone();
         let handle = thread::spawn(move || {
             while let Some(path) = files.lock().unwrap().pop() {
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
                 if let Err(e) = fs::remove_file(&path) {
                     error!("Failed to delete file: {:?}", e);
                 } else {
-                    info!("Deleted old backup file: {:?}", path)
+                    deleted.fetch_add(1, Ordering::Relaxed);
+                    stage.file_done(&path.to_string_lossy(), size);
+                    info!("Deleted old backup file: {:?}", path);
+                    // 清单和上传时加密留下的 "<压缩包>.enc" 一起删除
+                    for sidecar in [manifest_path_for(&path), encrypted_path_for(&path)] {
+                        if sidecar.exists() {
+                            if let Err(e) = fs::remove_file(&sidecar) {
+                                error!("Failed to delete {:?}: {:?}", sidecar, e);
+                            }
+                        }
+                    }
                 }
             }
         });
@@ -46,7 +78,35 @@ pub fn delete_old_backups(backup_path: &Path, max_age_days: u64, extension: &str
         handle.join().unwrap();
     }
 
-    Ok(())
+    let result: io::Result<u64> = Ok(deleted.load(Ordering::Relaxed));
+    stage.finish(&result);
+    result
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn backup_file(dir: &Path, name: &str, age_days: u64) {
+        let file = File::create(dir.join(name)).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age_days * 24 * 60 * 60)).unwrap();
+    }
+
+    #[test]
+    fn deletes_old_tar_gz_backups() {
+        // 插件传入格式名，--config 填入扩展名，两者都要匹配 .tar.gz
+        for extension in ["gzip", "tar.gz"] {
+            let dir = tempfile::tempdir().unwrap();
+            backup_file(dir.path(), "w_old.tar.gz", 10);
+            backup_file(dir.path(), "w_new.tar.gz", 1);
+            backup_file(dir.path(), "w_old.zip", 10);
 
+            assert_eq!(delete_old_backups(dir.path(), 7, extension).unwrap(), 1);
+            assert!(!dir.path().join("w_old.tar.gz").exists());
+            assert!(dir.path().join("w_new.tar.gz").exists());
+            assert!(dir.path().join("w_old.zip").exists());
+        }
+    }
+}
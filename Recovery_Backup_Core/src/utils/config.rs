@@ -0,0 +1,597 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use base64::Engine;
+use base64::engine::general_purpose;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::utils::archive::ArchiveFormat;
+use crate::utils::chunked::{UploadStrategy, DEFAULT_CHUNK_SIZE};
+use crate::utils::crypto::{EncryptionKey, EncryptionSource};
+use crate::utils::s3::{default_region, S3Target};
+use crate::utils::sftp::{default_port, SftpTarget};
+use crate::utils::storage::RemoteSource;
+use crate::utils::upload::{RetryPolicy, UploadOptions, DEFAULT_CONCURRENCY};
+
+// 环境变量覆盖的前缀，例如 BACKUPJS_BACKUP_PATH、BACKUPJS_UPLOAD__PASSWORD
+pub const ENV_PREFIX: &str = "BACKUPJS_";
+// 指定配置文件路径的环境变量，不作为覆盖项
+pub const ENV_CONFIG: &str = "BACKUPJS_CONFIG";
+
+// 与 BackupJS 的 config.json 使用相同的字段名和默认值
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    #[serde(rename = "Language")]
+    pub language: String,
+    // 普通备份保留的天数
+    #[serde(rename = "MaxStorageTime")]
+    pub max_storage_time: u64,
+    #[serde(rename = "BackupPath")]
+    pub backup_path: PathBuf,
+    #[serde(rename = "PermanentBackupPath")]
+    pub permanent_backup_path: PathBuf,
+    #[serde(rename = "queryRetries")]
+    pub query_retries: u32,
+    // 毫秒
+    #[serde(rename = "retryDelay")]
+    pub retry_delay: u64,
+    #[serde(rename = "initialDelay")]
+    pub initial_delay: u64,
+    pub format: String,
+    // 压缩级别 0-9
+    #[serde(rename = "Compress")]
+    pub compress: u32,
+    // 秒
+    #[serde(rename = "MaxWaitForZip")]
+    pub max_wait_for_zip: u64,
+    // 7za.exe 所在目录
+    #[serde(rename = "7za")]
+    pub seven_zip: PathBuf,
+    #[serde(rename = "RecoveryBackupCore")]
+    pub recovery_backup_core: PathBuf,
+    #[serde(rename = "serverExe")]
+    pub server_exe: String,
+    pub upload: UploadConfig,
+    pub encryption: EncryptionConfig,
+    pub allowlist: Vec<String>,
+    #[serde(rename = "Serein")]
+    pub serein: SereinConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UploadConfig {
+    pub remote_path: String,
+    pub webdav_url: String,
+    pub username: String,
+    pub password: String,
+    pub allow_insecure: bool,
+    // 包括第一次在内的最多尝试次数
+    pub retries: u32,
+    // 第一次重试前等待的毫秒数，之后每次翻倍
+    pub retry_delay: u64,
+    pub retry_max_delay: u64,
+    // 超过 chunkSize 字节的文件使用的上传方式: single、nextcloud 或 ranged
+    pub strategy: UploadStrategy,
+    pub chunk_size: u64,
+    // 上传目录时同时上传的文件数
+    pub concurrency: usize,
+    // 远程已有大小和校验和相同的文件时不再上传
+    pub skip_unchanged: bool,
+    // 远程存储类型: webdav、s3 或 sftp，共用 remotePath
+    pub backend: StorageKind,
+    pub s3: S3Config,
+    pub sftp: SftpConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[default]
+    Webdav,
+    S3,
+    Sftp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct S3Config {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    // MinIO、Garage 等自建服务通常需要 endpoint/bucket/key 形式的地址
+    pub path_style: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SftpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    // 私钥文件，不为空时使用密钥认证
+    pub key_file: String,
+    pub key_passphrase: String,
+    // 为空时使用 ~/.ssh/known_hosts
+    pub known_hosts: String,
+}
+
+// 备份上传前的加密，keyFile 优先于 passphrase
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    pub key_file: String,
+    pub passphrase: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SereinConfig {
+    pub enabled: bool,
+    pub id: String,
+    pub host: String,
+    pub auth: String,
+    pub pmid: String,
+    pub gmid: String,
+    pub msg: SereinMessages,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct SereinMessages {
+    pub processing: String,
+    pub success: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            language: "zh_CN".to_string(),
+            max_storage_time: 7,
+            backup_path: PathBuf::from("./backup"),
+            permanent_backup_path: PathBuf::from("./backup/permanent_backup"),
+            query_retries: 10,
+            retry_delay: 100,
+            initial_delay: 50,
+            format: "zip".to_string(),
+            compress: 0,
+            max_wait_for_zip: 1800,
+            seven_zip: PathBuf::from("./plugins/BackupJS"),
+            recovery_backup_core: PathBuf::from("./plugins/BackupJS"),
+            server_exe: "bedrock_server_mod.exe".to_string(),
+            upload: UploadConfig::default(),
+            encryption: EncryptionConfig::default(),
+            allowlist: vec!["114514".to_string()],
+            serein: SereinConfig::default(),
+        }
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            remote_path: "/backup".to_string(),
+            webdav_url: "https://xxx.com/webdav".to_string(),
+            username: "123".to_string(),
+            password: "114514".to_string(),
+            allow_insecure: false,
+            retries: RetryPolicy::default().max_attempts,
+            retry_delay: RetryPolicy::default().initial_delay_ms,
+            retry_max_delay: RetryPolicy::default().max_delay_ms,
+            strategy: UploadStrategy::Single,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            concurrency: DEFAULT_CONCURRENCY,
+            skip_unchanged: true,
+            backend: StorageKind::Webdav,
+            s3: S3Config::default(),
+            sftp: SftpConfig::default(),
+        }
+    }
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        S3Config {
+            endpoint: String::new(),
+            region: default_region(),
+            bucket: String::new(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            path_style: false,
+        }
+    }
+}
+
+impl Default for SftpConfig {
+    fn default() -> Self {
+        SftpConfig {
+            host: String::new(),
+            port: default_port(),
+            username: String::new(),
+            password: String::new(),
+            key_file: String::new(),
+            key_passphrase: String::new(),
+            known_hosts: String::new(),
+        }
+    }
+}
+
+impl Default for SereinConfig {
+    fn default() -> Self {
+        SereinConfig {
+            enabled: false,
+            id: "myserver".to_string(),
+            host: "http://127.0.0.1:61545".to_string(),
+            auth: "abcd".to_string(),
+            pmid: String::new(),
+            gmid: String::new(),
+            msg: SereinMessages::default(),
+        }
+    }
+}
+
+impl Default for SereinMessages {
+    fn default() -> Self {
+        SereinMessages { processing: "正在回档".to_string(), success: "回档成功".to_string() }
+    }
+}
+
+impl Config {
+    // 依次应用默认值、配置文件、BACKUPJS_ 环境变量和命令行的 key=value 覆盖，后者优先
+    pub fn load(path: Option<&Path>, overrides: &[String]) -> io::Result<Self> {
+        Self::load_with(path, std::env::vars(), overrides)
+    }
+
+    // 环境变量由调用方给出，测试不需要修改进程的环境
+    fn load_with(path: Option<&Path>, env: impl IntoIterator<Item = (String, String)>, overrides: &[String]) -> io::Result<Self> {
+        let mut value = serde_json::to_value(Config::default()).map_err(io::Error::other)?;
+        let mut problems = Vec::new();
+
+        if let Some(path) = path {
+            let text = fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("读取配置文件 {} 失败: {}", path.display(), e)))?;
+            let file: Value = serde_json::from_str(&text)
+                .map_err(|e| invalid(format!("配置文件 {} 格式错误: {}", path.display(), e)))?;
+            merge(&mut value, file, "", &mut problems);
+        }
+
+        let mut env_vars: Vec<(String, String)> = env
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != ENV_CONFIG)
+            .collect();
+        env_vars.sort();
+        for (name, raw) in env_vars {
+            let keys: Vec<&str> = name[ENV_PREFIX.len()..].split("__").collect();
+            if let Err(e) = set_value(&mut value, &keys, &raw) {
+                problems.push(format!("环境变量 {}: {}", name, e));
+            }
+        }
+
+        for assignment in overrides {
+            let Some((key, raw)) = assignment.split_once('=') else {
+                problems.push(format!("--set {}: 应为 key=value 形式", assignment));
+                continue;
+            };
+            let keys: Vec<&str> = key.split('.').collect();
+            if let Err(e) = set_value(&mut value, &keys, raw) {
+                problems.push(format!("--set {}: {}", key, e));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(invalid(format!("配置无效: {}", problems.join("; "))));
+        }
+        let config: Config = serde_json::from_value(value).map_err(|e| invalid(format!("配置无效: {}", e)))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // 检查字段之间的取值，一次报告所有问题
+    pub fn validate(&self) -> io::Result<()> {
+        let mut problems = Vec::new();
+        if ArchiveFormat::from_name(&self.format).is_none() {
+            problems.push(format!("format: 不支持的压缩格式 \"{}\"", self.format));
+        }
+        if self.compress > 9 {
+            problems.push(format!("Compress: 压缩级别 {} 超出 0-9", self.compress));
+        }
+        if self.backup_path.as_os_str().is_empty() {
+            problems.push("BackupPath: 不能为空".to_string());
+        }
+        if self.permanent_backup_path.as_os_str().is_empty() {
+            problems.push("PermanentBackupPath: 不能为空".to_string());
+        }
+        if !self.upload.webdav_url.is_empty() && !is_http_url(&self.upload.webdav_url) {
+            problems.push(format!("upload.webdavUrl: \"{}\" 不是 http(s) 地址", self.upload.webdav_url));
+        }
+        if self.upload.backend == StorageKind::S3 {
+            if !is_http_url(&self.upload.s3.endpoint) {
+                problems.push(format!("upload.s3.endpoint: \"{}\" 不是 http(s) 地址", self.upload.s3.endpoint));
+            }
+            if self.upload.s3.bucket.is_empty() {
+                problems.push("upload.s3.bucket: 使用 s3 时不能为空".to_string());
+            }
+        }
+        if self.upload.backend == StorageKind::Sftp {
+            if self.upload.sftp.host.is_empty() || self.upload.sftp.username.is_empty() {
+                problems.push("upload.sftp: 使用 sftp 时 host 和 username 不能为空".to_string());
+            }
+            if self.upload.sftp.password.is_empty() && self.upload.sftp.key_file.is_empty() {
+                problems.push("upload.sftp: 需要设置 password 或 keyFile".to_string());
+            }
+        }
+        if self.upload.chunk_size == 0 {
+            problems.push("upload.chunkSize: 不能为 0".to_string());
+        }
+        if self.encryption.enabled && self.encryption.key_file.is_empty() && self.encryption.passphrase.is_empty() {
+            problems.push("encryption: 启用时需要设置 keyFile 或 passphrase".to_string());
+        }
+        if self.upload.concurrency == 0 {
+            problems.push("upload.concurrency: 不能为 0".to_string());
+        }
+        if self.serein.enabled {
+            if !is_http_url(&self.serein.host) {
+                problems.push(format!("Serein.host: \"{}\" 不是 http(s) 地址", self.serein.host));
+            }
+            if self.serein.id.is_empty() || self.serein.auth.is_empty() {
+                problems.push("Serein: 启用时 id 和 auth 不能为空".to_string());
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(invalid(format!("配置无效: {}", problems.join("; "))))
+    }
+
+    pub fn upload_options(&self) -> UploadOptions {
+        UploadOptions {
+            retry: RetryPolicy {
+                max_attempts: self.upload.retries,
+                initial_delay_ms: self.upload.retry_delay,
+                max_delay_ms: self.upload.retry_max_delay,
+                ..RetryPolicy::default()
+            },
+            strategy: self.upload.strategy,
+            chunk_size: self.upload.chunk_size,
+            concurrency: self.upload.concurrency,
+            skip_unchanged: self.upload.skip_unchanged,
+            // 密钥可能读取失败，由调用方通过 encryption_key 设置
+            encryption: None,
+        }
+    }
+
+    // 配置的密钥，未设置 keyFile 和 passphrase 时使用环境变量，都没有时为 None
+    pub fn encryption_key(&self) -> io::Result<Option<EncryptionKey>> {
+        let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        EncryptionSource { key_file: non_empty(&self.encryption.key_file).map(PathBuf::from), passphrase: non_empty(&self.encryption.passphrase) }.key()
+    }
+
+    // 远程存储设置，未配置 webdavUrl、S3 地址或 SFTP 主机时为 None
+    pub fn remote_source(&self) -> Option<RemoteSource> {
+        let upload = &self.upload;
+        let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        let (s3, sftp) = match upload.backend {
+            StorageKind::Webdav if upload.webdav_url.is_empty() => return None,
+            StorageKind::Webdav => (None, None),
+            StorageKind::S3 if upload.s3.endpoint.is_empty() => return None,
+            StorageKind::S3 => (Some(S3Target {
+                endpoint: upload.s3.endpoint.clone(),
+                region: upload.s3.region.clone(),
+                bucket: upload.s3.bucket.clone(),
+                access_key: upload.s3.access_key.clone(),
+                secret_key: upload.s3.secret_key.clone(),
+                path_style: upload.s3.path_style,
+            }), None),
+            StorageKind::Sftp if upload.sftp.host.is_empty() => return None,
+            StorageKind::Sftp => (None, Some(SftpTarget {
+                host: upload.sftp.host.clone(),
+                port: upload.sftp.port,
+                username: upload.sftp.username.clone(),
+                password: upload.sftp.password.clone(),
+                key_file: non_empty(&upload.sftp.key_file).map(PathBuf::from),
+                key_passphrase: non_empty(&upload.sftp.key_passphrase),
+                known_hosts: non_empty(&upload.sftp.known_hosts).map(PathBuf::from),
+            })),
+        };
+        Some(RemoteSource {
+            remote_path: upload.remote_path.clone(),
+            webdav_url: upload.webdav_url.clone(),
+            username: upload.username.clone(),
+            password: upload.password.clone(),
+            allow_insecure: upload.allow_insecure,
+            s3,
+            sftp,
+        })
+    }
+
+    pub fn archive_format(&self) -> ArchiveFormat {
+        ArchiveFormat::from_name(&self.format).unwrap_or(ArchiveFormat::Zip)
+    }
+
+    pub fn seven_zip_exe(&self) -> PathBuf {
+        self.seven_zip.join(if cfg!(windows) { "7za.exe" } else { "7za" })
+    }
+
+    // 与插件相同的 Serein 回档通知地址和认证，未启用时为 None
+    pub fn serein_recover(&self) -> Option<(String, String)> {
+        if !self.serein.enabled {
+            return None;
+        }
+        let mut url = format!("{}/serein/{{}}?id={}", self.serein.host, self.serein.id);
+        if !self.serein.pmid.is_empty() {
+            url.push_str(&format!("&pmid={}", self.serein.pmid));
+        }
+        if !self.serein.gmid.is_empty() {
+            url.push_str(&format!("&gmid={}", self.serein.gmid));
+        }
+        let msg = &self.serein.msg;
+        if !msg.processing.is_empty() && !msg.success.is_empty() {
+            url.push_str(&format!("&msg={}", general_purpose::STANDARD.encode(&msg.processing)));
+            url.push_str(&format!("&msg={}", general_purpose::STANDARD.encode(&msg.success)));
+        }
+        Some((url, self.serein.auth.clone()))
+    }
+
+    // stats 检查 Serein 状态使用的地址
+    pub fn serein_health(&self) -> Option<(String, String)> {
+        self.serein
+            .enabled
+            .then(|| (format!("{}/serein/health", self.serein.host), self.serein.auth.clone()))
+    }
+}
+
+// 从 server.properties 读取当前世界名
+pub fn read_level_name(server_dir: &Path) -> io::Result<String> {
+    let text = fs::read_to_string(server_dir.join("server.properties"))?;
+    text.lines()
+        .find_map(|line| line.trim().strip_prefix("level-name=").map(|name| name.trim().to_string()))
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| invalid("server.properties 中没有 level-name"))
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+// 键名比较时忽略大小写和下划线，使 BACKUP_PATH 能匹配 BackupPath
+fn normalize_key(key: &str) -> String {
+    key.chars().filter(|c| *c != '_' && *c != '-').flat_map(char::to_lowercase).collect()
+}
+
+fn find_key(object: &Map<String, Value>, key: &str) -> Option<String> {
+    let normalized = normalize_key(key);
+    object.keys().find(|existing| normalize_key(existing) == normalized).cloned()
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "布尔值",
+        Value::Number(_) => "数字",
+        Value::String(_) => "字符串",
+        Value::Array(_) => "数组",
+        Value::Object(_) => "对象",
+    }
+}
+
+// 与插件的 deepMerge 相同，额外检查每个已知字段的类型，未知字段保留给插件使用
+fn merge(target: &mut Value, source: Value, prefix: &str, problems: &mut Vec<String>) {
+    let (Value::Object(target), Value::Object(source)) = (target, source) else {
+        return;
+    };
+    for (key, value) in source {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match target.get_mut(&key) {
+            Some(existing @ Value::Object(_)) if value.is_object() => merge(existing, value, &path, problems),
+            Some(existing) if std::mem::discriminant(existing) != std::mem::discriminant(&value) => {
+                problems.push(format!("{}: 应为{}，实际为{}", path, type_name(existing), type_name(&value)));
+            }
+            Some(existing) => *existing = value,
+            None => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+// 按键路径设置一个已知字段，字符串字段直接使用原文，其他字段按 JSON 解析
+fn set_value(root: &mut Value, keys: &[&str], raw: &str) -> Result<(), String> {
+    let mut current = root;
+    for (index, key) in keys.iter().enumerate() {
+        let Value::Object(object) = current else {
+            return Err(format!("{} 不是对象", keys[..index].join(".")));
+        };
+        let name = find_key(object, key).ok_or_else(|| format!("未知的配置项 {}", key))?;
+        current = object.get_mut(&name).expect("key exists");
+    }
+    let parsed = match &*current {
+        Value::String(_) => Value::String(raw.to_string()),
+        existing => {
+            let parsed: Value = serde_json::from_str(raw).map_err(|_| format!("\"{}\" 不是有效的{}", raw, type_name(existing)))?;
+            if std::mem::discriminant(existing) != std::mem::discriminant(&parsed) {
+                return Err(format!("应为{}，实际为{}", type_name(existing), type_name(&parsed)));
+            }
+            parsed
+        }
+    };
+    *current = parsed;
+    Ok(())
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn sets(assignments: &[&str]) -> Vec<String> {
+        assignments.iter().map(|assignment| assignment.to_string()).collect()
+    }
+
+    fn config_file(dir: &Path) -> PathBuf {
+        let path = dir.join("config.json");
+        fs::write(&path, r#"{"BackupPath":"/file","Compress":3,"MaxStorageTime":30,"upload":{"username":"file","retries":2},"custom":{"kept":true}}"#).unwrap();
+        path
+    }
+
+    #[test]
+    fn later_sources_take_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = config_file(dir.path());
+
+        let config = Config::load_with(Some(&path), env(&[]), &[]).unwrap();
+        assert_eq!((config.backup_path.as_path(), config.compress, config.upload.username.as_str()), (Path::new("/file"), 3, "file"));
+        // 配置文件中没有的字段保留默认值
+        assert_eq!((config.format.as_str(), config.upload.chunk_size), ("zip", DEFAULT_CHUNK_SIZE));
+
+        // 环境变量覆盖配置文件，键名忽略大小写和下划线，嵌套字段用 __ 分隔
+        let vars = env(&[("BACKUPJS_BACKUP_PATH", "/env"), ("BACKUPJS_COMPRESS", "5"), ("BACKUPJS_UPLOAD__USERNAME", "env"), ("BACKUPJS_CONFIG", "ignored.json"), ("OTHER_COMPRESS", "9")]);
+        let config = Config::load_with(Some(&path), vars.clone(), &[]).unwrap();
+        assert_eq!((config.backup_path.as_path(), config.compress, config.upload.username.as_str()), (Path::new("/env"), 5, "env"));
+        assert_eq!((config.max_storage_time, config.upload.retries), (30, 2));
+
+        // --set 覆盖环境变量
+        let config = Config::load_with(Some(&path), vars, &sets(&["Compress=7", "upload.username=cli", "upload.retries=6"])).unwrap();
+        assert_eq!((config.backup_path.as_path(), config.compress, config.upload.username.as_str(), config.upload.retries), (Path::new("/env"), 7, "cli", 6));
+    }
+
+    #[test]
+    fn string_overrides_are_taken_verbatim() {
+        // 字符串字段不按 JSON 解析，数字样子的密码保持原样
+        let config = Config::load_with(None, env(&[("BACKUPJS_UPLOAD__PASSWORD", "0123")]), &sets(&["upload.remotePath=/a=b"])).unwrap();
+        assert_eq!((config.upload.password.as_str(), config.upload.remote_path.as_str()), ("0123", "/a=b"));
+    }
+
+    #[test]
+    fn reports_all_invalid_overrides() {
+        let vars = env(&[("BACKUPJS_COMPRESS", "high"), ("BACKUPJS_NO_SUCH_KEY", "1")]);
+        let e = Config::load_with(None, vars, &sets(&["upload.retries=\"3\"", "format"])).unwrap_err();
+        let message = e.to_string();
+        for expected in ["环境变量 BACKUPJS_COMPRESS", "未知的配置项 NO_SUCH_KEY", "--set upload.retries: 应为数字，实际为字符串", "--set format: 应为 key=value 形式"] {
+            assert!(message.contains(expected), "{}: {}", expected, message);
+        }
+
+        // 合并后仍然检查取值范围
+        let e = Config::load_with(None, env(&[]), &sets(&["Compress=12"])).unwrap_err();
+        assert!(e.to_string().contains("超出 0-9"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_mistyped_config_file_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, r#"{"Compress":"5","upload":{"retries":true}}"#).unwrap();
+        let e = Config::load_with(Some(&path), env(&[]), &[]).unwrap_err();
+        assert!(e.to_string().contains("Compress: 应为数字，实际为字符串") && e.to_string().contains("upload.retries"), "{}", e);
+    }
+}
@@ -1,37 +1,72 @@
 use std::{fs, io};
 use std::path::Path;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use crate::utils::events::Stage;
+use crate::utils::report::{CopyReport, ErrorMode, FileStatus, ReportCollector};
+use crate::utils::stats::get_directory_stats_sync;
 
-pub fn copy_dir_recursive(src: &Path, dst: &Path, delete_after_copy: bool) -> io::Result<()> {
-    if src.is_file() {
-        // 如果是文件，直接复制
-        fs::copy(src, dst)?;
+pub fn copy_dir_recursive(src: &Path, dst: &Path, delete_after_copy: bool, mode: ErrorMode) -> io::Result<CopyReport> {
+    let (bytes_total, files_total) = if src.is_file() {
+        (fs::metadata(src)?.len(), 1)
+    } else {
+        // 总量只用于进度显示，统计失败的文件会在复制时记录到报告中
+        get_directory_stats_sync(src).unwrap_or((0, 0))
+    };
 
+    let stage = Stage::start("copy", bytes_total, files_total);
+    let collector = ReportCollector::new(mode);
+    let result = copy_dir_inner(src, dst, delete_after_copy, &stage, &collector);
+    let report = collector.finish(result.err());
+    let outcome = if report.success { Ok(()) } else { Err(report.summary()) };
+    stage.finish(&outcome);
+    Ok(report)
+}
+
+// 复制单个文件，移动模式下复制成功后删除源文件
+fn copy_file(src: &Path, dst: &Path, delete_after_copy: bool, stage: &Stage, collector: &ReportCollector) -> io::Result<()> {
+    let name = src.to_string_lossy();
+    let result = fs::copy(src, dst).and_then(|bytes| {
+        stage.file_done(&name, bytes);
         if delete_after_copy {
             fs::remove_file(src)?;
         }
-        return Ok(());
+        Ok((FileStatus::Copied, bytes))
+    });
+    collector.record(&name, true, result)
+}
+
+fn copy_dir_inner(src: &Path, dst: &Path, delete_after_copy: bool, stage: &Stage, collector: &ReportCollector) -> io::Result<()> {
+    if src.is_file() {
+        // 如果是文件，直接复制
+        return copy_file(src, dst, delete_after_copy, stage, collector);
     }
 
     if !dst.exists() {
-        fs::create_dir(dst)?;
+        if let Err(e) = fs::create_dir(dst) {
+            collector.record(&dst.to_string_lossy(), true, Err(e))?;
+            // best-effort 模式下继续复制其他目录，这个目录中的内容记为跳过
+            collector.skip(&src.to_string_lossy(), "目标目录创建失败");
+            return Ok(());
+        }
     }
 
-    let entries: Vec<_> = fs::read_dir(src)?
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()?;
+    let entries = match fs::read_dir(src).and_then(|entries| {
+        entries.map(|res| res.map(|e| e.path())).collect::<Result<Vec<_>, io::Error>>()
+    }) {
+        Ok(entries) => entries,
+        Err(e) => return collector.record(&src.to_string_lossy(), true, Err(e)),
+    };
 
     entries.par_iter().try_for_each(|path| {
         let dst_path = dst.join(path.file_name().unwrap());
 
         if path.is_dir() {
-            copy_dir_recursive(&path, &dst_path, delete_after_copy)
+            copy_dir_inner(path, &dst_path, delete_after_copy, stage, collector)
+        } else if path.is_file() {
+            copy_file(path, &dst_path, delete_after_copy, stage, collector)
         } else {
-            fs::copy(path, &dst_path).map(|_| ())?;
-
-            if delete_after_copy {
-                fs::remove_file(path)?;
-            }
+            // 失效的符号链接、管道等无法复制
+            collector.skip(&path.to_string_lossy(), "不是普通文件");
             Ok(())
         }
     })
@@ -1,8 +1,11 @@
 use std::{fs, io};
-use std::io::BufRead;
 use std::path::{Path, PathBuf};
 use rayon::prelude::*;
-use tracing::{error};
+use tracing::{error, info, warn};
+use crate::utils::events::Stage;
+use crate::utils::report::{CopyReport, ErrorMode, FileStatus, ReportCollector};
+use crate::utils::save_query::{SaveQueryEntry, SaveQueryList};
+use crate::utils::stats::get_directory_stats_sync;
 
 // 复制 db 文件并确保文件长度符合指定要求
 pub fn copy_and_truncate(source_path: &Path, destination_path: &Path, length: u64) -> io::Result<()> {
@@ -19,91 +22,144 @@ pub fn copy_and_truncate(source_path: &Path, destination_path: &Path, length: u6
 }
 
 
-pub fn copy_db(source_world: &Path, destination_world: &Path, db_list_file: &Path) -> io::Result<()> {
-    // 提取 source_world 的文件名部分，例如 "Bedrock level"
-    let source_world_name = match source_world.file_name() {
-        Some(name) => name.to_string_lossy().to_string(),
-        None => {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid source_world path"));
-        }
-    };
+// LevelDB 的表文件写入后不再修改，同名同长度即可视为相同
+fn is_table_file(db_file: &str) -> bool {
+    db_file.ends_with(".ldb") || db_file.ends_with(".sst")
+}
 
-    // 读取 db 文件列表，并动态去掉 source_world 前缀
-    let db_files: Vec<(String, u64)> = {
-        let file = fs::File::open(db_list_file)?;
-        let reader = io::BufReader::new(file);
-
-        // 使用 rayon 的并行读取行
-        reader
-            .lines()
-            .par_bridge() // 并行读取行内容
-            .flat_map(|line_result| {
-                line_result
-                    .map(|line| {
-                        // 拆分行内容，以逗号分隔并进行并行处理
-                        line.split(',')
-                            .filter_map(|db_path| {
-                                let db_path = db_path.trim();
-
-                                // 检查是否以 source_world_name 开头
-                                if db_path.starts_with(&source_world_name) {
-                                    // 去除 source_world_name 和附加的分隔信息
-                                    if let Some(stripped) = db_path.strip_prefix(&source_world_name) {
-                                        // 去掉前导分隔符（如 '/'）
-                                        let stripped = stripped.trim_start_matches(|c| c == '/' || c == '\\');
-                                        // 提取文件路径和长度
-                                        let mut parts = stripped.split(':');
-                                        let file_path = parts.next().unwrap_or(stripped).trim().to_string();
-                                        let length_str = parts.next().unwrap_or("0").trim();
-                                        let length = length_str.parse::<u64>().unwrap_or(0);
-                                        Some((file_path, length))
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect::<Vec<_>>() // 收集结果
-                    })
-                    .unwrap_or_else(|_| vec![]) // 处理错误情况
-            })
-            .collect() // 收集所有 db 文件路径
-    };
+// 基准目录中有同名且长度一致的表文件时，优先 reflink，其次硬链接，都不支持时返回错误由调用方改为复制
+fn link_from_base(base_path: &Path, destination_path: &Path, length: u64) -> io::Result<()> {
+    if fs::metadata(base_path)?.len() != length {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "基准文件长度不一致"));
+    }
+    if destination_path.exists() {
+        fs::remove_file(destination_path)?;
+    }
+    reflink(base_path, destination_path).or_else(|_| fs::hard_link(base_path, destination_path))
+}
 
-    if db_files.is_empty() {
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source_file = fs::File::open(source)?;
+    let destination_file = fs::File::create(destination)?;
+    // SAFETY: 两个文件描述符在调用期间都有效，FICLONE 不会访问用户态内存
+    if unsafe { libc::ioctl(destination_file.as_raw_fd(), libc::FICLONE, source_file.as_raw_fd()) } == 0 {
         return Ok(());
     }
+    let error = io::Error::last_os_error();
+    drop(destination_file);
+    let _ = fs::remove_file(destination);
+    Err(error)
+}
 
-    // 并行复制 db 文件并截断到指定长度
-    db_files.par_iter().for_each(|(db_file, length)| {
-        let source_db_path = source_world.join(db_file);
-        let destination_db_path = destination_world.join(db_file);
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台不支持 reflink"))
+}
 
-        if source_db_path.exists() {
-            if let Some(parent) = destination_db_path.parent() {
-                let _ = fs::create_dir_all(parent);
-            }
-            if let Err(e) = copy_and_truncate(&source_db_path, &destination_db_path, *length) {
-                error!("Error copying file {}: {:?}", source_db_path.display(), e);
-            }
+// 复制单个 db 文件，优先从基准目录链接，否则复制并截断到 save query 给出的长度
+fn copy_db_file(source_world: &Path, destination_world: &Path, base_dir: Option<&Path>, entry: &SaveQueryEntry) -> io::Result<(FileStatus, u64)> {
+    let source_db_path = source_world.join(&entry.path);
+    let destination_db_path = destination_world.join(&entry.path);
+    if let Some(parent) = destination_db_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let base_path = base_dir.filter(|_| is_table_file(&entry.path)).map(|base| base.join(&entry.path));
+    if let Some(Ok(())) = base_path.map(|base_path| link_from_base(&base_path, &destination_db_path, entry.length)) {
+        return Ok((FileStatus::Linked, entry.length));
+    }
+
+    // 目标文件可能是上次链接过来的，先删除再复制，避免改写到基准目录中的文件
+    match fs::remove_file(&destination_db_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let source_length = fs::metadata(&source_db_path)?.len();
+    if source_length < entry.length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("源文件只有 {} 字节，少于 {}", source_length, entry.length),
+        ));
+    }
+    copy_and_truncate(&source_db_path, &destination_db_path, entry.length)?;
+    let status = if source_length > entry.length { FileStatus::Truncated } else { FileStatus::Copied };
+    Ok((status, entry.length))
+}
+
+// base_dir 为上一次备份的暂存目录或解压后的备份，其中未变化的表文件会被直接复用
+pub fn copy_db(
+    source_world: &Path,
+    destination_world: &Path,
+    db_list_file: &Path,
+    base_dir: Option<&Path>,
+    mode: ErrorMode,
+) -> io::Result<CopyReport> {
+    // 列表格式错误时直接失败，不生成损坏的备份
+    let db_files = SaveQueryList::read(source_world, db_list_file)?;
+    if mode == ErrorMode::FailFast {
+        db_files.check_files(source_world)?;
+    }
+    let db_files = db_files.entries;
+
+    // 进度总量为 db 文件的目标长度加上 db 之外的其他文件
+    let (world_size, world_count) = get_directory_stats_sync(source_world)?;
+    let (db_size, db_count) = get_directory_stats_sync(&source_world.join("db")).unwrap_or((0, 0));
+    let bytes_total = db_files.iter().map(|entry| entry.length).sum::<u64>() + world_size.saturating_sub(db_size);
+    let files_total = db_files.len() as u64 + world_count.saturating_sub(db_count);
+    let stage = Stage::start("copy_db", bytes_total, files_total);
+
+    // 基准目录就是目标目录时，删除目标文件会连同基准一起删掉
+    let base_dir = base_dir.filter(|base| {
+        let same = fs::canonicalize(base).ok().is_some_and(|base| fs::canonicalize(destination_world).ok() == Some(base));
+        if same {
+            warn!("基准目录与目标目录相同，忽略基准目录");
+        }
+        !same
+    });
+
+    // 并行复制 db 文件并截断到指定长度，日志和 MANIFEST 每次都重新复制
+    let collector = ReportCollector::new(mode);
+    let result = db_files.par_iter().try_for_each(|entry| {
+        let result = copy_db_file(source_world, destination_world, base_dir, entry);
+        if let Err(e) = &result {
+            error!("Error copying file {}: {:?}", entry.path, e);
         }
+        stage.file_done(&entry.path, entry.length);
+        collector.record(&entry.path, true, result)
     });
 
     // 复制除了 db 文件夹之外的其他文件和文件夹
-    copy_other_files(source_world, destination_world)?;
+    let result = result.and_then(|_| copy_other_files(source_world, destination_world, &stage, &collector));
+    let report = collector.finish(result.err());
+    let outcome = if report.success { Ok(()) } else { Err(report.summary()) };
+    stage.finish(&outcome);
 
-    Ok(())
+    if base_dir.is_some() {
+        info!("复用了 {} 个未变化的表文件（{} 字节），复制了 {} 个文件", report.linked, report.bytes_linked, report.copied + report.truncated);
+    }
+    Ok(report)
+}
+
+// level.dat 缺失的世界无法恢复，其余 db 之外的文件失败不影响整体结果
+pub fn copy_other_files(source: &Path, destination: &Path, stage: &Stage, collector: &ReportCollector) -> io::Result<()> {
+    copy_other_files_inner(source, source, destination, stage, collector)
 }
 
-pub fn copy_other_files(source: &Path, destination: &Path) -> io::Result<()> {
+fn copy_other_files_inner(root: &Path, source: &Path, destination: &Path, stage: &Stage, collector: &ReportCollector) -> io::Result<()> {
     // 遍历 source 目录中的所有内容
-    for entry in fs::read_dir(source)? {
-        let entry = entry?;
-        let path = entry.path();
+    let entries = match fs::read_dir(source) {
+        Ok(entries) => entries,
+        Err(e) => return collector.record(&relative_name(root, source), false, Err(e)),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let name = relative_name(root, &path);
 
         // 跳过 db 文件夹
-        if path.ends_with("db") {
+        if source == root && path.ends_with("db") {
             continue;
         }
 
@@ -111,19 +167,117 @@ pub fn copy_other_files(source: &Path, destination: &Path) -> io::Result<()> {
         let dest_path = build_destination_path(&path, source, destination);
 
         if path.is_dir() {
-            fs::create_dir_all(&dest_path)?; // 创建目标文件夹
-            copy_other_files(&path, &dest_path)?; // 递归复制文件夹内容
+            // 创建目标文件夹后递归复制文件夹内容
+            match fs::create_dir_all(&dest_path) {
+                Ok(()) => copy_other_files_inner(root, &path, &dest_path, stage, collector)?,
+                Err(e) => collector.record(&name, false, Err(e))?,
+            }
+        } else if path.is_file() {
+            let result = fs::copy(&path, &dest_path).map(|bytes| (FileStatus::Copied, bytes));
+            if let Ok((_, bytes)) = &result {
+                stage.file_done(&name, *bytes);
+            }
+            collector.record(&name, name == "level.dat", result)?;
         } else {
-            fs::copy(&path, &dest_path)?; // 复制文件
+            collector.skip(&name, "不是普通文件");
         }
     }
 
     Ok(())
 }
 
+fn relative_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+    if relative.is_empty() { ".".to_string() } else { relative }
+}
+
 pub fn build_destination_path(path: &Path, source: &Path, destination: &Path) -> PathBuf {
     // 计算相对路径
-    let relative_path = path.strip_prefix(source).unwrap_or_else(|_| path);
+    let relative_path = path.strip_prefix(source).unwrap_or(path);
     // 拼接到目标路径
     destination.join(relative_path)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 源世界 w：两个表文件、MANIFEST 和 CURRENT，save query 只给出 000002.ldb 的前 180 字节
+    fn sample_world(root: &Path) -> (PathBuf, PathBuf) {
+        let world = root.join("w");
+        fs::create_dir_all(world.join("db")).unwrap();
+        fs::write(world.join("level.dat"), b"level").unwrap();
+        fs::write(world.join("db").join("000001.ldb"), vec![1u8; 100]).unwrap();
+        fs::write(world.join("db").join("000002.ldb"), vec![2u8; 200]).unwrap();
+        fs::write(world.join("db").join("MANIFEST-000001"), vec![3u8; 50]).unwrap();
+        fs::write(world.join("db").join("CURRENT"), b"MANIFEST-000001\n").unwrap();
+        let list = root.join("db_list.txt");
+        fs::write(&list, "w/db/000001.ldb:100, w/db/000002.ldb:180, w/db/MANIFEST-000001:50, w/db/CURRENT:16").unwrap();
+        (world, list)
+    }
+
+    fn status(report: &CopyReport, path: &str) -> FileStatus {
+        report.files.iter().find(|file| file.path == path).unwrap().status
+    }
+
+    #[test]
+    fn reuses_unchanged_tables_from_base() {
+        let dir = tempfile::tempdir().unwrap();
+        let (world, list) = sample_world(dir.path());
+        let (base, destination) = (dir.path().join("base"), dir.path().join("out"));
+        // 基准中 000001.ldb 长度一致会被复用，000002.ldb 长度不同，MANIFEST 不是表文件，都重新复制
+        fs::create_dir_all(base.join("db")).unwrap();
+        fs::write(base.join("db").join("000001.ldb"), vec![9u8; 100]).unwrap();
+        fs::write(base.join("db").join("000002.ldb"), vec![9u8; 150]).unwrap();
+        fs::write(base.join("db").join("MANIFEST-000001"), vec![9u8; 50]).unwrap();
+
+        let report = copy_db(&world, &destination, &list, Some(&base), ErrorMode::FailFast).unwrap();
+        assert!(report.success, "{}", report.summary());
+        assert_eq!((report.linked, report.bytes_linked), (1, 100));
+        assert_eq!(status(&report, "db/000001.ldb"), FileStatus::Linked);
+        assert_eq!(status(&report, "db/000002.ldb"), FileStatus::Truncated);
+        assert_eq!(status(&report, "db/MANIFEST-000001"), FileStatus::Copied);
+        assert_eq!(fs::read(destination.join("db").join("000001.ldb")).unwrap(), vec![9u8; 100]);
+        assert_eq!(fs::read(destination.join("db").join("000002.ldb")).unwrap(), vec![2u8; 180]);
+        assert_eq!(fs::read(destination.join("db").join("MANIFEST-000001")).unwrap(), vec![3u8; 50]);
+        assert_eq!(fs::read(destination.join("level.dat")).unwrap(), b"level");
+
+        // 不用基准再复制一次时先删除链接过来的文件，不会改写基准目录
+        let report = copy_db(&world, &destination, &list, None, ErrorMode::FailFast).unwrap();
+        assert_eq!(report.linked, 0);
+        assert_eq!(fs::read(destination.join("db").join("000001.ldb")).unwrap(), vec![1u8; 100]);
+        assert_eq!(fs::read(base.join("db").join("000001.ldb")).unwrap(), vec![9u8; 100]);
+    }
+
+    #[test]
+    fn error_mode_controls_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (world, list) = sample_world(dir.path());
+        fs::remove_file(world.join("db").join("000002.ldb")).unwrap();
+
+        // fail-fast 在复制前检查列表，什么都不写入
+        let e = copy_db(&world, &dir.path().join("fast"), &list, None, ErrorMode::FailFast).unwrap_err();
+        assert!(e.to_string().contains("db/000002.ldb 不存在"), "{}", e);
+        assert!(!dir.path().join("fast").join("level.dat").exists());
+
+        // best-effort 复制其余文件，缺少必需的 db 文件时结果为失败
+        let destination = dir.path().join("best");
+        let report = copy_db(&world, &destination, &list, None, ErrorMode::BestEffort).unwrap();
+        assert!(!report.success);
+        assert_eq!((report.copied, report.failed), (4, 1));
+        assert_eq!(status(&report, "db/000002.ldb"), FileStatus::Failed);
+        assert!(destination.join("db").join("000001.ldb").exists() && destination.join("level.dat").exists());
+    }
+
+    #[test]
+    fn ignores_base_equal_to_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let (world, list) = sample_world(dir.path());
+        let destination = dir.path().join("out");
+        copy_db(&world, &destination, &list, None, ErrorMode::FailFast).unwrap();
+
+        let report = copy_db(&world, &destination, &list, Some(&destination), ErrorMode::FailFast).unwrap();
+        assert!(report.success, "{}", report.summary());
+        assert_eq!(report.linked, 0);
+        assert_eq!(fs::read(destination.join("db").join("000001.ldb")).unwrap(), vec![1u8; 100]);
+    }
+}
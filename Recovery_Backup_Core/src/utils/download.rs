@@ -0,0 +1,269 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use futures::StreamExt;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Serialize;
+use tracing::{info, warn};
+use crate::utils::crypto::{decrypt_data, decrypt_file, encrypted_size, is_encrypted_data, EncryptionKey, ENCRYPTED_SUFFIX};
+use crate::utils::events::Stage;
+use crate::utils::manifest::{manifest_path_for, BackupManifest, MANIFEST_SUFFIX};
+use crate::utils::storage::StorageBackend;
+use crate::utils::upload::{with_retry, RetryPolicy, UploadError};
+
+// 未下载完的文件后缀，再次下载时从已有的长度继续
+pub const PARTIAL_SUFFIX: &str = ".part";
+
+#[derive(Serialize, Debug)]
+pub struct DownloadSummary {
+    // 下载后的本地路径
+    pub archive: String,
+    pub size: u64,
+    // 从 .part 文件已有的长度继续下载，0 表示从头开始
+    pub resumed_from: u64,
+    // 本地已有相同的备份，没有重新下载
+    pub reused: bool,
+    // 是否按清单检查了大小和哈希，没有清单或加密的备份没有密钥时只检查大小
+    pub manifest: bool,
+    // 加密的备份是否已经解密，archive 为解密后的文件
+    pub decrypted: bool,
+    pub attempts: u32,
+}
+
+fn partial_path_for(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(PARTIAL_SUFFIX);
+    PathBuf::from(name)
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// 下载远程清单，不存在时返回 None；加密备份的清单也是加密的，需要 encryption 解密
+pub(crate) async fn fetch_manifest(backend: &dyn StorageBackend, key: &str, encryption: Option<&EncryptionKey>) -> Result<Option<BackupManifest>, UploadError> {
+    let Some(data) = backend.read(key).await? else {
+        return Ok(None);
+    };
+    let data = match encryption {
+        _ if !is_encrypted_data(&data) => data,
+        Some(encryption) => decrypt_data(&data, encryption, &backend.location(key))?,
+        None => return Err(invalid(format!("远程清单 {} 已加密，需要提供密钥", backend.location(key))).into()),
+    };
+    Ok(Some(serde_json::from_slice(&data).map_err(|e| invalid(format!("远程清单 {} 无效: {}", backend.location(key), e)))?))
+}
+
+// 把 request 返回的 GET 请求下载到 part，每次尝试都用 Range 从 part 当前的长度继续
+pub(crate) async fn fetch_resumable<F>(url: &str, part: &Path, retry: &RetryPolicy, stage: &Stage, request: F) -> Result<u32, UploadError>
+where
+    F: Fn() -> Result<RequestBuilder, UploadError>,
+{
+    let (_, attempts) = with_retry(retry, url, || async {
+        let offset = fs::metadata(part).map_or(0, |metadata| metadata.len());
+        let mut request = request()?;
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let res = request.send().await.map_err(|e| UploadError::network(url, e))?;
+
+        let mut file = match res.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                let start = res
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("bytes ")?.split('-').next()?.parse::<u64>().ok());
+                if start != Some(offset) {
+                    return Err(invalid(format!("服务器返回的范围与请求的起点 {} 不一致", offset)).into());
+                }
+                OpenOptions::new().append(true).open(part)?
+            }
+            // 已经下载完整，由调用方检查大小
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+            status if status.is_success() => {
+                if offset > 0 {
+                    warn!("服务器不支持 Range 请求，从头开始下载");
+                    stage.rewind_bytes(offset);
+                }
+                File::create(part)?
+            }
+            _ => return Err(UploadError::status(url, res).await),
+        };
+
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| UploadError::network(url, e))?;
+            file.write_all(&chunk)?;
+            stage.add_bytes(chunk.len() as u64);
+        }
+        file.sync_all()?;
+        Ok(())
+    })
+    .await?;
+    Ok(attempts)
+}
+
+// 下载远程备份和它的清单到 destination_dir，中断后再次运行会继续下载，完成后检查大小和清单中的哈希。
+// 加密的备份 (.enc) 在提供 key 时解密为去掉 .enc 的文件，它的清单描述的是解密后的压缩包
+pub async fn download_backup(backend: &dyn StorageBackend, name: &str, destination_dir: &Path, key: Option<&EncryptionKey>) -> Result<DownloadSummary, UploadError> {
+    let file_name = name.rsplit('/').find(|part| !part.is_empty()).ok_or_else(|| invalid("备份名称为空"))?;
+    let mut archive = destination_dir.join(file_name);
+    let part = partial_path_for(&archive);
+    fs::create_dir_all(destination_dir)?;
+    let encrypted = file_name.len() > ENCRYPTED_SUFFIX.len() && file_name.ends_with(ENCRYPTED_SUFFIX);
+    // 校验和使用的本地明文文件，加密的备份没有密钥时为 None
+    let plain = match (encrypted, key) {
+        (false, _) => Some(archive.clone()),
+        (true, Some(_)) => Some(destination_dir.join(&file_name[..file_name.len() - ENCRYPTED_SUFFIX.len()])),
+        (true, None) => {
+            warn!("没有提供密钥，{} 下载后不解密", name);
+            None
+        }
+    };
+
+    // 加密备份的清单也是加密的，没有密钥时无法读取
+    let manifest = match plain {
+        Some(_) => fetch_manifest(backend, &format!("{}{}", name, MANIFEST_SUFFIX), key).await?,
+        None => None,
+    };
+    let expected_size = match &manifest {
+        Some(manifest) if encrypted => Some(encrypted_size(manifest.archive.size)),
+        Some(manifest) => Some(manifest.archive.size),
+        None => {
+            if plain.is_some() {
+                warn!("远程没有 {} 的清单，只检查文件大小", name);
+            }
+            let entry = backend.stat(name).await?;
+            let entry = entry.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("远程没有 {}", backend.location(name))))?;
+            entry.size
+        }
+    };
+
+    // 本地已有通过清单校验的同一备份时直接使用
+    if let (Some(manifest), Some(plain)) = (&manifest, &plain) {
+        if plain.is_file() && manifest.check_archive(plain).is_ok() {
+            info!("本地已有相同的备份 {}，跳过下载", plain.display());
+            manifest.write(plain)?;
+            let size = manifest.archive.size;
+            return Ok(DownloadSummary { archive: plain.to_string_lossy().into_owned(), size, resumed_from: 0, reused: true, manifest: true, decrypted: encrypted, attempts: 0 });
+        }
+    }
+
+    let resumed_from = fs::metadata(&part).map_or(0, |metadata| metadata.len());
+    if resumed_from > 0 {
+        info!("从 {} 字节处继续下载 {}", resumed_from, backend.location(name));
+    }
+    let stage = Stage::start("download", expected_size.unwrap_or(0), 1);
+    stage.add_bytes(resumed_from);
+    let result = backend.get(name, &part, &stage).await;
+    if result.is_ok() {
+        stage.file_done(file_name, 0);
+    }
+    stage.finish(&result);
+    let attempts = result?;
+
+    let size = fs::metadata(&part)?.len();
+    if let Some(expected) = expected_size {
+        if size != expected {
+            // 已有的部分不可信，下次从头下载
+            fs::remove_file(&part)?;
+            return Err(invalid(format!("下载的文件大小 {} 与预期的 {} 不一致", size, expected)).into());
+        }
+    }
+    fs::rename(&part, &archive)?;
+
+    let decrypted = match (key, &plain) {
+        (Some(key), Some(plain)) if encrypted => {
+            // 密钥错误时保留下载的加密文件
+            let temp = partial_path_for(plain);
+            decrypt_file(&archive, &temp, key)?;
+            fs::rename(&temp, plain)?;
+            fs::remove_file(&archive)?;
+            archive = plain.clone();
+            true
+        }
+        _ => false,
+    };
+    let size = fs::metadata(&archive)?.len();
+
+    if let Some(manifest) = &manifest {
+        if let Err(e) = manifest.check_archive(&archive) {
+            fs::remove_file(&archive)?;
+            return Err(invalid(format!("下载的备份未通过清单校验: {}", e)).into());
+        }
+        manifest.write(&archive)?;
+    } else if manifest_path_for(&archive).exists() {
+        // 本地残留的旧清单与新下载的文件无关
+        fs::remove_file(manifest_path_for(&archive))?;
+    }
+    info!("备份已下载到 {}", archive.display());
+    Ok(DownloadSummary { archive: archive.to_string_lossy().into_owned(), size, resumed_from, reused: false, manifest: manifest.is_some(), decrypted, attempts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // 只应答一次的 HTTP 服务器，返回地址和收到的请求头
+    fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/w_1.zip", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            while reader.read_line(&mut request).unwrap() > 2 && !request.ends_with("\r\n\r\n") {}
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    async fn fetch(url: &str, part: &Path) -> Result<u32, UploadError> {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let retry = RetryPolicy { max_attempts: 1, ..RetryPolicy::default() };
+        fetch_resumable(url, part, &retry, &Stage::start("download", 0, 0), || Ok(client.get(url))).await
+    }
+
+    #[tokio::test]
+    async fn resumes_from_partial_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("w_1.zip.part");
+        fs::write(&part, b"01234").unwrap();
+        let (url, server) = serve_once("HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 5-9/10\r\nContent-Length: 5\r\nConnection: close\r\n\r\n56789");
+
+        assert_eq!(fetch(&url, &part).await.unwrap(), 1);
+        assert!(server.join().unwrap().to_ascii_lowercase().contains("range: bytes=5-"));
+        assert_eq!(fs::read(&part).unwrap(), b"0123456789");
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_content_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("w_1.zip.part");
+        fs::write(&part, b"01234").unwrap();
+        // 服务器从 3 开始返回，追加后文件会错位，必须拒绝且不改动已下载的部分
+        let (url, server) = serve_once("HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 3-9/10\r\nContent-Length: 7\r\nConnection: close\r\n\r\n3456789");
+
+        let e = fetch(&url, &part).await.unwrap_err();
+        server.join().unwrap();
+        assert!(e.to_string().contains("不一致"), "{}", e);
+        assert_eq!(fs::read(&part).unwrap(), b"01234");
+    }
+
+    #[tokio::test]
+    async fn restarts_when_range_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("w_1.zip.part");
+        fs::write(&part, b"xxxxx").unwrap();
+        let (url, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n0123456789");
+
+        fetch(&url, &part).await.unwrap();
+        server.join().unwrap();
+        assert_eq!(fs::read(&part).unwrap(), b"0123456789");
+    }
+}
@@ -0,0 +1,211 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+// 两次进度事件之间的最短间隔，避免大量小文件时刷屏
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+static EVENTS_ENABLED: OnceLock<bool> = OnceLock::new();
+static STDOUT_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventMode {
+    // 只输出日志
+    None,
+    // 每行一个 JSON 事件写到 stdout
+    Ndjson,
+}
+
+impl EventMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Self::None),
+            "ndjson" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    StageStarted {
+        stage: &'a str,
+        bytes_total: u64,
+        files_total: u64,
+    },
+    Progress {
+        stage: &'a str,
+        bytes_done: u64,
+        bytes_total: u64,
+        files_done: u64,
+        files_total: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        current_file: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta_ms: Option<u64>,
+    },
+    StageFinished {
+        stage: &'a str,
+        success: bool,
+        duration_ms: u128,
+        bytes_done: u64,
+        files_done: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Result {
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<serde_json::Value>,
+    },
+}
+
+// 只能在启动时调用一次
+pub fn init_events(mode: EventMode) {
+    let _ = EVENTS_ENABLED.set(mode == EventMode::Ndjson);
+}
+
+pub fn events_enabled() -> bool {
+    *EVENTS_ENABLED.get().unwrap_or(&false)
+}
+
+pub fn emit(event: &Event) {
+    if !events_enabled() {
+        return;
+    }
+    let line = match serde_json::to_string(event) {
+        Ok(line) => line,
+        Err(_) => return,
+    };
+    let _guard = STDOUT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+// 输出操作的最终结果，events 模式下为 result 事件，否则打印格式化的 JSON
+pub fn emit_result<T: Serialize>(success: bool, error: Option<String>, data: Option<&T>) {
+    let data = data.and_then(|data| serde_json::to_value(data).ok());
+    if events_enabled() {
+        emit(&Event::Result { success, error, data });
+    } else if let Some(data) = data {
+        println!("{}", serde_json::to_string_pretty(&data).unwrap());
+    }
+}
+
+// request 模式的应答，events 模式下为 result 事件，否则在一行中输出包含 success 的完整 JSON 对象
+pub fn emit_reply(success: bool, error: Option<String>, data: Option<serde_json::Value>) {
+    if events_enabled() {
+        emit(&Event::Result { success, error, data });
+        return;
+    }
+    let reply = serde_json::json!({ "success": success, "error": error, "data": data });
+    let _guard = STDOUT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", reply);
+    let _ = stdout.flush();
+}
+
+// 一个长时间运行的阶段，负责输出开始、进度和结束事件，克隆后可在多个线程或异步流中共享
+#[derive(Clone)]
+pub struct Stage(Arc<StageState>);
+
+struct StageState {
+    name: &'static str,
+    start: Instant,
+    bytes_total: AtomicU64,
+    files_total: AtomicU64,
+    bytes_done: AtomicU64,
+    files_done: AtomicU64,
+    last_emit: Mutex<Instant>,
+}
+
+impl Stage {
+    pub fn start(name: &'static str, bytes_total: u64, files_total: u64) -> Self {
+        emit(&Event::StageStarted { stage: name, bytes_total, files_total });
+        let start = Instant::now();
+        Stage(Arc::new(StageState {
+            name,
+            start,
+            bytes_total: AtomicU64::new(bytes_total),
+            files_total: AtomicU64::new(files_total),
+            bytes_done: AtomicU64::new(0),
+            files_done: AtomicU64::new(0),
+            last_emit: Mutex::new(start),
+        }))
+    }
+
+    // 总量在开始时未知的阶段（如流式解压）可以之后补上
+    pub fn set_totals(&self, bytes_total: u64, files_total: u64) {
+        self.0.bytes_total.store(bytes_total, Ordering::Relaxed);
+        self.0.files_total.store(files_total, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.0.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.report(None, false);
+    }
+
+    // 重试前撤回上一次尝试已计入的字节数
+    pub fn rewind_bytes(&self, bytes: u64) {
+        let _ = self.0.bytes_done.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |done| Some(done.saturating_sub(bytes)));
+    }
+
+    pub fn file_done(&self, current_file: &str, bytes: u64) {
+        self.0.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.0.files_done.fetch_add(1, Ordering::Relaxed);
+        self.report(Some(current_file), false);
+    }
+
+    fn report(&self, current_file: Option<&str>, force: bool) {
+        if !events_enabled() {
+            return;
+        }
+        {
+            let mut last_emit = match self.0.last_emit.try_lock() {
+                Ok(last_emit) => last_emit,
+                Err(_) if !force => return,
+                Err(_) => self.0.last_emit.lock().unwrap_or_else(|e| e.into_inner()),
+            };
+            if !force && last_emit.elapsed() < PROGRESS_INTERVAL {
+                return;
+            }
+            *last_emit = Instant::now();
+        }
+
+        let bytes_done = self.0.bytes_done.load(Ordering::Relaxed);
+        let bytes_total = self.0.bytes_total.load(Ordering::Relaxed);
+        let eta_ms = if bytes_done > 0 && bytes_total > bytes_done {
+            let elapsed = self.0.start.elapsed().as_millis() as f64;
+            Some((elapsed * (bytes_total - bytes_done) as f64 / bytes_done as f64) as u64)
+        } else {
+            None
+        };
+        emit(&Event::Progress {
+            stage: self.0.name,
+            bytes_done,
+            bytes_total,
+            files_done: self.0.files_done.load(Ordering::Relaxed),
+            files_total: self.0.files_total.load(Ordering::Relaxed),
+            current_file,
+            eta_ms,
+        });
+    }
+
+    pub fn finish<T, E: std::fmt::Display>(self, result: &Result<T, E>) {
+        self.report(None, true);
+        emit(&Event::StageFinished {
+            stage: self.0.name,
+            success: result.is_ok(),
+            duration_ms: self.0.start.elapsed().as_millis(),
+            bytes_done: self.0.bytes_done.load(Ordering::Relaxed),
+            files_done: self.0.files_done.load(Ordering::Relaxed),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+}
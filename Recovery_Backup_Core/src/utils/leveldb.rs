@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::io;
+
+// MANIFEST 使用 LevelDB 的日志格式，按 32 KiB 分块，每条记录带 7 字节头
+const BLOCK_SIZE: usize = 32 * 1024;
+const HEADER_SIZE: usize = 7;
+
+const RECORD_ZERO: u8 = 0;
+const RECORD_FULL: u8 = 1;
+const RECORD_FIRST: u8 = 2;
+const RECORD_MIDDLE: u8 = 3;
+const RECORD_LAST: u8 = 4;
+
+// VersionEdit 中的字段标记
+const TAG_COMPARATOR: u64 = 1;
+const TAG_LOG_NUMBER: u64 = 2;
+const TAG_NEXT_FILE_NUMBER: u64 = 3;
+const TAG_LAST_SEQUENCE: u64 = 4;
+const TAG_COMPACT_POINTER: u64 = 5;
+const TAG_DELETED_FILE: u64 = 6;
+const TAG_NEW_FILE: u64 = 7;
+const TAG_PREV_LOG_NUMBER: u64 = 9;
+
+// 当前版本中仍在使用的表文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableFile {
+    pub number: u64,
+    pub size: u64,
+}
+
+impl TableFile {
+    // 新版 LevelDB 使用 .ldb，旧版使用 .sst，两者都可能出现
+    pub fn file_names(&self) -> [String; 2] {
+        [format!("{:06}.ldb", self.number), format!("{:06}.sst", self.number)]
+    }
+}
+
+// 从 CURRENT 文件的内容中取出当前 MANIFEST 的文件名
+pub fn current_manifest_name(current: &[u8]) -> io::Result<String> {
+    let name = String::from_utf8_lossy(current).trim_end_matches(['\r', '\n']).to_string();
+    if !name.starts_with("MANIFEST-") || name.contains(['/', '\\']) {
+        return Err(invalid(format!("CURRENT 内容无效: {}", name)));
+    }
+    Ok(name)
+}
+
+// 依次应用 MANIFEST 中的所有 VersionEdit，返回最终仍然存活的表文件
+pub fn live_tables(manifest: &[u8]) -> io::Result<Vec<TableFile>> {
+    let mut tables = BTreeMap::new();
+    for record in read_records(manifest)? {
+        apply_edit(&record, &mut tables)?;
+    }
+    Ok(tables.into_iter().map(|((_, number), size)| TableFile { number, size }).collect())
+}
+
+fn read_records(data: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut records = Vec::new();
+    let mut pending: Option<Vec<u8>> = None;
+    let mut pos = 0;
+
+    while pos < data.len() {
+        // 块尾不足一个记录头的部分是填充
+        let block_left = BLOCK_SIZE - pos % BLOCK_SIZE;
+        if block_left < HEADER_SIZE {
+            pos += block_left;
+            continue;
+        }
+        if data.len() - pos < HEADER_SIZE {
+            break;
+        }
+
+        let length = u16::from_le_bytes([data[pos + 4], data[pos + 5]]) as usize;
+        let kind = data[pos + 6];
+        let start = pos + HEADER_SIZE;
+        if kind == RECORD_ZERO && length == 0 {
+            // 预分配的空白区域，跳到下一个块
+            pos += block_left;
+            continue;
+        }
+        if length > block_left - HEADER_SIZE {
+            return Err(invalid(format!("MANIFEST 记录跨越了块边界，偏移 {}", pos)));
+        }
+        // 写入被中断时最后一条记录可能不完整，LevelDB 自身也会忽略它
+        if start + length > data.len() {
+            break;
+        }
+        let payload = &data[start..start + length];
+        pos = start + length;
+
+        match kind {
+            RECORD_FULL => records.push(payload.to_vec()),
+            RECORD_FIRST => pending = Some(payload.to_vec()),
+            RECORD_MIDDLE | RECORD_LAST => {
+                let Some(buffer) = pending.as_mut() else {
+                    return Err(invalid(format!("MANIFEST 记录缺少开头，偏移 {}", pos)));
+                };
+                buffer.extend_from_slice(payload);
+                if kind == RECORD_LAST {
+                    records.push(pending.take().unwrap());
+                }
+            }
+            other => return Err(invalid(format!("MANIFEST 记录类型未知: {}", other))),
+        }
+    }
+    Ok(records)
+}
+
+fn apply_edit(record: &[u8], tables: &mut BTreeMap<(u64, u64), u64>) -> io::Result<()> {
+    let mut input = record;
+    while !input.is_empty() {
+        match read_varint(&mut input)? {
+            TAG_COMPARATOR => {
+                read_slice(&mut input)?;
+            }
+            TAG_LOG_NUMBER | TAG_NEXT_FILE_NUMBER | TAG_LAST_SEQUENCE | TAG_PREV_LOG_NUMBER => {
+                read_varint(&mut input)?;
+            }
+            TAG_COMPACT_POINTER => {
+                read_varint(&mut input)?;
+                read_slice(&mut input)?;
+            }
+            TAG_DELETED_FILE => {
+                let level = read_varint(&mut input)?;
+                let number = read_varint(&mut input)?;
+                tables.remove(&(level, number));
+            }
+            TAG_NEW_FILE => {
+                let level = read_varint(&mut input)?;
+                let number = read_varint(&mut input)?;
+                let size = read_varint(&mut input)?;
+                read_slice(&mut input)?;
+                read_slice(&mut input)?;
+                tables.insert((level, number), size);
+            }
+            tag => return Err(invalid(format!("VersionEdit 字段未知: {}", tag))),
+        }
+    }
+    Ok(())
+}
+
+fn read_varint(input: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first().ok_or_else(|| invalid("VersionEdit 被截断"))?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("VersionEdit 中的整数过长"))
+}
+
+fn read_slice<'a>(input: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let length = read_varint(input)? as usize;
+    if input.len() < length {
+        return Err(invalid("VersionEdit 被截断"));
+    }
+    let (slice, rest) = input.split_at(length);
+    *input = rest;
+    Ok(slice)
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 日志记录: 校验和 (读取时不检查)、长度、类型、内容
+    fn record(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 0];
+        data.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        data.push(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn reads_current_manifest_name() {
+        assert_eq!(current_manifest_name(b"MANIFEST-000012\n").unwrap(), "MANIFEST-000012");
+        assert!(current_manifest_name(b"../MANIFEST-000012\n").is_err());
+        assert!(current_manifest_name(b"000012.log\n").is_err());
+    }
+
+    #[test]
+    fn applies_version_edits() {
+        // 比较器、日志号 2、下一个文件号 10、最后序号 100，新增 0 层的 5 号 (1000 字节) 和 1 层的 7 号 (300 字节)
+        let mut first = vec![1, 26];
+        first.extend_from_slice(b"leveldb.BytewiseComparator");
+        first.extend_from_slice(&[2, 2, 3, 10, 4, 100]);
+        first.extend_from_slice(&[7, 0, 5, 0xe8, 0x07, 1, b'a', 1, b'b']);
+        first.extend_from_slice(&[7, 1, 7, 0xac, 0x02, 1, b'a', 1, b'z']);
+        // 删除 0 层的 5 号，新增 1 层的 9 号 (200 字节)
+        let second = [6, 0, 5, 7, 1, 9, 0xc8, 0x01, 1, b'c', 1, b'd'];
+
+        let mut manifest = record(RECORD_FULL, &first);
+        manifest.extend(record(RECORD_FIRST, &second[..4]));
+        manifest.extend(record(RECORD_MIDDLE, &second[4..8]));
+        manifest.extend(record(RECORD_LAST, &second[8..]));
+        assert_eq!(live_tables(&manifest).unwrap(), vec![TableFile { number: 7, size: 300 }, TableFile { number: 9, size: 200 }]);
+
+        // 最后一条记录不完整时忽略它
+        manifest.extend(&record(RECORD_FULL, &[6, 1, 7])[..8]);
+        assert_eq!(live_tables(&manifest).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert_eq!(live_tables(&record(RECORD_FULL, &[8, 1])).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(live_tables(&record(RECORD_LAST, &[2, 2])).is_err());
+    }
+}
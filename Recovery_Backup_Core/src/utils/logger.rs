@@ -1,5 +1,6 @@
 use std::fmt;
 use std::fs::create_dir_all;
+use std::io::IsTerminal;
 use chrono::{Local};
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter};
 use tracing_subscriber::fmt::time::FormatTime;
@@ -14,14 +15,16 @@ impl FormatTime for CustomTime {
     }
 }
 
+// 控制台日志总是写到 stderr，stdout 只输出 JSON 结果和事件
 pub fn init_logger() {
     let logs_dir = "logs/BackupJS";
     create_dir_all(logs_dir).expect("Unable to create logs directory");
     // 控制台层
     let console_layer = tracing_subscriber::fmt::layer()
         .with_timer(CustomTime) // 使用启动时间计时器
-        .with_ansi(true)         // 控制台输出时使用 ANSI 转义字符
-        .with_target(true);      // 显示目标模块
+        .with_ansi(std::io::stderr().is_terminal()) // 输出到终端时使用 ANSI 转义字符
+        .with_target(true)      // 显示目标模块
+        .with_writer(std::io::stderr);
 
     // 文件层 - 按日期记录日志
     let file_layer = tracing_subscriber::fmt::layer()
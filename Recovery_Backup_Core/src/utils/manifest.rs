@@ -0,0 +1,201 @@
+use std::{fs, io};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::utils::archive::collect_entries;
+
+pub const MANIFEST_VERSION: u32 = 1;
+// 清单文件与压缩包放在同一目录，文件名为 "<压缩包>.manifest.json"
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+// 备份的触发方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    #[default]
+    Manual,
+    Scheduled,
+    Permanent,
+}
+
+// save query 给出的 db 文件及其有效长度
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DbFileEntry {
+    pub path: String,
+    pub length: u64,
+}
+
+// 压缩包内每个文件的大小和 BLAKE3 哈希
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileEntry {
+    pub path: String,
+    pub size: u64,
+    pub blake3: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchiveInfo {
+    pub name: String,
+    pub format: String,
+    pub size: u64,
+    pub blake3: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub tool_version: String,
+    pub world_name: String,
+    pub created_at: DateTime<Local>,
+    pub trigger: Trigger,
+    pub db_files: Vec<DbFileEntry>,
+    pub files: Vec<FileEntry>,
+    pub source_size: u64,
+    pub archive: ArchiveInfo,
+}
+
+pub fn manifest_path_for(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(MANIFEST_SUFFIX);
+    PathBuf::from(name)
+}
+
+pub fn is_manifest_path(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(MANIFEST_SUFFIX))
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+// 并行计算目录下所有文件的哈希，路径使用 '/' 分隔并按名称排序
+pub fn hash_tree(root: &Path) -> io::Result<Vec<FileEntry>> {
+    collect_entries(root)?
+        .par_iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| {
+            Ok(FileEntry {
+                path: entry.name.clone(),
+                size: entry.size,
+                blake3: hash_file(&entry.path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", entry.name, e)))?,
+            })
+        })
+        .collect()
+}
+
+impl BackupManifest {
+    // 根据暂存目录和已经生成的压缩包创建清单
+    pub fn create(
+        world_name: &str,
+        trigger: Trigger,
+        db_files: Vec<DbFileEntry>,
+        staging_dir: &Path,
+        archive: &Path,
+        format: &str,
+    ) -> io::Result<Self> {
+        let files = hash_tree(staging_dir)?;
+        Ok(BackupManifest {
+            version: MANIFEST_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            world_name: world_name.to_string(),
+            created_at: Local::now(),
+            trigger,
+            db_files,
+            source_size: files.iter().map(|file| file.size).sum(),
+            files,
+            archive: ArchiveInfo {
+                name: archive.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+                format: format.to_string(),
+                size: fs::metadata(archive)?.len(),
+                blake3: hash_file(archive)?,
+            },
+        })
+    }
+
+    pub fn write(&self, archive: &Path) -> io::Result<PathBuf> {
+        let path = manifest_path_for(archive);
+        let data = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    // 读取压缩包旁的清单，没有清单时返回 None
+    pub fn read(archive: &Path) -> io::Result<Option<Self>> {
+        let path = manifest_path_for(archive);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_str(&data)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("清单 {} 无效: {}", path.display(), e)))
+    }
+
+    // 检查压缩包的大小和哈希是否与清单一致
+    pub fn check_archive(&self, archive: &Path) -> io::Result<()> {
+        let size = fs::metadata(archive)?.len();
+        if size != self.archive.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("压缩包大小与清单不一致: {} != {}", size, self.archive.size),
+            ));
+        }
+        let hash = hash_file(archive)?;
+        if hash != self.archive.blake3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "压缩包哈希与清单不一致"));
+        }
+        Ok(())
+    }
+}
+
+// 找出目录下所有带清单的压缩包，清单损坏或压缩包不存在的跳过
+pub fn list_manifests(dir: &Path) -> io::Result<Vec<(PathBuf, BackupManifest)>> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !is_manifest_path(&path) {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy();
+        let archive = path.with_file_name(name.trim_end_matches(MANIFEST_SUFFIX));
+        if !archive.is_file() {
+            continue;
+        }
+        if let Ok(Some(manifest)) = BackupManifest::read(&archive) {
+            backups.push((archive, manifest));
+        }
+    }
+    backups.sort_by_key(|(_, manifest)| manifest.created_at);
+    Ok(backups)
+}
+
+// stats 输出中的最近一次备份
+#[derive(Serialize)]
+pub struct LatestBackup {
+    pub archive: String,
+    pub world_name: String,
+    pub created_at: DateTime<Local>,
+    pub trigger: Trigger,
+    pub size: u64,
+    pub source_size: u64,
+}
+
+pub fn latest_backup(dirs: &[&Path]) -> Option<LatestBackup> {
+    dirs.iter()
+        .filter_map(|dir| list_manifests(dir).ok())
+        .flatten()
+        .max_by_key(|(_, manifest)| manifest.created_at)
+        .map(|(archive, manifest)| LatestBackup {
+            archive: archive.to_string_lossy().into_owned(),
+            world_name: manifest.world_name,
+            created_at: manifest.created_at,
+            trigger: manifest.trigger,
+            size: manifest.archive.size,
+            source_size: manifest.source_size,
+        })
+}
@@ -1,8 +1,29 @@
 pub mod logger;
+pub mod config;
+pub mod events;
 pub mod upload;
+pub mod chunked;
+pub mod storage;
+pub mod webdav;
+pub mod s3;
+pub mod sftp;
+pub mod download;
+pub mod save_query;
+pub mod report;
 pub mod copy_db;
 pub mod copy;
+pub mod archive;
+pub mod backup;
+pub mod manifest;
+pub mod crypto;
+pub mod leveldb;
+pub mod verify;
 pub mod cleanup;
+pub mod retention;
+pub mod remote_retention;
+pub mod repository;
 pub mod stats;
 pub mod recover;
+pub mod process;
+#[allow(clippy::module_inception)]
 pub mod utils;
\ No newline at end of file
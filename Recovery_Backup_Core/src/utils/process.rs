@@ -0,0 +1,364 @@
+use std::{fs, io, thread};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// 服务器启动命令，例如 "LD_LIBRARY_PATH=. ./bedrock_server"
+pub struct LaunchCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub working_dir: PathBuf,
+    pub env: Vec<(String, String)>,
+}
+
+impl LaunchCommand {
+    pub fn new(program: &Path, working_dir: &Path) -> Self {
+        LaunchCommand {
+            program: program.to_string_lossy().into_owned(),
+            args: Vec::new(),
+            working_dir: working_dir.to_path_buf(),
+            env: Vec::new(),
+        }
+    }
+
+    // 解析一行命令，开头的 NAME=VALUE 作为环境变量，支持单双引号
+    pub fn parse(command_line: &str, working_dir: &Path) -> io::Result<Self> {
+        let mut words = split_command_line(command_line)?.into_iter().peekable();
+        let mut env = Vec::new();
+        while let Some((name, value)) = words.peek().and_then(|word| parse_env_assignment(word)) {
+            env.push((name, value));
+            words.next();
+        }
+
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("启动命令中没有可执行文件: {}", command_line)))?;
+        Ok(LaunchCommand { program, args: words.collect(), working_dir: working_dir.to_path_buf(), env })
+    }
+
+    pub fn spawn(&self) -> io::Result<Child> {
+        // 相对路径的程序按工作目录解析，与在服务器目录下执行命令的效果一致
+        let program = if self.program.contains(['/', '\\']) && Path::new(&self.program).is_relative() {
+            self.working_dir.join(&self.program)
+        } else {
+            PathBuf::from(&self.program)
+        };
+        Command::new(program)
+            .args(&self.args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .current_dir(&self.working_dir)
+            .spawn()
+    }
+}
+
+fn parse_env_assignment(word: &str) -> Option<(String, String)> {
+    let (name, value) = word.split_once('=')?;
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| (name.to_string(), value.to_string()))
+}
+
+fn split_command_line(command_line: &str) -> io::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+
+    for c in command_line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("启动命令引号不匹配: {}", command_line)));
+    }
+    if in_word {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+// 负责查找、停止和重新启动服务器进程
+pub struct ServerController {
+    // 服务器可执行文件的完整路径，用于匹配正在运行的进程
+    pub executable: PathBuf,
+    // 服务器写入的 pid 文件，存在时优先使用
+    pub pidfile: Option<PathBuf>,
+    // 发送 SIGTERM 后等待退出的时间，超时后强制结束
+    pub grace_period: Duration,
+    pub launch: LaunchCommand,
+}
+
+impl ServerController {
+    pub fn new(executable: PathBuf, working_dir: &Path) -> Self {
+        let launch = LaunchCommand::new(&executable, working_dir);
+        ServerController { executable, pidfile: None, grace_period: Duration::from_secs(10), launch }
+    }
+
+    pub fn find_pids(&self) -> io::Result<Vec<u32>> {
+        if let Some(pidfile) = &self.pidfile {
+            if let Some(pid) = read_pidfile(pidfile)? {
+                if platform::is_running(pid) {
+                    return Ok(vec![pid]);
+                }
+                warn!("pid 文件中的进程 {} 已不存在", pid);
+            }
+        }
+        platform::find_pids(&self.executable, &self.launch.working_dir)
+    }
+
+    // 停止所有匹配的服务器进程，先尝试正常退出，超过等待时间后强制结束
+    pub fn stop(&self) -> io::Result<()> {
+        let pids = self.find_pids()?;
+        if pids.is_empty() {
+            info!("未找到正在运行的服务器进程");
+            return Ok(());
+        }
+
+        for &pid in &pids {
+            info!("正在停止服务器进程 {}", pid);
+            platform::terminate(pid)?;
+        }
+
+        let deadline = Instant::now() + self.grace_period;
+        let mut remaining = pids;
+        while !remaining.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(200));
+            remaining.retain(|&pid| platform::is_running(pid));
+        }
+
+        for &pid in &remaining {
+            warn!("服务器进程 {} 在 {:?} 内没有退出，强制结束", pid, self.grace_period);
+            platform::kill(pid)?;
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !remaining.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(200));
+            remaining.retain(|&pid| platform::is_running(pid));
+        }
+        if !remaining.is_empty() {
+            return Err(io::Error::other(format!("无法结束服务器进程: {:?}", remaining)));
+        }
+        Ok(())
+    }
+
+    pub fn start(&self) -> io::Result<Child> {
+        info!("启动服务器: {} {}", self.launch.program, self.launch.args.join(" "));
+        self.launch.spawn()
+    }
+}
+
+fn read_pidfile(pidfile: &Path) -> io::Result<Option<u32>> {
+    match fs::read_to_string(pidfile) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("pid 文件内容无效: {}", pidfile.display()))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::{fs, io};
+    use std::path::{Path, PathBuf};
+
+    pub fn find_pids(executable: &Path, working_dir: &Path) -> io::Result<Vec<u32>> {
+        let executable = fs::canonicalize(executable).unwrap_or_else(|_| executable.to_path_buf());
+        let exe_name = executable.file_name().map(|name| name.to_os_string());
+        let working_dir = fs::canonicalize(working_dir).unwrap_or_else(|_| working_dir.to_path_buf());
+        let own_pid = std::process::id();
+
+        let mut pids = Vec::new();
+        for entry in fs::read_dir("/proc")? {
+            let entry = entry?;
+            let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+                continue;
+            };
+            if pid == own_pid {
+                continue;
+            }
+            let proc_dir = entry.path();
+
+            // 优先比较 /proc/<pid>/exe，被替换的文件会带有 " (deleted)" 后缀
+            if let Ok(exe) = fs::read_link(proc_dir.join("exe")) {
+                let exe = exe.to_string_lossy();
+                if Path::new(exe.trim_end_matches(" (deleted)")) == executable {
+                    pids.push(pid);
+                }
+                continue;
+            }
+
+            // 没有权限读取 exe 时退回到比较命令行的程序名和工作目录
+            let Ok(cmdline) = fs::read(proc_dir.join("cmdline")) else {
+                continue;
+            };
+            let argv0 = cmdline.split(|&b| b == 0).next().unwrap_or_default();
+            let argv0 = PathBuf::from(String::from_utf8_lossy(argv0).into_owned());
+            let cwd = fs::read_link(proc_dir.join("cwd")).ok();
+            if argv0.file_name().map(|name| name.to_os_string()) == exe_name && cwd.as_deref() == Some(working_dir.as_path()) {
+                pids.push(pid);
+            }
+        }
+        Ok(pids)
+    }
+
+    fn send_signal(pid: u32, signal: libc::c_int) -> io::Result<()> {
+        // SAFETY: kill 只向指定进程发送信号，不涉及内存访问
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::ESRCH) {
+            return Ok(());
+        }
+        Err(error)
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        // 僵尸进程已经退出，只是还没有被回收
+        if let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            if let Some(state) = stat.rsplit(')').next().and_then(|rest| rest.split_whitespace().next()) {
+                return state != "Z";
+            }
+        }
+        // SAFETY: 信号 0 只检查进程是否存在
+        unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+    }
+
+    pub fn terminate(pid: u32) -> io::Result<()> {
+        send_signal(pid, libc::SIGTERM)
+    }
+
+    pub fn kill(pid: u32) -> io::Result<()> {
+        send_signal(pid, libc::SIGKILL)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    fn tasklist(filter: &str) -> io::Result<String> {
+        let output = Command::new("tasklist")
+            .args(["/FI", filter, "/FO", "CSV", "/NH"])
+            .stdout(Stdio::piped())
+            .output()?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    // CSV 每行格式为 "映像名称","PID",...
+    fn parse_pids(output: &str) -> Vec<u32> {
+        output
+            .lines()
+            .filter_map(|line| line.split("\",\"").nth(1))
+            .filter_map(|pid| pid.trim_matches('"').parse().ok())
+            .collect()
+    }
+
+    pub fn find_pids(executable: &Path, _working_dir: &Path) -> io::Result<Vec<u32>> {
+        let exe_name = executable
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid server executable path"))?;
+        Ok(parse_pids(&tasklist(&format!("IMAGENAME eq {}", exe_name.to_string_lossy()))?))
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        tasklist(&format!("PID eq {}", pid)).map(|output| !parse_pids(&output).is_empty()).unwrap_or(false)
+    }
+
+    // 控制台程序无法通过 taskkill 正常关闭，与原来的行为一致直接强制结束
+    pub fn terminate(pid: u32) -> io::Result<()> {
+        kill(pid)
+    }
+
+    pub fn kill(pid: u32) -> io::Result<()> {
+        let status = Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/F"])
+            .stdout(Stdio::null())
+            .status()?;
+        // 进程在此期间自己退出时 taskkill 也会失败，只有进程还在时才算失败
+        if !status.success() && is_running(pid) {
+            return Err(io::Error::other(format!("taskkill 结束进程 {} 失败: {}", pid, status)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_quoted_words() {
+        assert_eq!(split_command_line("  ./bedrock_server  -a 'b c' \"d 'e'\" f\"g h\"i ").unwrap(), ["./bedrock_server", "-a", "b c", "d 'e'", "fg hi"]);
+        assert_eq!(split_command_line("run ''").unwrap(), ["run", ""]);
+        assert!(split_command_line("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_unmatched_quotes() {
+        for line in ["./bedrock_server 'world", "\"./bedrock_server"] {
+            let e = split_command_line(line).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert!(e.to_string().contains("引号不匹配"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn parses_env_assignments() {
+        assert_eq!(parse_env_assignment("LD_LIBRARY_PATH=."), Some(("LD_LIBRARY_PATH".to_string(), ".".to_string())));
+        assert_eq!(parse_env_assignment("A_1=x=y"), Some(("A_1".to_string(), "x=y".to_string())));
+        assert_eq!(parse_env_assignment("EMPTY="), Some(("EMPTY".to_string(), String::new())));
+        for word in ["./bedrock_server", "=x", "1A=x", "A-B=x", "--level=1"] {
+            assert_eq!(parse_env_assignment(word), None, "{}", word);
+        }
+    }
+
+    #[test]
+    fn parses_launch_command() {
+        let dir = Path::new("/srv/bedrock");
+        let command = LaunchCommand::parse("LD_LIBRARY_PATH=. ./bedrock_server", dir).unwrap();
+        assert_eq!(command.env, [("LD_LIBRARY_PATH".to_string(), ".".to_string())]);
+        assert_eq!(command.program, "./bedrock_server");
+        assert!(command.args.is_empty());
+        assert_eq!(command.working_dir, dir);
+
+        // 程序之后的 NAME=VALUE 是普通参数
+        let command = LaunchCommand::parse("A=1 'B=two words' \"/opt/my server/run\" --x=1 C=3", dir).unwrap();
+        assert_eq!(command.env, [("A".to_string(), "1".to_string()), ("B".to_string(), "two words".to_string())]);
+        assert_eq!(command.program, "/opt/my server/run");
+        assert_eq!(command.args, ["--x=1", "C=3"]);
+    }
+
+    #[test]
+    fn rejects_commands_without_program() {
+        for line in ["", "   ", "LD_LIBRARY_PATH=."] {
+            let e = LaunchCommand::parse(line, Path::new(".")).map(|_| ()).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", line);
+        }
+        assert!(LaunchCommand::parse("./bedrock_server 'x", Path::new(".")).is_err());
+    }
+}
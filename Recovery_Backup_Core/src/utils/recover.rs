@@ -1,12 +1,44 @@
 use std::{fs, io, thread};
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::Command;
 use std::thread::sleep;
 use std::time::Duration;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use crate::utils::archive::{can_extract_natively, extract_archive, ArchiveFormat};
+use crate::utils::crypto::{Decrypted, EncryptionKey};
+use crate::utils::events::Stage;
+use crate::utils::process::ServerController;
 use crate::utils::utils::send_request;
+use crate::utils::verify::verify_decrypted;
+
+// 优先原生解压 zip/tar 系列格式，其余格式在提供了 7za 路径时交给 7za
+pub fn unzip_backup(zip_path: &Path, target_dir: &Path, seven_zip_path: Option<&Path>) -> io::Result<()> {
+    let native = ArchiveFormat::from_path(zip_path).is_some_and(can_extract_natively);
+    if native {
+        let stats = extract_archive(zip_path, target_dir)?;
+        info!("已解压 {} 个文件，共 {} 字节，耗时 {} ms", stats.file_count, stats.size, stats.duration_ms);
+        return Ok(());
+    }
+
+    match seven_zip_path {
+        Some(seven_zip_path) => {
+            let stage = Stage::start("extract", 0, 0);
+            let result = unzip_with_7za(zip_path, target_dir, seven_zip_path);
+            stage.finish(&result);
+            result
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("无法原生解压 {}，且未提供 7za 路径", zip_path.display()),
+        )),
+    }
+}
+
+pub fn unzip_with_7za(zip_path: &Path, target_dir: &Path, seven_zip_path: &Path) -> io::Result<()> {
+    if !seven_zip_path.is_file() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("找不到 7za: {}", seven_zip_path.display())));
+    }
 
-pub fn unzip_backup(zip_path: &Path, target_dir: &Path, seven_zip_path: &Path) -> io::Result<()> {
     // 构建解压命令
     let output = Command::new(seven_zip_path)
         .arg("x")  // 提取命令
@@ -17,26 +49,187 @@ pub fn unzip_backup(zip_path: &Path, target_dir: &Path, seven_zip_path: &Path) -
 
     // 检查命令是否成功
     if !output.status.success() {
-        error!("提取备份失败: {}", String::from_utf8_lossy(&output.stderr));
-        return Err(io::Error::new(io::ErrorKind::Other, "Extraction failed"));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!("提取备份失败: {}", stderr);
+        return Err(io::Error::other(format!("7za extraction failed: {}", stderr.trim())));
     }
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn recover_backup(
     backup_path: &Path,
     target_dir: &Path,
     world_name: &str,
-    server_exe: &str,
-    seven_zip_path: &Path,
+    server: &ServerController,
+    seven_zip_path: Option<&Path>,
     url: Option<&str>,
     auth: Option<&str>,
+    force: bool,
+    key: Option<&EncryptionKey>,
 ) -> io::Result<()> {
     let worlds_dir = target_dir.join("worlds");
     let world_path = worlds_dir.join(world_name);
-    let server_exe_path = target_dir.join(server_exe);
+    let staging_path = worlds_dir.join(format!(".{}.restore", world_name));
+    let previous_path = worlds_dir.join(format!(".{}.previous", world_name));
+
+    // 加密的备份只解密一次，校验和解压都使用解密后的文件，密钥错误时在停服前就失败
+    let decrypted = Decrypted::open(backup_path, key)?;
+
+    // 停服前先校验备份，避免停服后才发现备份无法恢复
+    let report = verify_decrypted(backup_path, &decrypted)?;
+    if let (Some(world), Some(created_at)) = (&report.world_name, &report.created_at) {
+        info!("备份清单: 世界 {}，创建于 {}", world, created_at);
+    }
+    if !report.valid {
+        if !force {
+            // 只能交给 7za 的旧备份没有经过校验，确认可用时需要强制恢复
+            let hint = if report.unverified { "，确认备份可用时使用 --force 强制恢复" } else { "" };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("备份校验未通过: {}{}", report.summary(), hint)));
+        }
+        warn!("备份校验未通过，按要求强制恢复: {}", report.summary());
+    }
+
+    // 上次恢复中途退出时留下的暂存目录不能和这次解压的内容混在一起
+    clear_stale_dirs(&world_path, &staging_path, &previous_path)?;
+
+    // 服务器仍在运行时先解压到世界目录旁的暂存目录，失败时现有世界不受影响
+    if let Err(e) = unzip_backup(decrypted.path(), &staging_path, seven_zip_path).and_then(|_| validate_world(&staging_path)) {
+        let _ = fs::remove_dir_all(&staging_path);
+        return Err(e);
+    }
+    drop(decrypted);
+
+    let stage = Stage::start("stop_server", 0, 0);
+    let result = stop_server(server, url, auth);
+    stage.finish(&result);
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staging_path);
+        return Err(e);
+    }
+
+    // 把现有世界移到一旁，再把暂存目录改名为世界目录，任一步失败都会回滚
+    let stage = Stage::start("swap_world", 0, 0);
+    let swap_result = swap_world(&staging_path, &world_path, &previous_path);
+    stage.finish(&swap_result);
+
+    let stage = Stage::start("start_server", 0, 0);
+    let start_result = start_server(server, url, auth);
+    stage.finish(&start_result);
+
+    if let Err(e) = swap_result {
+        let _ = fs::remove_dir_all(&staging_path);
+        return Err(e);
+    }
+    start_result?;
+
+    // 旧世界只在替换成功后删除，删除失败不影响恢复结果
+    if previous_path.exists() {
+        if let Err(e) = remove_dir_with_retry(&previous_path) {
+            warn!("删除旧世界目录 {} 失败: {}", previous_path.display(), e);
+        }
+    }
 
+    info!("Backup {} recovered and server started.", backup_path.display());
+
+    Ok(())
+}
+
+// 删除上次恢复留下的暂存目录和旧世界目录；世界目录不存在时旧世界目录是唯一的副本，先把它改回世界目录
+fn clear_stale_dirs(world_path: &Path, staging_path: &Path, previous_path: &Path) -> io::Result<()> {
+    remove_stale_dir(staging_path)?;
+    if previous_path.exists() && !world_path.exists() {
+        warn!("上次恢复没有完成，把 {} 改回 {}", previous_path.display(), world_path.display());
+        return fs::rename(previous_path, world_path);
+    }
+    remove_stale_dir(previous_path)
+}
+
+fn remove_stale_dir(path: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(path) {
+        Ok(_) => {
+            warn!("已删除上次恢复留下的 {}", path.display());
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// 恢复出的世界必须包含 level.dat 和 db/CURRENT
+pub fn validate_world(world_path: &Path) -> io::Result<()> {
+    for required in ["level.dat", "db/CURRENT"] {
+        if !world_path.join(required).is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("备份中缺少 {}，不是有效的世界", required),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn swap_world(staging_path: &Path, world_path: &Path, previous_path: &Path) -> io::Result<()> {
+    if previous_path.exists() {
+        remove_dir_with_retry(previous_path)?;
+    }
+
+    let had_world = world_path.exists();
+    if had_world {
+        rename_with_retry(world_path, previous_path)?;
+    }
+
+    if let Err(e) = rename_with_retry(staging_path, world_path) {
+        if !had_world {
+            return Err(e);
+        }
+        let stage = Stage::start("rollback", 0, 0);
+        let rollback = rename_with_retry(previous_path, world_path);
+        stage.finish(&rollback);
+        return Err(match rollback {
+            Ok(_) => {
+                warn!("替换世界失败，已回滚到原来的世界");
+                io::Error::new(e.kind(), format!("替换世界失败: {}；已回滚到原来的世界", e))
+            }
+            Err(rollback_error) => {
+                error!("回滚失败，原来的世界保存在 {}", previous_path.display());
+                io::Error::new(
+                    e.kind(),
+                    format!("替换世界失败: {}；回滚失败: {}，原来的世界保存在 {}", e, rollback_error, previous_path.display()),
+                )
+            }
+        });
+    }
+    Ok(())
+}
+
+// 服务器刚被结束时文件句柄可能还没释放，改名和删除都需要重试
+fn rename_with_retry(from: &Path, to: &Path) -> io::Result<()> {
+    retry(|| fs::rename(from, to), &format!("重命名 {} 到 {}", from.display(), to.display()))
+}
+
+fn remove_dir_with_retry(path: &Path) -> io::Result<()> {
+    retry(|| fs::remove_dir_all(path), &format!("删除 {}", path.display()))
+}
+
+fn retry(mut f: impl FnMut() -> io::Result<()>, action: &str) -> io::Result<()> {
+    const ATTEMPTS: u32 = 5;
+    for attempt in 1..=ATTEMPTS {
+        match f() {
+            Ok(_) => return Ok(()),
+            // 路径不存在时重试也不会成功
+            Err(e) if attempt < ATTEMPTS && e.kind() != io::ErrorKind::NotFound => {
+                error!("{}失败: {}. Retrying...", action, e);
+                sleep(Duration::from_secs(3));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+fn stop_server(server: &ServerController, url: Option<&str>, auth: Option<&str>) -> io::Result<()> {
     // 先处理 stop 请求
     if let Some(url) = url {
         let mut modified_url = url.replace("{}", "stop"); // 替换为 stop
@@ -62,36 +255,20 @@ pub fn recover_backup(
         thread::sleep(Duration::from_secs(5));
     }
 
-
-
-
     // 查找并终止服务器进程
+    let mut result = Ok(());
     for _ in 0..3 {
-        if let Err(e) = terminate_server_process(&server_exe_path) {
-            error!("终止服务器进程时出错：{}", e);
-        } else {
-            break;
+        result = server.stop();
+        match &result {
+            Err(e) => error!("终止服务器进程时出错：{}", e),
+            Ok(_) => break,
         }
         thread::sleep(Duration::from_secs(5));
     }
+    result
+}
 
-    // 检查目标目录中是否存在 world_name
-    while world_path.exists() {
-        match fs::remove_dir_all(&world_path) {
-            Ok(_) => {
-                info!("已成功删除世界目录");
-                break;
-            },
-            Err(e) => {
-                error!("Failed to delete the world directory: {}. Retrying...", e);
-                sleep(Duration::from_secs(3)); // 等待一秒后重试
-            },
-        }
-    }
-
-    // 解压备份文件到目标目录，并命名为 world_name
-    unzip_backup(&backup_path, &world_path, seven_zip_path)?;
-
+fn start_server(server: &ServerController, url: Option<&str>, auth: Option<&str>) -> io::Result<()> {
     if let Some(url) = url {
         let mut modified_url = url.replace("{}", "start"); // 替换为 start
 
@@ -138,39 +315,86 @@ pub fn recover_backup(
         }
     }
 
+    // 启动服务器
+    server.start()?;
 
+    Ok(())
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // 启动服务器
-    Command::new(&server_exe_path)
-        .current_dir(target_dir) // 设置工作目录
-        .spawn()?;
+    fn world(path: &Path, content: &str) {
+        fs::create_dir_all(path.join("db")).unwrap();
+        fs::write(path.join("level.dat"), content).unwrap();
+    }
 
-    info!("Backup {} recovered and server started.", backup_path.display());
+    fn content(path: &Path) -> String {
+        fs::read_to_string(path.join("level.dat")).unwrap()
+    }
 
-    Ok(())
-}
+    #[test]
+    fn swaps_staging_into_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let (staging, world_path, previous) = (dir.path().join(".w.restore"), dir.path().join("w"), dir.path().join(".w.previous"));
+        world(&world_path, "old");
+        world(&staging, "new");
+        world(&previous, "stale");
+
+        swap_world(&staging, &world_path, &previous).unwrap();
+        assert_eq!(content(&world_path), "new");
+        assert_eq!(content(&previous), "old");
+        assert!(!staging.exists());
+    }
 
+    #[test]
+    fn swaps_without_existing_world() {
+        let dir = tempfile::tempdir().unwrap();
+        let (staging, world_path, previous) = (dir.path().join(".w.restore"), dir.path().join("w"), dir.path().join(".w.previous"));
+        world(&staging, "new");
 
-pub fn terminate_server_process(server_exe_path: &Path) -> io::Result<()> {
-    let output = Command::new("tasklist")
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()?;
+        swap_world(&staging, &world_path, &previous).unwrap();
+        assert_eq!(content(&world_path), "new");
+        assert!(!previous.exists());
+    }
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
+    #[test]
+    fn rolls_back_when_staging_cannot_be_moved() {
+        let dir = tempfile::tempdir().unwrap();
+        let (staging, world_path, previous) = (dir.path().join(".w.restore"), dir.path().join("w"), dir.path().join(".w.previous"));
+        world(&world_path, "old");
+
+        // 没有解压出的目录，第二次改名失败，原来的世界应当被改回去
+        let e = swap_world(&staging, &world_path, &previous).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert!(e.to_string().contains("已回滚到原来的世界"), "{}", e);
+        assert_eq!(content(&world_path), "old");
+        assert!(!previous.exists());
+    }
 
-    for line in output_str.lines() {
-        if line.contains(server_exe_path.file_name().unwrap().to_str().unwrap()) {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if let Some(pid) = parts.get(1) {
-                Command::new("taskkill")
-                    .args(&["/PID", pid, "/F"])
-                    .spawn()?
-                    .wait()?;
-            }
-        }
+    #[test]
+    fn recovers_interrupted_swap() {
+        let dir = tempfile::tempdir().unwrap();
+        let (staging, world_path, previous) = (dir.path().join(".w.restore"), dir.path().join("w"), dir.path().join(".w.previous"));
+        // 上次恢复在两次改名之间中断：世界已经改名为 .previous，解压目录还在
+        world(&previous, "old");
+        world(&staging, "half");
+
+        clear_stale_dirs(&world_path, &staging, &previous).unwrap();
+        assert_eq!(content(&world_path), "old");
+        assert!(!staging.exists() && !previous.exists());
     }
 
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn removes_stale_previous_when_world_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        let (staging, world_path, previous) = (dir.path().join(".w.restore"), dir.path().join("w"), dir.path().join(".w.previous"));
+        world(&world_path, "current");
+        world(&previous, "old");
+
+        clear_stale_dirs(&world_path, &staging, &previous).unwrap();
+        assert_eq!(content(&world_path), "current");
+        assert!(!previous.exists());
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+use chrono::Local;
+use tracing::{error, info, warn};
+use crate::utils::archive::backup_suffix;
+use crate::utils::crypto::EncryptionKey;
+use crate::utils::download::fetch_manifest;
+use crate::utils::events::Stage;
+use crate::utils::manifest::{Trigger, MANIFEST_SUFFIX};
+use crate::utils::retention::{plan_retention, Candidate, RetentionDecision, RetentionPolicy, RetentionReport};
+use crate::utils::storage::{Depth, StorageBackend};
+use crate::utils::upload::UploadError;
+
+// 只接受远程目录的直接子项，防止越出配置的远程目录
+fn check_key(name: &str) -> io::Result<&str> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\\') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} 不在远程目录中", name)));
+    }
+    Ok(name)
+}
+
+// 列出远程目录中后缀匹配的备份，有清单时使用清单中的创建时间，否则使用服务器的修改时间；加密的清单用 key 解密
+async fn collect_remote_candidates(backend: &dyn StorageBackend, extension: &str, key: Option<&EncryptionKey>) -> Result<(Vec<Candidate>, HashSet<String>), UploadError> {
+    let entries = backend.list("", Depth::One).await?;
+    let files: Vec<_> = entries.into_iter().filter(|entry| !entry.is_collection && !entry.path.contains('/')).collect();
+    let names: HashSet<String> = files.iter().map(|entry| entry.name.clone()).collect();
+
+    let suffix = backup_suffix(extension);
+    let mut candidates = Vec::new();
+    for entry in files {
+        if !entry.name.ends_with(&suffix) || entry.name.ends_with(MANIFEST_SUFFIX) {
+            continue;
+        }
+
+        let mut manifest = None;
+        let manifest_name = format!("{}{}", entry.name, MANIFEST_SUFFIX);
+        if names.contains(&manifest_name) {
+            match fetch_manifest(backend, check_key(&manifest_name)?, key).await {
+                Ok(found) => manifest = found,
+                Err(e) => warn!("读取远程清单 {} 失败，使用修改时间: {}", manifest_name, e),
+            }
+        }
+        let (created_at, permanent) = match manifest {
+            Some(manifest) => (manifest.created_at, manifest.trigger == Trigger::Permanent),
+            None => {
+                // 没有修改时间时视为最新的备份，不会因为保留天数被删除
+                let modified = entry.last_modified.unwrap_or_else(|| {
+                    warn!("远程文件 {} 没有修改时间", entry.name);
+                    Local::now()
+                });
+                (modified, false)
+            }
+        };
+        candidates.push(Candidate { path: PathBuf::from(&entry.name), created_at, size: entry.size.unwrap_or(0), permanent });
+    }
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.created_at));
+    Ok((candidates, names))
+}
+
+async fn remove_remote_backup(backend: &dyn StorageBackend, name: &str, names: &HashSet<String>) -> Result<(), UploadError> {
+    backend.delete(check_key(name)?).await?;
+    let manifest_name = format!("{}{}", name, MANIFEST_SUFFIX);
+    if names.contains(&manifest_name) {
+        backend.delete(check_key(&manifest_name)?).await?;
+    }
+    Ok(())
+}
+
+// 按策略清理远程目录中的备份，只处理该目录的直接子项，dry_run 为 false 时删除需要清理的备份及其清单
+pub async fn apply_remote_retention(backend: &dyn StorageBackend, extension: &str, policy: &RetentionPolicy, dry_run: bool, key: Option<&EncryptionKey>) -> Result<RetentionReport, UploadError> {
+    if !dry_run && backend.prefix().is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "远程目录为空时不执行远程清理，请设置 remote_path").into());
+    }
+    let (candidates, names) = collect_remote_candidates(backend, extension, key).await?;
+    let (keep, reasons) = plan_retention(&candidates, policy);
+
+    let pruned_bytes = candidates.iter().zip(&keep).filter(|(_, keep)| !**keep).map(|(c, _)| c.size).sum();
+    let pruned_count = keep.iter().filter(|keep| !**keep).count() as u64;
+    let stage = Stage::start("remote_retention", if dry_run { 0 } else { pruned_bytes }, if dry_run { 0 } else { pruned_count });
+
+    let mut report = RetentionReport { dry_run, kept: 0, pruned: 0, kept_bytes: 0, pruned_bytes: 0, failed: 0, decisions: Vec::new() };
+    for ((candidate, keep), reasons) in candidates.into_iter().zip(keep).zip(reasons) {
+        let name = candidate.path.to_string_lossy().into_owned();
+        let mut error = None;
+        if keep {
+            report.kept += 1;
+            report.kept_bytes += candidate.size;
+        } else if dry_run {
+            info!("[dry-run] 将删除远程备份 {}: {}", name, reasons.join(", "));
+            report.pruned += 1;
+            report.pruned_bytes += candidate.size;
+        } else {
+            match remove_remote_backup(backend, &name, &names).await {
+                Ok(()) => {
+                    info!("已删除远程备份 {}: {}", name, reasons.join(", "));
+                    stage.file_done(&name, candidate.size);
+                    report.pruned += 1;
+                    report.pruned_bytes += candidate.size;
+                }
+                Err(e) => {
+                    error!("删除远程备份 {} 失败: {}", name, e);
+                    report.failed += 1;
+                    error = Some(e.to_string());
+                }
+            }
+        }
+        report.decisions.push(RetentionDecision {
+            path: backend.location(&name),
+            created_at: candidate.created_at,
+            size: candidate.size,
+            keep,
+            reasons,
+            error,
+        });
+    }
+
+    let result = match report.failed {
+        0 => Ok(()),
+        _ => Err(io::Error::other(report.summary())),
+    };
+    stage.finish(&result);
+    Ok(report)
+}
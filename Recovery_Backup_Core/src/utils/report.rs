@@ -0,0 +1,195 @@
+use std::io;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+// 复制过程中遇到错误时的处理方式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorMode {
+    // 第一个文件失败就停止
+    #[default]
+    FailFast,
+    // 继续处理其余文件，最后汇总失败的文件
+    BestEffort,
+}
+
+impl ErrorMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fail-fast" => Some(Self::FailFast),
+            "best-effort" => Some(Self::BestEffort),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Copied,
+    // 复制后截断到 save query 给出的长度
+    Truncated,
+    // 从基准目录链接，没有实际复制
+    Linked,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileOutcome {
+    pub path: String,
+    pub status: FileStatus,
+    pub bytes: u64,
+    // 失败的文件是否会导致整个操作失败
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CopyReport {
+    // 没有必需的文件失败，且没有因 fail-fast 中止
+    pub success: bool,
+    pub copied: u64,
+    pub truncated: u64,
+    pub linked: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub bytes_copied: u64,
+    pub bytes_linked: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub files: Vec<FileOutcome>,
+}
+
+impl CopyReport {
+    // 用于日志和错误信息的简短说明
+    pub fn summary(&self) -> String {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        let failed: Vec<&str> = self
+            .files
+            .iter()
+            .filter(|file| file.status == FileStatus::Failed && file.required)
+            .map(|file| file.path.as_str())
+            .collect();
+        if failed.is_empty() {
+            format!("复制 {} 个，截断 {} 个，链接 {} 个，失败 {} 个", self.copied, self.truncated, self.linked, self.failed)
+        } else {
+            format!("{} 个必需文件复制失败: {}", failed.len(), failed.join(", "))
+        }
+    }
+}
+
+// 在多个线程中收集每个文件的结果
+pub struct ReportCollector {
+    mode: ErrorMode,
+    files: Mutex<Vec<FileOutcome>>,
+}
+
+impl ReportCollector {
+    pub fn new(mode: ErrorMode) -> Self {
+        ReportCollector { mode, files: Mutex::new(Vec::new()) }
+    }
+
+    pub fn skip(&self, path: &str, reason: &str) {
+        self.push(FileOutcome { path: path.to_string(), status: FileStatus::Skipped, bytes: 0, required: false, reason: Some(reason.to_string()) });
+    }
+
+    // 记录一个文件的结果，fail-fast 模式下失败会返回错误以中止后续复制
+    pub fn record(&self, path: &str, required: bool, result: io::Result<(FileStatus, u64)>) -> io::Result<()> {
+        match result {
+            Ok((status, bytes)) => {
+                self.push(FileOutcome { path: path.to_string(), status, bytes, required, reason: None });
+                Ok(())
+            }
+            Err(e) => {
+                let reason = e.to_string();
+                self.push(FileOutcome { path: path.to_string(), status: FileStatus::Failed, bytes: 0, required, reason: Some(reason.clone()) });
+                if self.mode == ErrorMode::FailFast {
+                    return Err(io::Error::new(e.kind(), format!("{}: {}", path, reason)));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn push(&self, outcome: FileOutcome) {
+        self.files.lock().unwrap_or_else(|e| e.into_inner()).push(outcome);
+    }
+
+    // aborted 为中止复制的错误，文件按路径排序保证输出稳定
+    pub fn finish(self, aborted: Option<io::Error>) -> CopyReport {
+        let mut files = self.files.into_inner().unwrap_or_else(|e| e.into_inner());
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut report = CopyReport { error: aborted.map(|e| e.to_string()), ..Default::default() };
+        for file in &files {
+            match file.status {
+                FileStatus::Copied => report.copied += 1,
+                FileStatus::Truncated => report.truncated += 1,
+                FileStatus::Linked => report.linked += 1,
+                FileStatus::Skipped => report.skipped += 1,
+                FileStatus::Failed => report.failed += 1,
+            }
+            match file.status {
+                FileStatus::Copied | FileStatus::Truncated => report.bytes_copied += file.bytes,
+                FileStatus::Linked => report.bytes_linked += file.bytes,
+                _ => {}
+            }
+        }
+        report.success = report.error.is_none() && !files.iter().any(|file| file.status == FileStatus::Failed && file.required);
+        report.files = files;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing() -> io::Result<(FileStatus, u64)> {
+        Err(io::Error::new(io::ErrorKind::NotFound, "不存在"))
+    }
+
+    #[test]
+    fn fail_fast_stops_on_first_failure() {
+        let collector = ReportCollector::new(ErrorMode::FailFast);
+        collector.record("db/000001.ldb", true, Ok((FileStatus::Copied, 10))).unwrap();
+        // 不是必需的文件失败同样中止
+        let e = collector.record("resource_packs/a", false, missing()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert!(e.to_string().starts_with("resource_packs/a: "), "{}", e);
+
+        let report = collector.finish(Some(e));
+        assert!(!report.success);
+        assert_eq!((report.copied, report.failed), (1, 1));
+        assert_eq!(report.summary(), "resource_packs/a: 不存在");
+    }
+
+    #[test]
+    fn best_effort_fails_only_on_required_files() {
+        let collector = ReportCollector::new(ErrorMode::BestEffort);
+        collector.record("db/000001.ldb", true, Ok((FileStatus::Linked, 100))).unwrap();
+        collector.record("db/000002.ldb", true, Ok((FileStatus::Truncated, 30))).unwrap();
+        collector.record("level.dat", true, Ok((FileStatus::Copied, 5))).unwrap();
+        collector.record("resource_packs/a", false, missing()).unwrap();
+        collector.skip("fifo", "不是普通文件");
+
+        let report = collector.finish(None);
+        assert!(report.success, "{}", report.summary());
+        assert_eq!((report.copied, report.truncated, report.linked, report.skipped, report.failed), (1, 1, 1, 1, 1));
+        assert_eq!((report.bytes_copied, report.bytes_linked), (35, 100));
+        // 文件按路径排序
+        let paths: Vec<_> = report.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["db/000001.ldb", "db/000002.ldb", "fifo", "level.dat", "resource_packs/a"]);
+
+        let collector = ReportCollector::new(ErrorMode::BestEffort);
+        collector.record("db/CURRENT", true, missing()).unwrap();
+        collector.record("level.dat", true, Ok((FileStatus::Copied, 5))).unwrap();
+        let report = collector.finish(None);
+        assert!(!report.success);
+        assert_eq!(report.summary(), "1 个必需文件复制失败: db/CURRENT");
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, Local};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+use crate::utils::archive::safe_join;
+use crate::utils::events::Stage;
+use crate::utils::manifest::{hash_file, hash_tree, FileEntry, Trigger};
+use crate::utils::retention::{plan_retention, Candidate, RetentionPolicy};
+
+pub const REPOSITORY_VERSION: u32 = 1;
+
+// 仓库目录结构：
+//   config.json             仓库版本
+//   objects/<前两位>/<哈希>  按 BLAKE3 哈希存放的文件内容，相同内容只存一份
+//   snapshots/<id>.json      每次备份的快照索引
+//   lock                     写操作期间存在，防止多个进程同时修改仓库
+const CONFIG_FILE: &str = "config.json";
+const OBJECTS_DIR: &str = "objects";
+const SNAPSHOTS_DIR: &str = "snapshots";
+const LOCK_FILE: &str = "lock";
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize)]
+struct RepositoryConfig {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub id: String,
+    pub world_name: String,
+    pub created_at: DateTime<Local>,
+    pub trigger: Trigger,
+    pub tool_version: String,
+    pub total_size: u64,
+    // 本次备份新写入仓库的字节数
+    pub new_bytes: u64,
+    pub files: Vec<FileEntry>,
+}
+
+// list 输出中的快照摘要，不包含文件列表
+#[derive(Serialize, Debug)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub world_name: String,
+    pub created_at: DateTime<Local>,
+    pub trigger: Trigger,
+    pub total_size: u64,
+    pub new_bytes: u64,
+    pub file_count: u64,
+}
+
+impl From<&Snapshot> for SnapshotInfo {
+    fn from(snapshot: &Snapshot) -> Self {
+        SnapshotInfo {
+            id: snapshot.id.clone(),
+            world_name: snapshot.world_name.clone(),
+            created_at: snapshot.created_at,
+            trigger: snapshot.trigger,
+            total_size: snapshot.total_size,
+            new_bytes: snapshot.new_bytes,
+            file_count: snapshot.files.len() as u64,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SnapshotDecision {
+    pub id: String,
+    pub created_at: DateTime<Local>,
+    pub keep: bool,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    pub snapshots_removed: u64,
+    pub objects_removed: u64,
+    pub bytes_freed: u64,
+    pub decisions: Vec<SnapshotDecision>,
+}
+
+pub struct Repository {
+    root: PathBuf,
+}
+
+// 持有期间仓库处于锁定状态，释放时删除锁文件
+struct RepositoryLock {
+    path: PathBuf,
+}
+
+impl Drop for RepositoryLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("删除仓库锁 {:?} 失败: {}", self.path, e);
+        }
+    }
+}
+
+impl Repository {
+    // 打开仓库，目录不存在时创建一个新仓库
+    pub fn open_or_init(root: &Path) -> io::Result<Self> {
+        let config_path = root.join(CONFIG_FILE);
+        if !config_path.exists() {
+            fs::create_dir_all(root.join(OBJECTS_DIR))?;
+            fs::create_dir_all(root.join(SNAPSHOTS_DIR))?;
+            let config = serde_json::to_string_pretty(&RepositoryConfig { version: REPOSITORY_VERSION }).map_err(io::Error::other)?;
+            write_atomic(&config_path, config.as_bytes())?;
+            info!("已初始化备份仓库 {:?}", root);
+        }
+        Self::open(root)
+    }
+
+    pub fn open(root: &Path) -> io::Result<Self> {
+        let data = fs::read_to_string(root.join(CONFIG_FILE))
+            .map_err(|e| io::Error::new(e.kind(), format!("{:?} 不是备份仓库: {}", root, e)))?;
+        let config: RepositoryConfig =
+            serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if config.version != REPOSITORY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("不支持的仓库版本: {}", config.version),
+            ));
+        }
+        Ok(Repository { root: root.to_path_buf() })
+    }
+
+    fn lock(&self) -> io::Result<RepositoryLock> {
+        let path = self.root.join(LOCK_FILE);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                writeln!(file, "{}", std::process::id())?;
+                Ok(RepositoryLock { path })
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("仓库正被其他进程使用，如确认没有进程在运行可删除 {:?}", path),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.root.join(OBJECTS_DIR).join(&hash[..2]).join(hash)
+    }
+
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        self.root.join(SNAPSHOTS_DIR).join(format!("{}.json", id))
+    }
+
+    // 将 source 目录保存为一个快照，仓库中已有的文件内容不会重复写入
+    pub fn backup(&self, source: &Path, world_name: &str, trigger: Trigger) -> io::Result<Snapshot> {
+        let _lock = self.lock()?;
+        let files = hash_tree(source)?;
+        let total_size = files.iter().map(|file| file.size).sum();
+
+        let stage = Stage::start("repo_backup", total_size, files.len() as u64);
+        let new_bytes = AtomicU64::new(0);
+        let result = files.par_iter().try_for_each(|file| -> io::Result<()> {
+            let object = self.object_path(&file.blake3);
+            if !object.exists() {
+                store_object(&source.join(&file.path), &object, &file.blake3)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file.path, e)))?;
+                new_bytes.fetch_add(file.size, Ordering::Relaxed);
+            }
+            stage.file_done(&file.path, file.size);
+            Ok(())
+        });
+        stage.finish(&result);
+        result?;
+
+        let created_at = Local::now();
+        let files_json = serde_json::to_vec(&files).map_err(io::Error::other)?;
+        let id = format!("{}-{}", created_at.format("%Y%m%d-%H%M%S"), &blake3::hash(&files_json).to_hex()[..8]);
+        let snapshot = Snapshot {
+            id,
+            world_name: world_name.to_string(),
+            created_at,
+            trigger,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            total_size,
+            new_bytes: new_bytes.load(Ordering::Relaxed),
+            files,
+        };
+        let data = serde_json::to_string_pretty(&snapshot).map_err(io::Error::other)?;
+        write_atomic(&self.snapshot_path(&snapshot.id), data.as_bytes())?;
+        info!("已创建快照 {}，新增 {} 字节", snapshot.id, snapshot.new_bytes);
+        Ok(snapshot)
+    }
+
+    // 按创建时间从旧到新列出所有快照
+    pub fn snapshots(&self) -> io::Result<Vec<Snapshot>> {
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(self.root.join(SNAPSHOTS_DIR))? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let data = fs::read_to_string(&path)?;
+            let snapshot: Snapshot = serde_json::from_str(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("快照 {:?} 无效: {}", path, e)))?;
+            snapshots.push(snapshot);
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.created_at);
+        Ok(snapshots)
+    }
+
+    // id 为 "latest" 时使用最新的快照
+    pub fn snapshot(&self, id: &str) -> io::Result<Snapshot> {
+        if id == "latest" {
+            return self
+                .snapshots()?
+                .pop()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "仓库中没有快照"));
+        }
+        // 只接受生成的 id 格式 (时间和十六进制哈希)，避免 "../x" 之类的 id 指向仓库外的文件
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("快照 id 无效: {}", id)));
+        }
+        let data = fs::read_to_string(self.snapshot_path(id))
+            .map_err(|e| io::Error::new(e.kind(), format!("快照 {} 不存在: {}", id, e)))?;
+        serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // 把快照还原到 target 目录，目标目录必须不存在或为空，每个文件写入后都会校验哈希
+    pub fn restore(&self, id: &str, target: &Path) -> io::Result<Snapshot> {
+        let snapshot = self.snapshot(id)?;
+        if target.exists() && fs::read_dir(target)?.next().is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("目标目录不为空: {:?}", target)));
+        }
+        fs::create_dir_all(target)?;
+
+        let stage = Stage::start("repo_restore", snapshot.total_size, snapshot.files.len() as u64);
+        let result = snapshot.files.par_iter().try_for_each(|file| -> io::Result<()> {
+            let path = safe_join(target, &file.path)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let object = self.object_path(&file.blake3);
+            fs::copy(&object, &path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file.path, e)))?;
+            if hash_file(&path)? != file.blake3 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("仓库对象已损坏: {}", file.path)));
+            }
+            stage.file_done(&file.path, file.size);
+            Ok(())
+        });
+        stage.finish(&result);
+        result?;
+        info!("已将快照 {} 还原到 {:?}", snapshot.id, target);
+        Ok(snapshot)
+    }
+
+    // 按保留策略删除快照，再回收不再被任何快照引用的对象
+    pub fn prune(&self, policy: &RetentionPolicy, dry_run: bool) -> io::Result<PruneReport> {
+        let _lock = self.lock()?;
+        let mut snapshots = self.snapshots()?;
+        snapshots.reverse();
+
+        let candidates: Vec<Candidate> = snapshots
+            .iter()
+            .map(|snapshot| Candidate {
+                path: self.snapshot_path(&snapshot.id),
+                created_at: snapshot.created_at,
+                // 快照之间共享对象，按每个快照新增的数据量估算其占用
+                size: snapshot.new_bytes,
+                permanent: snapshot.trigger == Trigger::Permanent,
+            })
+            .collect();
+        let (keep, reasons) = plan_retention(&candidates, policy);
+
+        let mut report = PruneReport { dry_run, snapshots_removed: 0, objects_removed: 0, bytes_freed: 0, decisions: Vec::new() };
+        let mut referenced = HashSet::new();
+        for ((snapshot, candidate), (keep, reasons)) in snapshots.iter().zip(&candidates).zip(keep.into_iter().zip(reasons)) {
+            if keep {
+                referenced.extend(snapshot.files.iter().map(|file| file.blake3.clone()));
+            } else {
+                if !dry_run {
+                    fs::remove_file(&candidate.path)?;
+                }
+                info!("{}删除快照 {}: {}", if dry_run { "[dry-run] 将" } else { "已" }, snapshot.id, reasons.join(", "));
+                report.snapshots_removed += 1;
+            }
+            report.decisions.push(SnapshotDecision { id: snapshot.id.clone(), created_at: snapshot.created_at, keep, reasons });
+        }
+
+        let stage = Stage::start("repo_gc", 0, 0);
+        let result = self.collect_garbage(&referenced, dry_run, &mut report, &stage);
+        stage.finish(&result);
+        result?;
+        Ok(report)
+    }
+
+    fn collect_garbage(&self, referenced: &HashSet<String>, dry_run: bool, report: &mut PruneReport, stage: &Stage) -> io::Result<()> {
+        for prefix in fs::read_dir(self.root.join(OBJECTS_DIR))? {
+            let prefix = prefix?.path();
+            if !prefix.is_dir() {
+                continue;
+            }
+            for object in fs::read_dir(&prefix)? {
+                let object = object?.path();
+                let name = object.file_name().unwrap().to_string_lossy().into_owned();
+                if referenced.contains(&name) {
+                    continue;
+                }
+                // 中断的写入留下的临时文件同样回收
+                let size = fs::metadata(&object)?.len();
+                if !dry_run {
+                    if let Err(e) = fs::remove_file(&object) {
+                        error!("删除对象 {:?} 失败: {}", object, e);
+                        continue;
+                    }
+                }
+                stage.file_done(&name, size);
+                report.objects_removed += 1;
+                report.bytes_freed += size;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 复制文件到对象目录，同时计算哈希，内容与预期不一致说明文件在备份期间被修改
+fn store_object(source: &Path, object: &Path, expected_hash: &str) -> io::Result<()> {
+    let dir = object.parent().unwrap();
+    fs::create_dir_all(dir)?;
+    let temp = dir.join(format!(".tmp-{}-{}", std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+    let result = (|| {
+        let mut input = File::open(source)?;
+        let mut output = File::create(&temp)?;
+        let mut hasher = blake3::Hasher::new();
+        let mut buffer = vec![0; 1024 * 1024];
+        loop {
+            let read = input.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            output.write_all(&buffer[..read])?;
+        }
+        output.sync_all()?;
+        if hasher.finalize().to_hex().as_str() != expected_hash {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "文件在备份期间被修改"));
+        }
+        fs::rename(&temp, object)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// 先写临时文件再改名，避免中断时留下不完整的索引
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_os_string();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    {
+        let mut file = File::create(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objects(root: &Path) -> usize {
+        fs::read_dir(root.join(OBJECTS_DIR)).unwrap().map(|prefix| fs::read_dir(prefix.unwrap().path()).unwrap().count()).sum()
+    }
+
+    // 两个内容相同的文件和一个不同的文件
+    fn sample_world(root: &Path) {
+        fs::create_dir_all(root.join("db")).unwrap();
+        fs::write(root.join("level.dat"), b"level").unwrap();
+        fs::write(root.join("db").join("000001.ldb"), vec![1u8; 1000]).unwrap();
+        fs::write(root.join("db").join("000002.ldb"), vec![1u8; 1000]).unwrap();
+    }
+
+    #[test]
+    fn stores_identical_content_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let (world, root) = (dir.path().join("world"), dir.path().join("repo"));
+        sample_world(&world);
+        let repo = Repository::open_or_init(&root).unwrap();
+
+        let first = repo.backup(&world, "w", Trigger::Manual).unwrap();
+        assert_eq!((first.files.len(), first.total_size, first.new_bytes), (3, 2005, 1005));
+        assert_eq!(objects(&root), 2);
+
+        // 没有变化时不写入新数据，只修改的文件写入新对象
+        let second = repo.backup(&world, "w", Trigger::Manual).unwrap();
+        assert_eq!(second.new_bytes, 0);
+        fs::write(world.join("level.dat"), b"level2").unwrap();
+        let third = repo.backup(&world, "w", Trigger::Manual).unwrap();
+        assert_eq!(third.new_bytes, 6);
+        assert_eq!(objects(&root), 3);
+        assert!(!root.join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn restores_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let (world, root, target) = (dir.path().join("world"), dir.path().join("repo"), dir.path().join("out"));
+        sample_world(&world);
+        let repo = Repository::open_or_init(&root).unwrap();
+        let snapshot = repo.backup(&world, "w", Trigger::Manual).unwrap();
+
+        assert_eq!(repo.restore("latest", &target).unwrap().id, snapshot.id);
+        for file in &snapshot.files {
+            assert_eq!(fs::read(target.join(&file.path)).unwrap(), fs::read(world.join(&file.path)).unwrap(), "{}", file.path);
+        }
+        let e = repo.restore(&snapshot.id, &target).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+
+        // 对象内容被改动时还原失败
+        let level = snapshot.files.iter().find(|file| file.path == "level.dat").unwrap();
+        fs::write(repo.object_path(&level.blake3), b"LEVEL").unwrap();
+        let e = repo.restore(&snapshot.id, &dir.path().join("out2")).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("已损坏"), "{}", e);
+    }
+
+    #[test]
+    fn prune_removes_unreferenced_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let (world, root) = (dir.path().join("world"), dir.path().join("repo"));
+        sample_world(&world);
+        let repo = Repository::open_or_init(&root).unwrap();
+        let old = repo.backup(&world, "w", Trigger::Manual).unwrap();
+        fs::write(world.join("level.dat"), b"level2").unwrap();
+        let new = repo.backup(&world, "w", Trigger::Manual).unwrap();
+
+        let policy = RetentionPolicy { keep_last: 1, ..RetentionPolicy::default() };
+        let report = repo.prune(&policy, true).unwrap();
+        assert_eq!((report.snapshots_removed, report.objects_removed, report.bytes_freed), (1, 1, 5));
+        assert_eq!((repo.snapshots().unwrap().len(), objects(&root)), (2, 3));
+
+        repo.prune(&policy, false).unwrap();
+        let ids: Vec<_> = repo.snapshots().unwrap().into_iter().map(|snapshot| snapshot.id).collect();
+        assert_eq!(ids, [new.id.as_str()]);
+        assert_eq!(objects(&root), 2);
+        assert_eq!(repo.snapshot(&old.id).unwrap_err().kind(), io::ErrorKind::NotFound);
+        repo.restore(&new.id, &dir.path().join("out")).unwrap();
+    }
+
+    #[test]
+    fn rejects_snapshot_ids_outside_the_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::open_or_init(&dir.path().join("repo")).unwrap();
+        fs::write(dir.path().join("x.json"), "{}").unwrap();
+
+        for id in ["../../x", "..", "", "20261018-120000-abc/../x"] {
+            let e = repo.snapshot(id).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", id);
+        }
+        assert_eq!(repo.snapshot("20261018-120000-0123abcd").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
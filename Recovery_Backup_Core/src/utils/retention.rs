@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Datelike, Local};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use crate::utils::archive::backup_suffix;
+use crate::utils::crypto::encrypted_path_for;
+use crate::utils::events::Stage;
+use crate::utils::manifest::{is_manifest_path, manifest_path_for, BackupManifest, Trigger};
+
+// 保留策略，各项为 0 表示不启用该规则
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    // 保留最新的 N 个备份
+    pub keep_last: usize,
+    // 最近 N 天中每天保留最新的一个
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+    pub keep_monthly: usize,
+    // 保留的备份总大小上限，超出时从最旧的开始删除
+    pub max_total_size: Option<u64>,
+    // 删除创建超过 N 天的备份，与 cleanup 的保留天数相同
+    pub max_age_days: Option<u64>,
+    // 无论其他规则如何，最新的 N 个备份都不会被删除
+    pub min_keep: usize,
+}
+
+impl RetentionPolicy {
+    fn has_keep_rules(&self) -> bool {
+        self.keep_last > 0 || self.keep_daily > 0 || self.keep_weekly > 0 || self.keep_monthly > 0
+    }
+
+    // 没有启用任何会删除备份的规则
+    pub fn is_empty(&self) -> bool {
+        !self.has_keep_rules() && self.max_total_size.is_none() && self.max_age_days.is_none()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RetentionDecision {
+    pub path: String,
+    pub created_at: DateTime<Local>,
+    pub size: u64,
+    pub keep: bool,
+    // 保留或删除的原因，例如 "last"、"daily 2026-10-18"、"max_total_size"
+    pub reasons: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub kept: u64,
+    pub pruned: u64,
+    pub kept_bytes: u64,
+    pub pruned_bytes: u64,
+    // 删除失败的备份数量，不为 0 时整个清理视为失败
+    pub failed: u64,
+    // 按创建时间从新到旧排列
+    pub decisions: Vec<RetentionDecision>,
+}
+
+impl RetentionReport {
+    // 用于错误信息的简短说明
+    pub fn summary(&self) -> String {
+        match self.decisions.iter().find(|decision| decision.error.is_some()) {
+            Some(first) => format!("{} 个备份删除失败，首个为 {}: {}", self.failed, first.path, first.error.as_deref().unwrap_or_default()),
+            None => "清理完成".to_string(),
+        }
+    }
+}
+
+// 参与保留计算的一个备份，也用于仓库中的快照
+pub struct Candidate {
+    pub path: PathBuf,
+    pub created_at: DateTime<Local>,
+    pub size: u64,
+    pub permanent: bool,
+}
+
+// 找出目录中后缀匹配的备份，有清单时使用清单中的创建时间，否则使用修改时间
+fn collect_candidates(backup_dir: &Path, extension: &str) -> io::Result<Vec<Candidate>> {
+    let suffix = backup_suffix(extension);
+    let mut candidates = Vec::new();
+    for entry in fs::read_dir(backup_dir)? {
+        let path = entry?.path();
+        let matches = path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(&suffix));
+        if !path.is_file() || !matches || is_manifest_path(&path) {
+            continue;
+        }
+
+        let metadata = fs::metadata(&path)?;
+        let manifest = BackupManifest::read(&path).unwrap_or_else(|e| {
+            error!("{}", e);
+            None
+        });
+        let (created_at, permanent) = match manifest {
+            Some(manifest) => (manifest.created_at, manifest.trigger == Trigger::Permanent),
+            None => (DateTime::from(metadata.modified()?), false),
+        };
+        candidates.push(Candidate { path, created_at, size: metadata.len(), permanent });
+    }
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.created_at));
+    Ok(candidates)
+}
+
+// 每个时间段只保留其中最新的一个备份，最多保留 count 个时间段
+fn keep_per_period<K, F>(candidates: &[Candidate], reasons: &mut [Vec<String>], count: usize, rule: &str, period: F)
+where
+    K: Eq + std::hash::Hash + std::fmt::Display,
+    F: Fn(&DateTime<Local>) -> K,
+{
+    let mut seen = HashSet::new();
+    for (candidate, reasons) in candidates.iter().zip(reasons.iter_mut()) {
+        if seen.len() >= count {
+            break;
+        }
+        let key = period(&candidate.created_at);
+        if !seen.contains(&key) {
+            reasons.push(format!("{} {}", rule, key));
+            seen.insert(key);
+        }
+    }
+}
+
+// 根据策略计算每个备份的去留，candidates 需要按创建时间从新到旧排列
+pub fn plan_retention(candidates: &[Candidate], policy: &RetentionPolicy) -> (Vec<bool>, Vec<Vec<String>>) {
+    let mut reasons: Vec<Vec<String>> = vec![Vec::new(); candidates.len()];
+
+    for (index, (candidate, reasons)) in candidates.iter().zip(reasons.iter_mut()).enumerate() {
+        if candidate.permanent {
+            reasons.push("permanent".to_string());
+        }
+        if index < policy.min_keep {
+            reasons.push("min_keep".to_string());
+        }
+        if index < policy.keep_last {
+            reasons.push("last".to_string());
+        }
+        if !policy.has_keep_rules() {
+            reasons.push("no_keep_rules".to_string());
+        }
+    }
+    keep_per_period(candidates, &mut reasons, policy.keep_daily, "daily", |time| time.date_naive());
+    keep_per_period(candidates, &mut reasons, policy.keep_weekly, "weekly", |time| {
+        let week = time.iso_week();
+        format!("{}-W{:02}", week.year(), week.week())
+    });
+    keep_per_period(candidates, &mut reasons, policy.keep_monthly, "monthly", |time| {
+        format!("{}-{:02}", time.year(), time.month())
+    });
+
+    let mut keep: Vec<bool> = reasons.iter().map(|reasons| !reasons.is_empty()).collect();
+    for (keep, reasons) in keep.iter_mut().zip(reasons.iter_mut()) {
+        if !*keep {
+            reasons.push("not_matched".to_string());
+        }
+    }
+
+    // 超过保留天数的备份即使匹配了保留规则也会删除
+    let cutoff = policy
+        .max_age_days
+        .and_then(|days| chrono::Duration::try_days(i64::try_from(days).ok()?))
+        .and_then(|age| Local::now().checked_sub_signed(age));
+    if let Some(cutoff) = cutoff {
+        for (index, candidate) in candidates.iter().enumerate() {
+            let protected = candidate.permanent || index < policy.min_keep;
+            if keep[index] && !protected && candidate.created_at < cutoff {
+                keep[index] = false;
+                reasons[index].push("max_age".to_string());
+            }
+        }
+    }
+
+    // 总大小超出上限时，从最旧的开始删除不受保护的备份
+    if let Some(max_total_size) = policy.max_total_size {
+        let mut total: u64 = candidates.iter().zip(&keep).filter(|(_, keep)| **keep).map(|(c, _)| c.size).sum();
+        for index in (0..candidates.len()).rev() {
+            if total <= max_total_size {
+                break;
+            }
+            let protected = candidates[index].permanent || index < policy.min_keep;
+            if keep[index] && !protected {
+                keep[index] = false;
+                total -= candidates[index].size;
+                reasons[index].push("max_total_size".to_string());
+            }
+        }
+    }
+
+    (keep, reasons)
+}
+
+// 按策略清理目录中的备份，dry_run 为 false 时删除需要清理的备份及其清单
+pub fn apply_retention(backup_dir: &Path, extension: &str, policy: &RetentionPolicy, dry_run: bool) -> io::Result<RetentionReport> {
+    let candidates = collect_candidates(backup_dir, extension)?;
+    let (keep, reasons) = plan_retention(&candidates, policy);
+
+    let pruned_bytes = candidates.iter().zip(&keep).filter(|(_, keep)| !**keep).map(|(c, _)| c.size).sum();
+    let pruned_count = keep.iter().filter(|keep| !**keep).count() as u64;
+    let stage = Stage::start("retention", if dry_run { 0 } else { pruned_bytes }, if dry_run { 0 } else { pruned_count });
+
+    let mut report = RetentionReport { dry_run, kept: 0, pruned: 0, kept_bytes: 0, pruned_bytes: 0, failed: 0, decisions: Vec::new() };
+    for ((candidate, keep), reasons) in candidates.into_iter().zip(keep).zip(reasons) {
+        let mut error = None;
+        if keep {
+            report.kept += 1;
+            report.kept_bytes += candidate.size;
+        } else if dry_run {
+            info!("[dry-run] 将删除备份 {:?}: {}", candidate.path, reasons.join(", "));
+            report.pruned += 1;
+            report.pruned_bytes += candidate.size;
+        } else {
+            match remove_backup(&candidate.path) {
+                Ok(()) => {
+                    info!("已删除备份 {:?}: {}", candidate.path, reasons.join(", "));
+                    stage.file_done(&candidate.path.to_string_lossy(), candidate.size);
+                    report.pruned += 1;
+                    report.pruned_bytes += candidate.size;
+                }
+                Err(e) => {
+                    error!("删除备份 {:?} 失败: {}", candidate.path, e);
+                    report.failed += 1;
+                    error = Some(e.to_string());
+                }
+            }
+        }
+        report.decisions.push(RetentionDecision {
+            path: candidate.path.to_string_lossy().into_owned(),
+            created_at: candidate.created_at,
+            size: candidate.size,
+            keep,
+            reasons,
+            error,
+        });
+    }
+
+    let result = match report.failed {
+        0 => Ok(()),
+        _ => Err(io::Error::other(report.summary())),
+    };
+    stage.finish(&result);
+    Ok(report)
+}
+
+// 同时删除清单和上传时加密留下的 "<压缩包>.enc"
+fn remove_backup(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    for sidecar in [manifest_path_for(path), encrypted_path_for(path)] {
+        if sidecar.exists() {
+            fs::remove_file(sidecar)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn candidate(created_at: DateTime<Local>, permanent: bool) -> Candidate {
+        Candidate { path: PathBuf::new(), created_at, size: 10, permanent }
+    }
+
+    fn at(month: u32, day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, month, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn keeps_latest_per_period_within_size_limit() {
+        let candidates = [
+            candidate(at(10, 18, 12), false),
+            candidate(at(10, 18, 8), false),
+            candidate(at(10, 17, 12), false),
+            candidate(at(10, 10, 12), true),
+            candidate(at(9, 1, 12), false),
+        ];
+        let policy = RetentionPolicy { keep_last: 1, keep_daily: 2, keep_monthly: 2, ..Default::default() };
+        let (keep, reasons) = plan_retention(&candidates, &policy);
+        assert_eq!(keep, [true, false, true, true, true]);
+        assert_eq!(reasons[0], ["last", "daily 2026-10-18", "monthly 2026-10"]);
+        assert_eq!(reasons[1], ["not_matched"]);
+        assert_eq!(reasons[3], ["permanent"]);
+        assert_eq!(reasons[4], ["monthly 2026-09"]);
+
+        // 超出总大小时从最旧的开始删除，永久备份不删除
+        let policy = RetentionPolicy { max_total_size: Some(25), ..policy };
+        let (keep, reasons) = plan_retention(&candidates, &policy);
+        assert_eq!(keep, [true, false, false, true, false]);
+        assert_eq!(reasons[2], ["daily 2026-10-17", "max_total_size"]);
+    }
+
+    #[test]
+    fn max_age_respects_min_keep() {
+        let old = Local::now() - chrono::Duration::days(40);
+        let candidates = [candidate(old, false), candidate(old - chrono::Duration::days(1), false)];
+        let policy = RetentionPolicy { max_age_days: Some(30), min_keep: 1, ..Default::default() };
+        let (keep, reasons) = plan_retention(&candidates, &policy);
+        assert_eq!(keep, [true, false]);
+        assert_eq!(reasons[1], ["no_keep_rules", "max_age"]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::Serialize;
+
+// save query 列出的一个文件，path 为去掉世界名前缀后的相对路径，使用 '/' 分隔
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SaveQueryEntry {
+    pub path: String,
+    pub length: u64,
+}
+
+// save query 的输出，格式为 "<世界名>/db/000005.ldb:5000, <世界名>/db/CURRENT:16, ..."
+#[derive(Debug, Clone)]
+pub struct SaveQueryList {
+    pub world_name: String,
+    pub entries: Vec<SaveQueryEntry>,
+}
+
+impl SaveQueryList {
+    // 世界名可能包含逗号或冒号，所以只在逗号后紧跟世界名前缀的位置拆分，长度取最后一个冒号之后的部分
+    pub fn parse(text: &str, world_name: &str) -> io::Result<Self> {
+        if world_name.is_empty() {
+            return Err(invalid("世界名为空"));
+        }
+        let prefixes = [format!("{}/", world_name), format!("{}\\", world_name)];
+        let mut entries = Vec::new();
+        let mut seen = HashSet::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            for raw in split_entries(line, &prefixes) {
+                let entry = parse_entry(raw, &prefixes)
+                    .map_err(|message| invalid(format!("第 {} 行的条目 \"{}\" 无效: {}", line_number + 1, raw, message)))?;
+                if !seen.insert(entry.path.clone()) {
+                    return Err(invalid(format!("第 {} 行的文件 {} 重复出现", line_number + 1, entry.path)));
+                }
+                entries.push(entry);
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(invalid("save query 输出中没有任何文件"));
+        }
+        Ok(SaveQueryList { world_name: world_name.to_string(), entries })
+    }
+
+    // 读取插件写入的列表文件，世界名取自 source_world 的目录名
+    pub fn read(source_world: &Path, db_list_file: &Path) -> io::Result<Self> {
+        let world_name = source_world
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid source_world path"))?;
+        let text = fs::read_to_string(db_list_file)?;
+        Self::parse(&text, &world_name)
+    }
+
+    // 确认列出的文件都存在且不短于记录的长度，否则截断时会在文件末尾补零
+    pub fn check_files(&self, source_world: &Path) -> io::Result<()> {
+        let mut problems = Vec::new();
+        for entry in &self.entries {
+            match fs::metadata(source_world.join(&entry.path)) {
+                Ok(metadata) if metadata.len() < entry.length => {
+                    problems.push(format!("{} 只有 {} 字节，少于 {}", entry.path, metadata.len(), entry.length));
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => problems.push(format!("{} 不存在", entry.path)),
+                Err(e) => problems.push(format!("{}: {}", entry.path, e)),
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("save query 列出的文件有问题: {}", problems.join("; "))))
+    }
+}
+
+fn split_entries<'a>(line: &'a str, prefixes: &[String]) -> Vec<&'a str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    for (index, _) in line.match_indices(',') {
+        let rest = line[index + 1..].trim_start();
+        if prefixes.iter().any(|prefix| rest.starts_with(prefix.as_str())) {
+            entries.push(line[start..index].trim());
+            start = index + 1;
+        }
+    }
+    entries.push(line[start..].trim());
+    entries
+}
+
+fn parse_entry(raw: &str, prefixes: &[String]) -> Result<SaveQueryEntry, String> {
+    let (path, length) = raw.rsplit_once(':').ok_or("缺少长度")?;
+    let length = length.trim();
+    if length.is_empty() || !length.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("长度 \"{}\" 不是整数", length));
+    }
+    let length = length.parse::<u64>().map_err(|e| e.to_string())?;
+
+    let relative = prefixes
+        .iter()
+        .find_map(|prefix| path.strip_prefix(prefix.as_str()))
+        .ok_or("不在当前世界目录下")?;
+    let parts: Vec<&str> = relative.split(['/', '\\']).collect();
+    if parts.iter().any(|part| part.is_empty() || *part == "." || *part == "..") {
+        return Err("路径非法".to_string());
+    }
+    Ok(SaveQueryEntry { path: parts.join("/"), length })
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_world_names_with_commas_and_colons() {
+        let text = "Bedrock level, 1:2/db/000005.ldb:5000, Bedrock level, 1:2/db/CURRENT:16\nBedrock level, 1:2\\level.dat:2185\n";
+        let list = SaveQueryList::parse(text, "Bedrock level, 1:2").unwrap();
+        assert_eq!(list.entries, vec![
+            SaveQueryEntry { path: "db/000005.ldb".to_string(), length: 5000 },
+            SaveQueryEntry { path: "db/CURRENT".to_string(), length: 16 },
+            SaveQueryEntry { path: "level.dat".to_string(), length: 2185 },
+        ]);
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        for text in ["", "world/db/CURRENT", "world/db/CURRENT:-1", "other/db/CURRENT:16", "world/../x:1", "world/a:1, world/a:2"] {
+            let e = SaveQueryList::parse(text, "world").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::Deserialize;
+use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, Session, Sftp};
+use tracing::{info, warn};
+use crate::utils::download::PARTIAL_SUFFIX;
+use crate::utils::events::Stage;
+use crate::utils::storage::{Depth, RemoteEntry, StorageBackend};
+use crate::utils::upload::{with_retry, RetryPolicy, UploadError, UploadOptions};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+// 单次 SSH 操作的超时，超时后断开连接并按重试策略重新连接
+const SESSION_TIMEOUT_MS: u32 = 60_000;
+const BUFFER_SIZE: usize = 256 * 1024;
+// libssh2 的 LIBSSH2_FX_* 状态码
+const FX_NO_SUCH_FILE: i32 = 2;
+const FX_PERMISSION_DENIED: i32 = 3;
+const FX_NO_SUCH_PATH: i32 = 10;
+
+// SFTP 服务器的连接设置，设置了 key_file 时使用密钥认证，否则使用密码
+#[derive(Deserialize, Debug, Clone)]
+pub struct SftpTarget {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    // OpenSSH 或 PEM 格式的私钥文件
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub key_passphrase: Option<String>,
+    // 不设置时使用 ~/.ssh/known_hosts，主机密钥不在其中时拒绝连接
+    #[serde(default)]
+    pub known_hosts: Option<PathBuf>,
+}
+
+pub fn default_port() -> u16 {
+    22
+}
+
+pub struct SftpBackend {
+    target: SftpTarget,
+    // 远程目录，以 '/' 开头时是绝对路径，否则相对于登录后的目录
+    absolute: bool,
+    prefix: String,
+    retry: RetryPolicy,
+    // 空闲的连接，同时进行的操作各自取出一个连接，没有空闲连接时新建，会话出错的连接直接丢弃
+    idle: Arc<Mutex<Vec<Sftp>>>,
+}
+
+fn default_known_hosts() -> io::Result<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "找不到用户目录，请设置 known_hosts"))?;
+    Ok(PathBuf::from(home).join(".ssh").join("known_hosts"))
+}
+
+fn denied(message: impl Into<String>) -> UploadError {
+    io::Error::new(io::ErrorKind::PermissionDenied, message.into()).into()
+}
+
+// 会话层的错误（连接断开、超时等）可以重试，SFTP 返回的状态（文件不存在、没有权限等）不重试
+fn sftp_error(location: &str, error: ssh2::Error) -> UploadError {
+    match error.code() {
+        ErrorCode::Session(_) => UploadError::Network { url: location.to_string(), message: error.message().to_string(), attempts: 1 },
+        ErrorCode::SFTP(FX_PERMISSION_DENIED) => denied(format!("{}: {}", location, error.message())),
+        ErrorCode::SFTP(_) => io::Error::from(error).into(),
+    }
+}
+
+fn not_found(error: &ssh2::Error) -> bool {
+    matches!(error.code(), ErrorCode::SFTP(FX_NO_SUCH_FILE) | ErrorCode::SFTP(FX_NO_SUCH_PATH))
+}
+
+// 读写远程文件时的错误来自 SSH 通道，按连接错误处理
+fn transfer_error(location: &str, error: io::Error) -> UploadError {
+    UploadError::Network { url: location.to_string(), message: error.to_string(), attempts: 1 }
+}
+
+fn connect(target: &SftpTarget) -> Result<Sftp, UploadError> {
+    let address = format!("{}:{}", target.host, target.port);
+    let network = |message: String| UploadError::Network { url: format!("sftp://{}", address), message, attempts: 1 };
+
+    let socket_address = address
+        .to_socket_addrs()
+        .map_err(|e| network(e.to_string()))?
+        .next()
+        .ok_or_else(|| network(format!("无法解析 {}", target.host)))?;
+    let tcp = TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT).map_err(|e| network(e.to_string()))?;
+    let mut session = Session::new().map_err(|e| network(e.message().to_string()))?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(SESSION_TIMEOUT_MS);
+    session.handshake().map_err(|e| network(e.message().to_string()))?;
+
+    let known_hosts_path = match &target.known_hosts {
+        Some(path) => path.clone(),
+        None => default_known_hosts()?,
+    };
+    let mut known_hosts = session.known_hosts().map_err(|e| network(e.message().to_string()))?;
+    known_hosts
+        .read_file(&known_hosts_path, KnownHostFileKind::OpenSSH)
+        .map_err(|e| denied(format!("无法读取 known_hosts {}: {}", known_hosts_path.display(), e.message())))?;
+    let (key, _) = session.host_key().ok_or_else(|| network("服务器没有提供主机密钥".to_string()))?;
+    match known_hosts.check_port(&target.host, target.port, key) {
+        CheckResult::Match => {}
+        CheckResult::NotFound => return Err(denied(format!("{} 中没有 {} 的主机密钥", known_hosts_path.display(), address))),
+        CheckResult::Mismatch => return Err(denied(format!("{} 的主机密钥与 {} 中记录的不一致", address, known_hosts_path.display()))),
+        CheckResult::Failure => return Err(denied(format!("无法检查 {} 的主机密钥", address))),
+    }
+
+    let auth = match &target.key_file {
+        Some(key_file) => session.userauth_pubkey_file(&target.username, None, key_file, target.key_passphrase.as_deref()),
+        None if !target.password.is_empty() => session.userauth_password(&target.username, &target.password),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "SFTP 需要 password 或 key_file").into()),
+    };
+    if let Err(e) = auth {
+        return Err(denied(format!("{}@{} 认证失败: {}", target.username, address, e.message())));
+    }
+    session.sftp().map_err(|e| network(e.message().to_string()))
+}
+
+// 逐级创建目录，已存在的目录跳过
+fn make_dirs(sftp: &Sftp, location: &str, path: &str) -> Result<u32, UploadError> {
+    let mut created = 0;
+    let mut current = if path.starts_with('/') { "/".to_string() } else { String::new() };
+    for segment in path.split('/').filter(|segment| !segment.is_empty() && *segment != ".") {
+        current.push_str(segment);
+        if !matches!(sftp.stat(Path::new(&current)), Ok(stat) if stat.is_dir()) {
+            if let Err(e) = sftp.mkdir(Path::new(&current), 0o755) {
+                // 其他客户端可能同时创建了同一个目录
+                if !matches!(sftp.stat(Path::new(&current)), Ok(stat) if stat.is_dir()) {
+                    return Err(sftp_error(location, e));
+                }
+            }
+            created += 1;
+        }
+        current.push('/');
+    }
+    Ok(created)
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn modified_time(mtime: Option<u64>) -> Option<DateTime<Local>> {
+    DateTime::from_timestamp(mtime? as i64, 0).map(|time| time.with_timezone(&Local))
+}
+
+impl SftpBackend {
+    pub fn new(target: &SftpTarget, remote_path: &str, retry: &RetryPolicy) -> io::Result<Self> {
+        if target.host.is_empty() || target.username.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "SFTP 需要 host 和 username"));
+        }
+        Ok(SftpBackend {
+            target: target.clone(),
+            absolute: remote_path.starts_with('/'),
+            prefix: remote_path.split('/').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("/"),
+            retry: retry.clone(),
+            idle: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    // key 在服务器上的路径
+    fn remote_path(&self, key: &str) -> String {
+        let path = self.prefix.split('/').chain(key.split('/')).filter(|part| !part.is_empty()).collect::<Vec<_>>().join("/");
+        match (self.absolute, path.is_empty()) {
+            (true, _) => format!("/{}", path),
+            (false, true) => ".".to_string(),
+            (false, false) => path,
+        }
+    }
+
+    fn url_for(&self, path: &str) -> String {
+        let base = format!("sftp://{}@{}:{}", self.target.username, self.target.host, self.target.port);
+        match path.strip_prefix('/') {
+            Some(path) => format!("{}/{}", base, path),
+            None => format!("{}/~/{}", base, path.trim_start_matches("./")),
+        }
+    }
+
+    // 在阻塞线程中用一个空闲连接执行 operation，会话出错时丢弃连接，按重试策略重新连接后再执行
+    async fn run<T, F>(&self, location: &str, operation: F) -> Result<(T, u32), UploadError>
+    where
+        T: Send + 'static,
+        F: Fn(&Sftp) -> Result<T, UploadError> + Send + Sync + 'static,
+    {
+        let operation = Arc::new(operation);
+        with_retry(&self.retry, location, || {
+            let operation = operation.clone();
+            let idle = self.idle.clone();
+            let target = self.target.clone();
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let pooled = idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+                    let sftp = match pooled {
+                        Some(sftp) => sftp,
+                        None => connect(&target)?,
+                    };
+                    let result = operation(&sftp);
+                    if !matches!(result, Err(UploadError::Network { .. })) {
+                        idle.lock().unwrap_or_else(|e| e.into_inner()).push(sftp);
+                    }
+                    result
+                })
+                .await
+                .map_err(io::Error::other)?
+            }
+        })
+        .await
+    }
+
+    fn entry(&self, key: &str, path: &str, stat: &ssh2::FileStat) -> RemoteEntry {
+        let relative = key.trim_matches('/');
+        RemoteEntry {
+            name: relative.rsplit('/').next().unwrap_or(relative).to_string(),
+            path: relative.to_string(),
+            href: self.url_for(path),
+            size: if stat.is_dir() { None } else { stat.size },
+            last_modified: modified_time(stat.mtime),
+            etag: None,
+            sha256: None,
+            is_collection: stat.is_dir(),
+        }
+    }
+}
+
+impl StorageBackend for SftpBackend {
+    fn location(&self, key: &str) -> String {
+        self.url_for(&self.remote_path(key))
+    }
+
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    // 先写入同目录下的临时文件，完成后改名，其他客户端不会看到不完整的文件
+    // SFTP 没有保存校验和的地方，上传后只能比较大小
+    fn put<'a>(&'a self, file: &'a Path, key: &'a str, _sha256: &'a str, _options: &'a UploadOptions, stage: &'a Stage) -> BoxFuture<'a, Result<u32, UploadError>> {
+        async move {
+            let path = self.remote_path(key);
+            let parent = parent_of(&path).to_string();
+            let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+            let temp = if parent.is_empty() && !path.starts_with('/') { format!(".{}{}", name, PARTIAL_SUFFIX) } else { format!("{}/.{}{}", parent, name, PARTIAL_SUFFIX) };
+            let location = self.location(key);
+            let local = file.to_path_buf();
+            let stage = stage.clone();
+
+            let (_, attempts) = self
+                .run(&location.clone(), move |sftp| {
+                    make_dirs(sftp, &location, &parent)?;
+                    let mut source = File::open(&local)?;
+                    let mut remote = sftp.create(Path::new(&temp)).map_err(|e| sftp_error(&location, e))?;
+                    let mut buffer = vec![0; BUFFER_SIZE];
+                    let mut written = 0;
+                    let copied: Result<(), UploadError> = (|| {
+                        loop {
+                            let read = source.read(&mut buffer)?;
+                            if read == 0 {
+                                break;
+                            }
+                            remote.write_all(&buffer[..read]).map_err(|e| transfer_error(&location, e))?;
+                            written += read as u64;
+                            stage.add_bytes(read as u64);
+                        }
+                        remote.close().map_err(|e| sftp_error(&location, e))
+                    })();
+                    if let Err(e) = copied {
+                        // 重试时从头上传
+                        stage.rewind_bytes(written);
+                        return Err(e);
+                    }
+
+                    // OpenSSH 的 SFTP 不能覆盖已有的文件，改名失败时先删除旧文件
+                    if let Err(e) = sftp.rename(Path::new(&temp), Path::new(&path), None) {
+                        if sftp.stat(Path::new(&path)).is_err() {
+                            return Err(sftp_error(&location, e));
+                        }
+                        sftp.unlink(Path::new(&path)).map_err(|e| sftp_error(&location, e))?;
+                        sftp.rename(Path::new(&temp), Path::new(&path), None).map_err(|e| sftp_error(&location, e))?;
+                    }
+                    Ok(())
+                })
+                .await?;
+            info!("文件上传成功: {}", file.display());
+            Ok(attempts)
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str, destination: &'a Path, stage: &'a Stage) -> BoxFuture<'a, Result<u32, UploadError>> {
+        async move {
+            let path = self.remote_path(key);
+            let location = self.location(key);
+            let part = destination.to_path_buf();
+            let stage = stage.clone();
+
+            let (_, attempts) = self
+                .run(&location.clone(), move |sftp| {
+                    let mut remote = sftp.open(Path::new(&path)).map_err(|e| sftp_error(&location, e))?;
+                    let size = remote.stat().map_err(|e| sftp_error(&location, e))?.size.unwrap_or(0);
+                    let mut offset = fs::metadata(&part).map_or(0, |metadata| metadata.len());
+                    if offset > size {
+                        warn!("本地已有的部分比远程文件大，从头开始下载");
+                        stage.rewind_bytes(offset);
+                        offset = 0;
+                    }
+                    let mut local = if offset > 0 { OpenOptions::new().append(true).open(&part)? } else { File::create(&part)? };
+                    remote.seek(SeekFrom::Start(offset)).map_err(|e| transfer_error(&location, e))?;
+
+                    let mut buffer = vec![0; BUFFER_SIZE];
+                    loop {
+                        let read = remote.read(&mut buffer).map_err(|e| transfer_error(&location, e))?;
+                        if read == 0 {
+                            break;
+                        }
+                        local.write_all(&buffer[..read])?;
+                        stage.add_bytes(read as u64);
+                    }
+                    local.sync_all()?;
+                    Ok(())
+                })
+                .await?;
+            Ok(attempts)
+        }
+        .boxed()
+    }
+
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, UploadError>> {
+        async move {
+            let path = self.remote_path(key);
+            let location = self.location(key);
+            let (data, _) = self
+                .run(&location.clone(), move |sftp| {
+                    let mut remote = match sftp.open(Path::new(&path)) {
+                        Ok(remote) => remote,
+                        Err(e) if not_found(&e) => return Ok(None),
+                        Err(e) => return Err(sftp_error(&location, e)),
+                    };
+                    let mut data = Vec::new();
+                    remote.read_to_end(&mut data).map_err(|e| transfer_error(&location, e))?;
+                    Ok(Some(data))
+                })
+                .await?;
+            Ok(data)
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, key: &'a str, depth: Depth) -> BoxFuture<'a, Result<Vec<RemoteEntry>, UploadError>> {
+        async move {
+            let root = self.remote_path(key);
+            let location = self.location(key);
+            let (found, _) = self
+                .run(&location.clone(), move |sftp| {
+                    // (相对路径, 远程路径, 属性)，按目录逐层展开
+                    let mut found = Vec::new();
+                    let mut pending = vec![(String::new(), root.clone())];
+                    while let Some((relative, dir)) = pending.pop() {
+                        for (child, stat) in sftp.readdir(Path::new(&dir)).map_err(|e| sftp_error(&location, e))? {
+                            let Some(name) = child.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+                                continue;
+                            };
+                            let child_relative = if relative.is_empty() { name.clone() } else { format!("{}/{}", relative, name) };
+                            let child_path = format!("{}/{}", dir.trim_end_matches('/'), name);
+                            if stat.is_dir() && depth == Depth::Infinity {
+                                pending.push((child_relative.clone(), child_path.clone()));
+                            }
+                            found.push((child_relative, child_path, stat));
+                        }
+                    }
+                    Ok(found)
+                })
+                .await?;
+
+            let mut entries: Vec<RemoteEntry> = found.iter().map(|(relative, path, stat)| self.entry(relative, path, stat)).collect();
+            entries.sort_by(|a, b| a.path.cmp(&b.path));
+            Ok(entries)
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), UploadError>> {
+        async move {
+            let path = self.remote_path(key);
+            let location = self.location(key);
+            self.run(&location.clone(), move |sftp| match sftp.unlink(Path::new(&path)) {
+                Err(e) if !not_found(&e) => Err(sftp_error(&location, e)),
+                _ => Ok(()),
+            })
+            .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn stat<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<RemoteEntry>, UploadError>> {
+        async move {
+            let path = self.remote_path(key);
+            let location = self.location(key);
+            let lookup = path.clone();
+            let (stat, _) = self
+                .run(&location.clone(), move |sftp| match sftp.stat(Path::new(&lookup)) {
+                    Ok(stat) => Ok(Some(stat)),
+                    Err(e) if not_found(&e) => Ok(None),
+                    Err(e) => Err(sftp_error(&location, e)),
+                })
+                .await?;
+            Ok(stat.map(|stat| self.entry(key, &path, &stat)))
+        }
+        .boxed()
+    }
+
+    fn create_dir<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<u32, UploadError>> {
+        async move {
+            let path = self.remote_path(key);
+            let location = self.location(key);
+            let (_, attempts) = self.run(&location.clone(), move |sftp| make_dirs(sftp, &location, &path)).await?;
+            Ok(attempts)
+        }
+        .boxed()
+    }
+}
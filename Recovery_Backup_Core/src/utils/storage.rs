@@ -0,0 +1,123 @@
+use std::io;
+use std::path::Path;
+use chrono::{DateTime, Local};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use crate::utils::events::Stage;
+use crate::utils::s3::{S3Backend, S3Target};
+use crate::utils::sftp::{SftpBackend, SftpTarget};
+use crate::utils::upload::{RetryPolicy, UploadError, UploadOptions};
+use crate::utils::webdav::WebDavBackend;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Depth {
+    // 只列出目录本身的直接子项
+    #[default]
+    #[serde(rename = "1")]
+    One,
+    #[serde(rename = "infinity")]
+    Infinity,
+}
+
+impl Depth {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "1" => Some(Self::One),
+            "infinity" => Some(Self::Infinity),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RemoteEntry {
+    pub name: String,
+    // 相对于列出的目录，使用 '/' 分隔
+    pub path: String,
+    pub href: String,
+    pub size: Option<u64>,
+    pub last_modified: Option<DateTime<Local>>,
+    pub etag: Option<String>,
+    // 上传时随文件保存的 SHA-256，服务器不支持时为 None
+    pub sha256: Option<String>,
+    pub is_collection: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RemoteListing {
+    pub url: String,
+    pub depth: Depth,
+    // 按路径排序，不包含目录本身
+    pub entries: Vec<RemoteEntry>,
+}
+
+// 远程存储的统一接口，key 是相对于远程目录的路径，使用 '/' 分隔，空字符串表示远程目录本身
+pub trait StorageBackend: Send + Sync {
+    // key 的完整地址，用于日志和报告
+    fn location(&self, key: &str) -> String;
+
+    // 配置的远程目录，为空表示整个存储
+    fn prefix(&self) -> &str;
+
+    // 上传本地文件，服务器支持时同时保存文件的 SHA-256，返回请求的尝试次数
+    fn put<'a>(&'a self, file: &'a Path, key: &'a str, sha256: &'a str, options: &'a UploadOptions, stage: &'a Stage) -> BoxFuture<'a, Result<u32, UploadError>>;
+
+    // 下载到 destination，文件已存在时从已有的长度继续，返回请求的尝试次数
+    fn get<'a>(&'a self, key: &'a str, destination: &'a Path, stage: &'a Stage) -> BoxFuture<'a, Result<u32, UploadError>>;
+
+    // 读取清单之类的小文件，不存在时返回 None
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, UploadError>>;
+
+    // 列出 key 下的文件和目录，按路径排序
+    fn list<'a>(&'a self, key: &'a str, depth: Depth) -> BoxFuture<'a, Result<Vec<RemoteEntry>, UploadError>>;
+
+    // 删除文件，不存在时也视为成功
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), UploadError>>;
+
+    // 文件的大小和修改时间，不存在时返回 None
+    fn stat<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<RemoteEntry>, UploadError>>;
+
+    // 创建目录，返回请求的尝试次数，没有目录概念的存储不需要实现
+    fn create_dir<'a>(&'a self, _key: &'a str) -> BoxFuture<'a, Result<u32, UploadError>> {
+        async { Ok(0) }.boxed()
+    }
+}
+
+// 远程存储的连接设置，设置了 s3 或 sftp 时使用对应的存储，否则使用 WebDAV
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RemoteSource {
+    // WebDAV、SFTP 中的目录或 S3 中的键前缀
+    pub remote_path: String,
+    pub webdav_url: String,
+    pub username: String,
+    pub password: String,
+    pub allow_insecure: bool,
+    pub s3: Option<S3Target>,
+    pub sftp: Option<SftpTarget>,
+}
+
+impl RemoteSource {
+    pub fn open(&self, retry: &RetryPolicy) -> io::Result<Box<dyn StorageBackend>> {
+        if let Some(s3) = &self.s3 {
+            return Ok(Box::new(S3Backend::new(s3, &self.remote_path, self.allow_insecure, retry)?));
+        }
+        if let Some(sftp) = &self.sftp {
+            return Ok(Box::new(SftpBackend::new(sftp, &self.remote_path, retry)?));
+        }
+        if self.webdav_url.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "没有设置 webdav_url、s3 或 sftp"));
+        }
+        Ok(Box::new(WebDavBackend::new(&self.webdav_url, &self.remote_path, &self.username, &self.password, self.allow_insecure, retry)?))
+    }
+}
+
+// 列出远程目录下的文件和目录
+pub async fn list_remote(backend: &dyn StorageBackend, depth: Depth) -> Result<RemoteListing, UploadError> {
+    let entries = backend.list("", depth).await?;
+    let url = backend.location("");
+    info!("远程目录 {} 中有 {} 项", url, entries.len());
+    Ok(RemoteListing { url, depth, entries })
+}
@@ -1,65 +1,381 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::path::Path;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, Local};
 use futures::future::BoxFuture;
-use futures::FutureExt;
+use futures::{stream, FutureExt, StreamExt};
 use reqwest::Client;
-use tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+use crate::utils::chunked::{is_upload_state_path, UploadStrategy, DEFAULT_CHUNK_SIZE};
+use crate::utils::crypto::{encrypt_file, encrypted_from, encrypted_key, encrypted_path_for, is_encrypted, EncryptionKey, ENCRYPTED_SUFFIX};
+use crate::utils::download::PARTIAL_SUFFIX;
+use crate::utils::events::Stage;
+use crate::utils::manifest::is_manifest_path;
+use crate::utils::stats::get_directory_stats_sync;
+use crate::utils::storage::{RemoteEntry, StorageBackend};
 
-pub async fn upload_file(client: &Client, file_path: &Path, url: &str, username: &str, password: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let file = File::open(file_path)?;
-    let file_stream = reqwest::Body::wrap_stream(tokio_util::codec::FramedRead::new(tokio::fs::File::from_std(file), tokio_util::codec::BytesCodec::new()));
+// 错误信息中保留的响应正文长度
+const MAX_ERROR_BODY: usize = 512;
 
-    let res = client
-        .put(url)
-        .basic_auth(username, Some(password))
-        .body(file_stream)
-        .send()
-        .await?;
+// 网络错误和 5xx 响应的重试策略，等待时间按 multiplier 指数增长，不超过 max_delay_ms
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryPolicy {
+    // 包括第一次在内的最多尝试次数
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    // 在 [delay/2, delay] 之间随机等待，避免多个客户端同时重试
+    pub jitter: bool,
+}
 
-    if res.status().is_success() {
-        info!("文件上传成功: {}", file_path.display());
-    } else {
-        error!("文件上传失败: {}", res.status());
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 4, initial_delay_ms: 1000, max_delay_ms: 30_000, multiplier: 2.0, jitter: true }
+    }
+}
+
+// 上传方式和重试设置
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UploadOptions {
+    pub retry: RetryPolicy,
+    pub strategy: UploadStrategy,
+    // 分块上传时每块的字节数
+    pub chunk_size: u64,
+    // 上传目录时同时上传的文件数
+    pub concurrency: usize,
+    // 远程已有大小和校验和（或修改时间）相同的文件时不再上传
+    pub skip_unchanged: bool,
+    // 设置时上传前加密，远程文件名加上 .enc，已经加密的文件原样上传
+    #[serde(skip)]
+    pub encryption: Option<EncryptionKey>,
+}
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions { retry: RetryPolicy::default(), strategy: UploadStrategy::Single, chunk_size: DEFAULT_CHUNK_SIZE, concurrency: DEFAULT_CONCURRENCY, skip_unchanged: true, encryption: None }
+    }
+}
+
+impl RetryPolicy {
+    // 第 attempt 次失败后的等待时间，attempt 从 1 开始
+    fn delay(&self, attempt: u32) -> Duration {
+        let base = self.initial_delay_ms as f64 * self.multiplier.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        let delay = base.min(self.max_delay_ms as f64) as u64;
+        if !self.jitter || delay < 2 {
+            return Duration::from_millis(delay);
+        }
+        let random = RandomState::new().build_hasher().finish();
+        Duration::from_millis(delay / 2 + random % (delay - delay / 2 + 1))
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    // 服务器返回了非 2xx 状态
+    Status { url: String, status: u16, body: String, attempts: u32 },
+    // 连接失败、超时等没有拿到响应的错误
+    Network { url: String, message: String, attempts: u32 },
+    Io(io::Error),
+    // 目录中有文件上传失败，其余文件已经上传
+    Incomplete(Box<UploadSummary>),
+}
+
+impl UploadError {
+    fn retryable(&self) -> bool {
+        match self {
+            UploadError::Status { status, .. } => *status >= 500,
+            UploadError::Network { .. } => true,
+            UploadError::Io(_) | UploadError::Incomplete(_) => false,
+        }
+    }
+
+    pub fn attempts(&self) -> u32 {
+        match self {
+            UploadError::Status { attempts, .. } | UploadError::Network { attempts, .. } => *attempts,
+            UploadError::Io(_) => 0,
+            UploadError::Incomplete(summary) => summary.attempts,
+        }
+    }
+
+    // 输出到结果中的数据，目录上传未完成时包含每个文件的结果
+    pub fn report(&self) -> serde_json::Value {
+        match self {
+            UploadError::Incomplete(summary) => serde_json::to_value(summary).unwrap_or_default(),
+            _ => serde_json::json!({ "attempts": self.attempts() }),
+        }
+    }
+
+    fn with_attempts(mut self, count: u32) -> Self {
+        if let UploadError::Status { attempts, .. } | UploadError::Network { attempts, .. } = &mut self {
+            *attempts = count;
+        }
+        self
+    }
+
+    pub(crate) fn network(url: &str, error: reqwest::Error) -> Self {
+        UploadError::Network { url: url.to_string(), message: error.to_string(), attempts: 1 }
+    }
+
+    pub(crate) async fn status(url: &str, response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let mut body = response.text().await.unwrap_or_default().trim().to_string();
+        if body.len() > MAX_ERROR_BODY {
+            let mut end = MAX_ERROR_BODY;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+            body.push_str("...");
+        }
+        UploadError::Status { url: url.to_string(), status, body, attempts: 1 }
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::Status { url, status, body, attempts } => {
+                write!(f, "请求 {} 失败: HTTP {}，共尝试 {} 次", url, status, attempts)?;
+                if !body.is_empty() {
+                    write!(f, ": {}", body)?;
+                }
+                Ok(())
+            }
+            UploadError::Network { url, message, attempts } => write!(f, "请求 {} 失败: {}，共尝试 {} 次", url, message, attempts),
+            UploadError::Io(e) => write!(f, "{}", e),
+            UploadError::Incomplete(summary) => {
+                write!(f, "{} 个文件上传失败，{} 个已上传，{} 个跳过", summary.failed.len(), summary.files, summary.skipped)?;
+                if let Some(first) = summary.failed.first() {
+                    write!(f, ": {}: {}", first.path, first.error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+impl From<io::Error> for UploadError {
+    fn from(e: io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+// 一次上传的结果，attempts 为所有请求的尝试次数之和
+#[derive(Serialize, Debug, Default)]
+pub struct UploadSummary {
+    // 已上传的文件数和字节数
+    pub files: u64,
+    pub bytes: u64,
+    pub attempts: u32,
+    // 上传进度文件等没有上传的文件
+    pub skipped: u64,
+    // 远程已有相同文件而没有上传的文件数
+    pub unchanged: u64,
+    // 上传后远程校验和与本地一致的文件数，其余文件只比较了大小
+    pub verified: u64,
+    pub failed: Vec<FailedUpload>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FailedUpload {
+    pub path: String,
+    pub error: String,
+}
+
+// 执行 request 直到成功、遇到不可重试的错误或用完尝试次数，返回结果和尝试次数
+pub(crate) async fn with_retry<T, F, Fut>(policy: &RetryPolicy, url: &str, mut request: F) -> Result<(T, u32), UploadError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, UploadError>>,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Ok(value) => return Ok((value, attempt)),
+            Err(e) if e.retryable() && attempt < max_attempts => {
+                let delay = policy.delay(attempt);
+                warn!("{} 第 {} 次请求失败，{} ms 后重试: {}", url, attempt, delay.as_millis(), e.with_attempts(attempt));
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.with_attempts(attempt)),
+        }
+    }
+}
+
+// 文件内容的 SHA-256，小写十六进制
+pub(crate) fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// 远程文件与本地相同：大小一致，且校验和一致；服务器没有保存校验和时要求远程文件不早于本地文件
+fn unchanged(entry: &RemoteEntry, size: u64, modified: Option<SystemTime>, sha256: &str) -> bool {
+    if entry.is_collection || entry.size != Some(size) {
+        return false;
+    }
+    if let Some(remote) = &entry.sha256 {
+        return remote == sha256;
+    }
+    match (entry.last_modified, modified) {
+        // HTTP 的 Last-Modified 只精确到秒
+        (Some(remote), Some(local)) => remote.timestamp() >= DateTime::<Local>::from(local).timestamp(),
+        _ => false,
+    }
+}
+
+// 上传结束后删除的临时文件
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
     }
+}
 
-    Ok(())
+// 单个文件的上传结果
+enum FileUpload {
+    // 上传的尝试次数，以及远程校验和是否已经核对
+    Uploaded { attempts: u32, verified: bool },
+    Unchanged,
 }
 
-pub fn upload_directory<'a>(client: &'a Client, dir_path: &'a Path, base_url: &'a str, remote_path: &'a str, username: &'a str, password: &'a str) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+// 上传一个文件并核对远程文件的大小和校验和，不一致时视为上传失败
+async fn upload_file(backend: &dyn StorageBackend, path: &Path, key: &str, options: &UploadOptions, stage: &Stage) -> Result<FileUpload, UploadError> {
+    let metadata = fs::metadata(path)?;
+    let mut sha256 = file_sha256(path)?;
+
+    // 加密后的文件保存在旁边的 "<文件>.enc"，再次上传和续传时直接使用，内容不变时不重新加密；
+    // 清单同样加密，它很小，每次加密到临时文件，远程名称与加密后的压缩包对应
+    let mut encrypted = None;
+    let mut manifest = None;
+    let mut key = key.to_string();
+    if let Some(encryption) = &options.encryption {
+        if is_manifest_path(path) {
+            let mut temp = encrypted_path_for(path).into_os_string();
+            temp.push(PARTIAL_SUFFIX);
+            let temp = TempFile(PathBuf::from(temp));
+            encrypt_file(path, &temp.0, encryption, &sha256)?;
+            sha256 = file_sha256(&temp.0)?;
+            key = encrypted_key(&key);
+            manifest = Some(temp);
+        } else if !is_encrypted(path)? {
+            let target = encrypted_path_for(path);
+            if !encrypted_from(&target, encryption, &sha256, metadata.len())? {
+                let mut temp = target.clone().into_os_string();
+                temp.push(PARTIAL_SUFFIX);
+                let temp = TempFile(PathBuf::from(temp));
+                encrypt_file(path, &temp.0, encryption, &sha256)?;
+                fs::rename(&temp.0, &target)?;
+            }
+            sha256 = file_sha256(&target)?;
+            key = encrypted_key(&key);
+            encrypted = Some(target);
+        }
+    }
+    let path = manifest.as_ref().map(|temp| temp.0.as_path()).or(encrypted.as_deref()).unwrap_or(path);
+    let key = key.as_str();
+    let size = fs::metadata(path)?.len();
+
+    if options.skip_unchanged {
+        match backend.stat(key).await {
+            Ok(Some(entry)) if unchanged(&entry, size, metadata.modified().ok(), &sha256) => {
+                info!("远程文件没有变化，跳过: {}", backend.location(key));
+                stage.add_bytes(size);
+                return Ok(FileUpload::Unchanged);
+            }
+            Ok(_) => {}
+            // 无法确定时照常上传
+            Err(e) => warn!("读取远程文件 {} 的信息失败: {}", backend.location(key), e),
+        }
+    }
+
+    let attempts = backend.put(path, key, &sha256, options, stage).await?;
+    let mismatch = |message: String| -> UploadError { io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", backend.location(key), message)).into() };
+    let entry = match backend.stat(key).await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return Err(mismatch("上传后远程文件不存在".to_string())),
+        // 服务器不支持 HEAD 等无法核对的情况只记录警告
+        Err(e) => {
+            warn!("无法核对上传的文件 {}: {}", backend.location(key), e);
+            return Ok(FileUpload::Uploaded { attempts, verified: false });
+        }
+    };
+    if let Some(remote_size) = entry.size {
+        if remote_size != size {
+            return Err(mismatch(format!("远程文件大小 {} 与本地文件大小 {} 不一致", remote_size, size)));
+        }
+    }
+    let verified = match &entry.sha256 {
+        Some(remote) if *remote != sha256 => return Err(mismatch(format!("远程文件的 SHA-256 {} 与本地文件 {} 不一致", remote, sha256))),
+        Some(_) => true,
+        None => false,
+    };
+    Ok(FileUpload::Uploaded { attempts, verified })
+}
+
+impl UploadSummary {
+    fn add(&mut self, upload: FileUpload, size: u64) {
+        match upload {
+            FileUpload::Uploaded { attempts, verified } => {
+                self.attempts += attempts;
+                self.files += 1;
+                self.bytes += size;
+                self.verified += verified as u64;
+            }
+            FileUpload::Unchanged => self.unchanged += 1,
+        }
+    }
+}
+
+// 递归列出目录中要上传的文件，按顺序创建远程目录，返回 (本地路径, key)
+fn collect_directory<'a>(backend: &'a dyn StorageBackend, dir_path: &'a Path, prefix: String, summary: &'a mut UploadSummary, files: &'a mut Vec<(PathBuf, String)>) -> BoxFuture<'a, Result<(), UploadError>> {
     async move {
-        let entries = fs::read_dir(dir_path)?;
+        let mut entries = fs::read_dir(dir_path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
 
         for entry in entries {
-            let entry = entry?;
             let path = entry.path();
-            let relative_path = path.strip_prefix(dir_path)?;
-            let remote_path_str = remote_path.strip_prefix('/').unwrap_or(remote_path);
-            let remote_file_url = if path.is_file() {
-                format!("{}/{}/{}", base_url.strip_suffix('/').unwrap_or(base_url), remote_path_str, relative_path.display())
-            } else {
-                format!("{}/{}/{}", base_url.strip_suffix('/').unwrap_or(base_url), remote_path_str, relative_path.display())
-            };
+            let key = format!("{}{}", prefix, entry.file_name().to_string_lossy());
 
+            // 未完成的下载和加密的临时文件也不上传
+            if is_upload_state_path(&path) || key.ends_with(PARTIAL_SUFFIX) {
+                summary.skipped += 1;
+                continue;
+            }
             if path.is_file() {
-                info!("上传文件: {}", remote_file_url); // 调试信息
-                upload_file(client, &path, &remote_file_url, username, password).await?;
+                files.push((path, key));
             } else if path.is_dir() {
-                // 创建远程目录
-                let res = client
-                    .request(reqwest::Method::from_bytes(b"MKCOL")?, &remote_file_url)
-                    .basic_auth(username, Some(password))
-                    .send()
-                    .await?;
-
-                if res.status().is_success() {
-                    info!("目录创建成功: {}", remote_file_url);
-                } else {
-                    error!("目录创建失败: {}", res.status());
-                }
-
-                // 递归上传子目录内容
-                upload_directory(client, &path, base_url, &remote_file_url.strip_prefix(base_url).unwrap_or(&remote_file_url), username, password).await?;
+                summary.attempts += backend.create_dir(&key).await?;
+                collect_directory(backend, &path, format!("{}/", key), summary, files).await?;
+            } else {
+                warn!("跳过无法上传的路径: {}", path.display());
+                summary.skipped += 1;
             }
         }
 
@@ -67,27 +383,177 @@ pub fn upload_directory<'a>(client: &'a Client, dir_path: &'a Path, base_url: &'
     }.boxed()
 }
 
-pub async fn upload_backup(file_path: &Path, webdav_url: &str, remote_path: &str, username: &str, password: &str, allow_insecure: bool) -> Result<(), Box<dyn std::error::Error>> {
-    // 允许不安全的 HTTPS 连接（根据参数决定）
-    let client_builder = reqwest::Client::builder();
+// 最多同时上传 options.concurrency 个文件，单个文件失败不影响其他文件，全部结束后有失败时返回 Incomplete
+async fn upload_directory(backend: &dyn StorageBackend, dir_path: &Path, options: &UploadOptions, stage: &Stage, mut summary: UploadSummary) -> Result<UploadSummary, UploadError> {
+    let mut files = Vec::new();
+    collect_directory(backend, dir_path, String::new(), &mut summary, &mut files).await?;
+    // 加密上传时 "<文件>.enc" 是上次加密留下的，与对应的文件一起上传
+    if options.encryption.is_some() {
+        let paths: HashSet<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+        let before = files.len();
+        files.retain(|(path, _)| {
+            let plain = path.to_string_lossy().strip_suffix(ENCRYPTED_SUFFIX).map(PathBuf::from);
+            !plain.is_some_and(|plain| paths.contains(&plain))
+        });
+        summary.skipped += (before - files.len()) as u64;
+    }
+
+    let mut uploads = stream::iter(files)
+        .map(|(path, key)| async move {
+            info!("上传文件: {}", backend.location(&key)); // 调试信息
+            let result = upload_file(backend, &path, &key, options, stage).await;
+            (path, key, result)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+    while let Some((path, key, result)) = uploads.next().await {
+        match result {
+            Ok(upload) => {
+                summary.add(upload, fs::metadata(&path)?.len());
+                stage.file_done(&path.to_string_lossy(), 0);
+            }
+            Err(e) => {
+                error!("上传 {} 失败: {}", path.display(), e);
+                summary.attempts += e.attempts();
+                summary.failed.push(FailedUpload { path: key, error: e.to_string() });
+            }
+        }
+    }
+
+    if !summary.failed.is_empty() {
+        summary.failed.sort_by(|a, b| a.path.cmp(&b.path));
+        return Err(UploadError::Incomplete(Box::new(summary)));
+    }
+    Ok(summary)
+}
 
-    let client = if allow_insecure {
-        client_builder.danger_accept_invalid_certs(true).build()?
+// 允许不安全的 HTTPS 连接（根据参数决定）
+pub(crate) fn build_client(allow_insecure: bool) -> io::Result<Client> {
+    let client_builder = reqwest::Client::builder();
+    if allow_insecure {
+        client_builder.danger_accept_invalid_certs(true).build()
     } else {
-        client_builder.build()?
-    };
+        client_builder.build()
+    }
+    .map_err(io::Error::other)
+}
+
+pub async fn upload_backup(file_path: &Path, backend: &dyn StorageBackend, options: &UploadOptions) -> Result<UploadSummary, UploadError> {
+    let mut summary = UploadSummary::default();
+    if !file_path.is_file() && !file_path.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "提供的路径无效").into());
+    }
+    // 像 mkdir -p 一样先创建远程目录本身
+    summary.attempts += backend.create_dir("").await?;
 
     if file_path.is_file() {
         // 如果是文件，上传文件
-        let file_name = file_path.file_name().unwrap().to_str().unwrap();
-        let remote_file_url = format!("{}/{}", webdav_url.trim_end_matches('/'), format!("{}/{}", remote_path.trim_start_matches('/'), file_name));
-        info!("准备上传文件到: {}", remote_file_url); // 调试信息
-        upload_file(&client, file_path, &remote_file_url, username, password).await
-    } else if file_path.is_dir() {
+        let file_name = file_path.file_name().unwrap().to_string_lossy();
+        info!("准备上传文件到: {}", backend.location(&file_name)); // 调试信息
+        let size = fs::metadata(file_path)?.len();
+        let stage = Stage::start("upload", size, 1);
+        let result = upload_file(backend, file_path, &file_name, options, &stage).await;
+        if result.is_ok() {
+            stage.file_done(&file_name, 0);
+        }
+        stage.finish(&result);
+        summary.add(result?, size);
+    } else {
         // 如果是目录，上传目录内容
         info!("准备上传目录: {}", file_path.display()); // 调试信息
-        upload_directory(&client, file_path, webdav_url, remote_path, username, password).await
-    } else {
-        Err("提供的路径无效".into())
+        let (bytes_total, files_total) = get_directory_stats_sync(file_path)?;
+        let stage = Stage::start("upload", bytes_total, files_total);
+        let result = upload_directory(backend, file_path, options, &stage, summary).await;
+        stage.finish(&result);
+        summary = result?;
+        info!("目录上传完成: {} 个文件，{} 个没有变化，跳过 {} 个", summary.files, summary.unchanged, summary.skipped);
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn status(code: u16) -> UploadError {
+        UploadError::Status { url: "http://example.com/a".to_string(), status: code, body: String::new(), attempts: 1 }
+    }
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, initial_delay_ms: 1, max_delay_ms: 1, multiplier: 2.0, jitter: false }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max() {
+        let policy = RetryPolicy { jitter: false, ..RetryPolicy::default() };
+        let delays: Vec<u64> = (1..=7).map(|attempt| policy.delay(attempt).as_millis() as u64).collect();
+        assert_eq!(delays, [1000, 2000, 4000, 8000, 16_000, 30_000, 30_000]);
+        // multiplier 小于 1 时按 1 处理，等待时间不会缩短
+        let policy = RetryPolicy { multiplier: 0.5, jitter: false, ..RetryPolicy::default() };
+        assert_eq!(policy.delay(3), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_stays_within_half_to_full_delay() {
+        let policy = RetryPolicy::default();
+        for attempt in 1..=6 {
+            let full = RetryPolicy { jitter: false, ..policy.clone() }.delay(attempt);
+            let delay = policy.delay(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{:?} {:?}", delay, full);
+        }
+    }
+
+    #[test]
+    fn classifies_retryable_errors() {
+        assert!(status(500).retryable() && status(503).retryable());
+        assert!(!status(404).retryable() && !status(401).retryable() && !status(409).retryable());
+        assert!(UploadError::Network { url: String::new(), message: "timeout".to_string(), attempts: 1 }.retryable());
+        assert!(!UploadError::Io(io::Error::other("disk")).retryable());
+        assert!(!UploadError::Incomplete(Box::default()).retryable());
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_success() {
+        let calls = Cell::new(0);
+        let (value, attempts) = with_retry(&fast_policy(4), "u", || {
+            calls.set(calls.get() + 1);
+            let call = calls.get();
+            async move { if call < 3 { Err(status(502)) } else { Ok(call) } }
+        })
+        .await
+        .unwrap();
+        assert_eq!((value, attempts), (3, 3));
+    }
+
+    #[tokio::test]
+    async fn stops_on_client_errors_and_after_max_attempts() {
+        let calls = Cell::new(0);
+        let e = with_retry(&fast_policy(4), "u", || {
+            calls.set(calls.get() + 1);
+            async { Err::<(), _>(status(403)) }
+        })
+        .await
+        .unwrap_err();
+        assert_eq!((calls.get(), e.attempts()), (1, 1));
+
+        calls.set(0);
+        let e = with_retry(&fast_policy(3), "u", || {
+            calls.set(calls.get() + 1);
+            async { Err::<(), _>(status(500)) }
+        })
+        .await
+        .unwrap_err();
+        assert_eq!((calls.get(), e.attempts()), (3, 3));
+        assert!(e.to_string().contains("共尝试 3 次"), "{}", e);
+
+        // max_attempts 为 0 时仍然请求一次
+        calls.set(0);
+        with_retry(&fast_policy(0), "u", || {
+            calls.set(calls.get() + 1);
+            async { Err::<(), _>(status(500)) }
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(calls.get(), 1);
     }
 }
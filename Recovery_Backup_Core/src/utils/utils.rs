@@ -2,14 +2,14 @@ use std::io;
 
 // 函数：判断字符串是否是 Base64 编码
 pub fn is_base64_encoded(input: &str) -> bool {
-    input.len() % 4 == 0 && input.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
+    input.len().is_multiple_of(4) && input.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
 }
 
 pub fn send_request(url: &str, auth: Option<&str>) -> io::Result<String> {
     let client = reqwest::blocking::Client::builder()
         .timeout(std::time::Duration::from_secs(1)) // 限制超时时间为1秒
         .build()
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        .map_err(|err| io::Error::other(err.to_string()))?;
 
     let mut request = client.get(url);
 
@@ -19,8 +19,23 @@ pub fn send_request(url: &str, auth: Option<&str>) -> io::Result<String> {
 
     let response = request
         .send()
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        .map_err(|err| io::Error::other(err.to_string()))?;
 
     // 返回 HTTP 响应状态码的字符串
     Ok(response.status().as_u16().to_string())
 }
+
+// 函数：解析大小，支持 K/M/G/T 后缀（按 1024 计算），不带后缀时单位为字节
+pub fn parse_size(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let upper = input.to_ascii_uppercase();
+    let number = upper.trim_end_matches('B');
+    let (number, multiplier) = match number.chars().last()? {
+        'K' => (&number[..number.len() - 1], 1u64 << 10),
+        'M' => (&number[..number.len() - 1], 1 << 20),
+        'G' => (&number[..number.len() - 1], 1 << 30),
+        'T' => (&number[..number.len() - 1], 1 << 40),
+        _ => (number, 1),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
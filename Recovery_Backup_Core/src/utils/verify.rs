@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use tracing::{info, warn};
+use crate::utils::archive::{can_extract_natively, read_archive, safe_join, ArchiveFormat};
+use crate::utils::crypto::{Decrypted, EncryptionKey};
+use crate::utils::events::Stage;
+use crate::utils::leveldb::{current_manifest_name, live_tables};
+use crate::utils::manifest::BackupManifest;
+
+// 恢复世界必须存在的文件
+const REQUIRED_FILES: [&str; 2] = ["level.dat", "db/CURRENT"];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    // 清单或世界必需的文件不在压缩包中
+    Missing,
+    // 压缩包中有清单里没有的文件
+    Unexpected,
+    SizeMismatch,
+    HashMismatch,
+    // MANIFEST 引用的表文件不存在或大小不对
+    MissingTable,
+    // 条目名称非法或内容无法解析
+    Invalid,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileFailure {
+    pub path: String,
+    pub kind: FailureKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct VerifyReport {
+    pub archive: String,
+    pub valid: bool,
+    // 是否找到了清单，没有清单时只检查结构和 LevelDB
+    pub manifest: bool,
+    // 是否为加密的备份，加密的备份解密后再校验
+    pub encrypted: bool,
+    pub world_name: Option<String>,
+    pub created_at: Option<DateTime<Local>>,
+    pub archive_hash_ok: Option<bool>,
+    pub files_checked: u64,
+    pub tables_checked: u64,
+    // 没有清单、内容也无法原生读取 (旧版本插件用 7za 生成的备份)，没有经过校验，只能强制恢复
+    pub unverified: bool,
+    // 压缩包本身无法完整读取时的错误
+    pub error: Option<String>,
+    pub warnings: Vec<String>,
+    pub failures: Vec<FileFailure>,
+    pub duration_ms: u128,
+}
+
+impl VerifyReport {
+    fn fail(&mut self, path: &str, kind: FailureKind, detail: Option<String>) {
+        self.failures.push(FileFailure { path: path.to_string(), kind, detail });
+    }
+
+    // 用于日志和错误信息的简短说明
+    pub fn summary(&self) -> String {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        match self.failures.first() {
+            Some(first) => format!("{} 个文件校验失败，首个为 {} ({:?})", self.failures.len(), first.path, first.kind),
+            None => "校验通过".to_string(),
+        }
+    }
+}
+
+struct ArchivedFile {
+    size: u64,
+    blake3: String,
+}
+
+// 检查压缩包能否完整读取、文件哈希是否与清单一致，以及 LevelDB 的表文件是否齐全，加密的备份需要 key
+pub fn verify_backup(archive: &Path, key: Option<&EncryptionKey>) -> io::Result<VerifyReport> {
+    let decrypted = Decrypted::open(archive, key)?;
+    verify_decrypted(archive, &decrypted)
+}
+
+// 清单在 archive 旁，内容从解密后的压缩包读取
+pub fn verify_decrypted(archive: &Path, decrypted: &Decrypted) -> io::Result<VerifyReport> {
+    let start = Instant::now();
+    let plain = decrypted.path();
+    let archive_size = std::fs::metadata(plain)?.len();
+    let mut report = VerifyReport {
+        archive: archive.to_string_lossy().into_owned(),
+        valid: false,
+        manifest: false,
+        encrypted: decrypted.is_encrypted(),
+        world_name: None,
+        created_at: None,
+        archive_hash_ok: None,
+        files_checked: 0,
+        tables_checked: 0,
+        unverified: false,
+        error: None,
+        warnings: Vec::new(),
+        failures: Vec::new(),
+        duration_ms: 0,
+    };
+
+    let manifest = match BackupManifest::read(archive) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            report.fail(&report.archive.clone(), FailureKind::Invalid, Some(e.to_string()));
+            None
+        }
+    };
+    match &manifest {
+        Some(manifest) => {
+            report.manifest = true;
+            report.world_name = Some(manifest.world_name.clone());
+            report.created_at = Some(manifest.created_at);
+            let check = manifest.check_archive(plain);
+            report.archive_hash_ok = Some(check.is_ok());
+            if let Err(e) = check {
+                report.fail(&manifest.archive.name, FailureKind::HashMismatch, Some(e.to_string()));
+            }
+        }
+        None => report.warnings.push("没有找到备份清单，跳过文件哈希校验".to_string()),
+    }
+
+    let files_total = manifest.as_ref().map_or(0, |manifest| manifest.files.len() as u64);
+    let bytes_total = manifest.as_ref().map_or(archive_size, |manifest| manifest.source_size);
+    let stage = Stage::start("verify", bytes_total, files_total);
+
+    // CURRENT 和 MANIFEST 很小，留在内存中用于检查 LevelDB
+    let mut files = BTreeMap::new();
+    let mut db_meta = BTreeMap::new();
+    let result = read_archive(plain, |name, reader| {
+        let normalized = name.trim_start_matches("./").replace('\\', "/");
+        safe_join(Path::new("."), &normalized)?;
+
+        let mut hasher = blake3::Hasher::new();
+        let size = if normalized == "db/CURRENT" || normalized.starts_with("db/MANIFEST-") {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            hasher.update(&data);
+            let size = data.len() as u64;
+            db_meta.insert(normalized.clone(), data);
+            size
+        } else {
+            io::copy(reader, &mut hasher)?
+        };
+        stage.file_done(&normalized, size);
+        files.insert(normalized, ArchivedFile { size, blake3: hasher.finalize().to_hex().to_string() });
+        Ok(())
+    });
+    stage.finish(&result);
+    match result {
+        // 旧版本插件用 7za 生成的备份没有清单，格式也不能原生解压，只能标记为未校验；能原生读取的格式读取失败说明已损坏
+        Err(e) if manifest.is_none() && !ArchiveFormat::from_path(plain).is_some_and(can_extract_natively) => {
+            report.unverified = true;
+            report.error = Some(format!("没有清单的旧备份无法原生读取，内容未经校验: {}", e));
+            report.duration_ms = start.elapsed().as_millis();
+            warn!("备份 {} 无法原生读取，内容未经校验: {}", archive.display(), e);
+            return Ok(report);
+        }
+        Err(e) => report.error = Some(format!("压缩包无法完整读取: {}", e)),
+        Ok(_) => {}
+    }
+    report.files_checked = files.len() as u64;
+
+    if let Some(manifest) = &manifest {
+        for expected in &manifest.files {
+            match files.get(&expected.path) {
+                None => report.fail(&expected.path, FailureKind::Missing, None),
+                Some(file) if file.size != expected.size => report.fail(
+                    &expected.path,
+                    FailureKind::SizeMismatch,
+                    Some(format!("{} != {}", file.size, expected.size)),
+                ),
+                Some(file) if file.blake3 != expected.blake3 => report.fail(&expected.path, FailureKind::HashMismatch, None),
+                Some(_) => {}
+            }
+        }
+        // 读取失败时后面的文件本来就缺失，不再重复报告多余文件
+        if report.error.is_none() {
+            for path in files.keys() {
+                if !manifest.files.iter().any(|expected| &expected.path == path) {
+                    report.fail(path, FailureKind::Unexpected, None);
+                }
+            }
+        }
+    }
+
+    if report.error.is_none() {
+        for required in REQUIRED_FILES {
+            if !files.contains_key(required) {
+                report.fail(required, FailureKind::Missing, None);
+            }
+        }
+        check_leveldb(&files, &db_meta, &mut report);
+    }
+
+    report.valid = report.error.is_none() && report.failures.is_empty();
+    report.duration_ms = start.elapsed().as_millis();
+    if report.valid {
+        info!("备份 {} 校验通过", archive.display());
+    } else {
+        warn!("备份 {} 校验未通过: {}", archive.display(), report.summary());
+    }
+    Ok(report)
+}
+
+// 确认当前 MANIFEST 引用的每个表文件都在压缩包中且大小一致
+fn check_leveldb(files: &BTreeMap<String, ArchivedFile>, db_meta: &BTreeMap<String, Vec<u8>>, report: &mut VerifyReport) {
+    let Some(current) = db_meta.get("db/CURRENT") else {
+        return;
+    };
+    let manifest_name = match current_manifest_name(current) {
+        Ok(name) => format!("db/{}", name),
+        Err(e) => return report.fail("db/CURRENT", FailureKind::Invalid, Some(e.to_string())),
+    };
+    let Some(manifest) = db_meta.get(&manifest_name) else {
+        return report.fail(&manifest_name, FailureKind::Missing, None);
+    };
+    let tables = match live_tables(manifest) {
+        Ok(tables) => tables,
+        Err(e) => return report.fail(&manifest_name, FailureKind::Invalid, Some(e.to_string())),
+    };
+
+    for table in tables {
+        report.tables_checked += 1;
+        let [ldb, sst] = table.file_names().map(|name| format!("db/{}", name));
+        match files.get(&ldb).or_else(|| files.get(&sst)) {
+            Some(file) if file.size == table.size => {}
+            Some(file) => report.fail(&ldb, FailureKind::MissingTable, Some(format!("大小 {} 与 MANIFEST 中的 {} 不一致", file.size, table.size))),
+            None => report.fail(&ldb, FailureKind::MissingTable, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::utils::archive::{compress_dir, CompressOptions};
+
+    fn verify(path: &Path) -> VerifyReport {
+        verify_backup(path, None).unwrap()
+    }
+
+    // 世界目录: MANIFEST 引用 0 层的 5 号表 (3 字节)，tables 为实际写入的表文件
+    fn write_world(dir: &Path, tables: &[(&str, &[u8])]) {
+        fs::create_dir_all(dir.join("db")).unwrap();
+        fs::write(dir.join("level.dat"), b"level").unwrap();
+        fs::write(dir.join("db/CURRENT"), b"MANIFEST-000001\n").unwrap();
+        let edit = [7, 0, 5, 3, 1, b'a', 1, b'b'];
+        let mut manifest = vec![0, 0, 0, 0, edit.len() as u8, 0, 1];
+        manifest.extend_from_slice(&edit);
+        fs::write(dir.join("db/MANIFEST-000001"), manifest).unwrap();
+        for (name, data) in tables {
+            fs::write(dir.join("db").join(name), data).unwrap();
+        }
+    }
+
+    fn zip(source: &Path, destination: &Path) {
+        compress_dir(source, destination, &CompressOptions { format: ArchiveFormat::Zip, level: 1, threads: 1 }).unwrap();
+    }
+
+    #[test]
+    fn checks_leveldb_tables_without_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        write_world(&dir.path().join("complete"), &[("000005.ldb", b"abc")]);
+        write_world(&dir.path().join("missing"), &[]);
+        zip(&dir.path().join("complete"), &dir.path().join("complete.zip"));
+        zip(&dir.path().join("missing"), &dir.path().join("missing.zip"));
+
+        let report = verify(&dir.path().join("complete.zip"));
+        assert!(report.valid && !report.manifest, "{}", report.summary());
+        assert_eq!(report.tables_checked, 1);
+
+        let report = verify(&dir.path().join("missing.zip"));
+        assert!(!report.valid);
+        assert_eq!(report.failures[0].path, "db/000005.ldb");
+        assert_eq!(report.failures[0].kind, FailureKind::MissingTable);
+    }
+
+    #[test]
+    fn corrupt_archives_without_manifest_are_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        write_world(&dir.path().join("world"), &[("000005.ldb", b"abc")]);
+        let archive = dir.path().join("world.zip");
+        zip(&dir.path().join("world"), &archive);
+        let data = fs::read(&archive).unwrap();
+        fs::write(&archive, &data[..data.len() / 2]).unwrap();
+
+        let report = verify(&archive);
+        assert!(!report.valid && !report.unverified);
+        assert!(report.error.is_some());
+    }
+
+    #[test]
+    fn legacy_7za_archives_are_unverified() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("world.gzip");
+        fs::write(&archive, b"7za output").unwrap();
+
+        let report = verify(&archive);
+        assert!(!report.valid && report.unverified);
+    }
+}
@@ -0,0 +1,494 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Local};
+use futures::future::BoxFuture;
+use futures::{FutureExt, TryStreamExt};
+use quick_xml::events::{BytesRef, Event};
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::NsReader;
+use reqwest::header::{CONTENT_LENGTH, ETAG, LAST_MODIFIED};
+use reqwest::{Client, Method, StatusCode, Url};
+use tracing::info;
+use crate::utils::chunked::{upload_chunked, UploadStrategy};
+use crate::utils::download::fetch_resumable;
+use crate::utils::events::Stage;
+use crate::utils::storage::{Depth, RemoteEntry, StorageBackend};
+use crate::utils::upload::{build_client, with_retry, RetryPolicy, UploadError, UploadOptions};
+
+const DAV: Namespace = Namespace(b"DAV:");
+const OC: Namespace = Namespace(b"http://owncloud.org/ns");
+pub(crate) const OC_CHECKSUM: &str = "OC-Checksum";
+pub(crate) const AMZ_META_SHA256: &str = "x-amz-meta-sha256";
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns"><d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/><d:getetag/><oc:checksums/></d:prop></d:propfind>"#;
+
+// 一个 response 中 200 状态的属性
+#[derive(Default)]
+struct PropResponse {
+    href: String,
+    size: Option<u64>,
+    last_modified: Option<String>,
+    etag: Option<String>,
+    sha256: Option<String>,
+    is_collection: bool,
+    ok: bool,
+}
+
+// 远程目录地址，以 '/' 结尾
+pub fn collection_url(webdav_url: &str, remote_path: &str) -> io::Result<Url> {
+    let remote_path = remote_path.trim_matches('/');
+    let joined = if remote_path.is_empty() {
+        format!("{}/", webdav_url.trim_end_matches('/'))
+    } else {
+        format!("{}/{}/", webdav_url.trim_end_matches('/'), remote_path)
+    };
+    Url::parse(&joined).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的 WebDAV 地址 {}: {}", joined, e)))
+}
+
+fn depth_header(depth: Depth) -> &'static str {
+    match depth {
+        Depth::One => "1",
+        Depth::Infinity => "infinity",
+    }
+}
+
+// WebDAV 存储，使用 basic 认证
+pub struct WebDavBackend {
+    client: Client,
+    // webdav_url 和远程目录的地址，以 '/' 结尾
+    root: Url,
+    base: Url,
+    remote_path: String,
+    username: String,
+    password: String,
+    retry: RetryPolicy,
+    // 已经创建或确认存在的目录地址，同一目录只发送一次 MKCOL
+    created: Mutex<HashSet<String>>,
+}
+
+impl WebDavBackend {
+    pub fn new(webdav_url: &str, remote_path: &str, username: &str, password: &str, allow_insecure: bool, retry: &RetryPolicy) -> io::Result<Self> {
+        Ok(WebDavBackend {
+            client: build_client(allow_insecure)?,
+            root: collection_url(webdav_url, "")?,
+            base: collection_url(webdav_url, remote_path)?,
+            remote_path: remote_path.trim_matches('/').to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            retry: retry.clone(),
+            created: Mutex::new(HashSet::new()),
+        })
+    }
+
+    // key 的地址，每一段单独编码，目录以 '/' 结尾
+    fn url(&self, key: &str, collection: bool) -> io::Result<Url> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "WebDAV 地址不能作为目录"))?
+            .pop_if_empty()
+            .extend(key.split('/').filter(|part| !part.is_empty()))
+            .extend(collection.then_some(""));
+        Ok(url)
+    }
+}
+
+impl StorageBackend for WebDavBackend {
+    fn location(&self, key: &str) -> String {
+        self.url(key, key.is_empty()).map_or_else(|_| key.to_string(), |url| url.to_string())
+    }
+
+    fn prefix(&self) -> &str {
+        &self.remote_path
+    }
+
+    // Nextcloud 和 ownCloud 保存 OC-Checksum，之后在 HEAD 响应中返回
+    fn put<'a>(&'a self, file: &'a Path, key: &'a str, sha256: &'a str, options: &'a UploadOptions, stage: &'a Stage) -> BoxFuture<'a, Result<u32, UploadError>> {
+        async move {
+            let url = self.url(key, false)?;
+            let checksum = format!("SHA256:{}", sha256);
+            upload_file(&self.client, file, url.as_str(), &self.username, &self.password, Some(&checksum), options, stage).await
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str, destination: &'a Path, stage: &'a Stage) -> BoxFuture<'a, Result<u32, UploadError>> {
+        async move {
+            let url = self.url(key, false)?;
+            fetch_resumable(url.as_str(), destination, &self.retry, stage, || Ok(self.client.get(url.clone()).basic_auth(&self.username, Some(&self.password)))).await
+        }
+        .boxed()
+    }
+
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, UploadError>> {
+        async move {
+            let url = self.url(key, false)?;
+            let (data, _) = with_retry(&self.retry, url.as_str(), || async {
+                match self.client.get(url.clone()).basic_auth(&self.username, Some(&self.password)).send().await {
+                    Ok(res) if res.status() == StatusCode::NOT_FOUND => Ok(None),
+                    Ok(res) if res.status().is_success() => res.bytes().await.map(|data| Some(data.to_vec())).map_err(|e| UploadError::network(url.as_str(), e)),
+                    Ok(res) => Err(UploadError::status(url.as_str(), res).await),
+                    Err(e) => Err(UploadError::network(url.as_str(), e)),
+                }
+            })
+            .await?;
+            Ok(data)
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, key: &'a str, depth: Depth) -> BoxFuture<'a, Result<Vec<RemoteEntry>, UploadError>> {
+        async move {
+            let url = self.url(key, true)?;
+            propfind(&self.client, &url, &self.username, &self.password, depth, &self.retry).await
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), UploadError>> {
+        async move {
+            let url = self.url(key, false)?;
+            with_retry(&self.retry, url.as_str(), || async {
+                match self.client.delete(url.clone()).basic_auth(&self.username, Some(&self.password)).send().await {
+                    Ok(res) if res.status().is_success() || res.status() == StatusCode::NOT_FOUND => Ok(()),
+                    Ok(res) => Err(UploadError::status(url.as_str(), res).await),
+                    Err(e) => Err(UploadError::network(url.as_str(), e)),
+                }
+            })
+            .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn stat<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<RemoteEntry>, UploadError>> {
+        async move {
+            let url = self.url(key, false)?;
+            let (entry, _) = with_retry(&self.retry, url.as_str(), || async {
+                match self.client.head(url.clone()).basic_auth(&self.username, Some(&self.password)).send().await {
+                    Ok(res) if res.status() == StatusCode::NOT_FOUND => Ok(None),
+                    Ok(res) if res.status().is_success() => Ok(Some(head_entry(key, url.as_str(), res.headers()))),
+                    Ok(res) => Err(UploadError::status(url.as_str(), res).await),
+                    Err(e) => Err(UploadError::network(url.as_str(), e)),
+                }
+            })
+            .await?;
+            Ok(entry)
+        }
+        .boxed()
+    }
+
+    // 像 mkdir -p 一样从远程目录的第一段开始逐级 MKCOL
+    fn create_dir<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<u32, UploadError>> {
+        async move {
+            let segments: Vec<&str> = self.remote_path.split('/').chain(key.split('/')).filter(|part| !part.is_empty()).collect();
+            let mut attempts = 0;
+            for depth in 1..=segments.len() {
+                let mut url = self.root.clone();
+                url.path_segments_mut()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "WebDAV 地址不能作为目录"))?
+                    .pop_if_empty()
+                    .extend(&segments[..depth])
+                    .push("");
+                if !self.created.lock().unwrap_or_else(|e| e.into_inner()).insert(url.to_string()) {
+                    continue;
+                }
+                match make_collection(&self.client, url.as_str(), &self.username, &self.password, &self.retry).await {
+                    Ok(count) => attempts += count,
+                    Err(e) => {
+                        self.created.lock().unwrap_or_else(|e| e.into_inner()).remove(url.as_str());
+                        return Err(e);
+                    }
+                }
+            }
+            Ok(attempts)
+        }
+        .boxed()
+    }
+}
+
+// 从 "SHA1:... SHA256:..." 形式的 OC-Checksum 或 oc:checksum 中取出 SHA-256
+fn sha256_from_checksums(checksums: &str) -> Option<String> {
+    checksums.split_whitespace().find_map(|checksum| match checksum.split_once(':') {
+        Some((algorithm, value)) if algorithm.eq_ignore_ascii_case("SHA256") => Some(value.to_ascii_lowercase()),
+        _ => None,
+    })
+}
+
+// 由 HEAD 响应头得到文件信息，WebDAV 和 S3 共用，SHA-256 来自 OC-Checksum 或 S3 的 x-amz-meta-sha256
+pub(crate) fn head_entry(key: &str, href: &str, headers: &reqwest::header::HeaderMap) -> RemoteEntry {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let sha256 = header(AMZ_META_SHA256).map(str::to_ascii_lowercase).or_else(|| sha256_from_checksums(header(OC_CHECKSUM)?));
+    RemoteEntry {
+        name: key.rsplit('/').next().unwrap_or(key).to_string(),
+        path: key.to_string(),
+        href: href.to_string(),
+        size: header(CONTENT_LENGTH.as_str()).and_then(|value| value.parse().ok()),
+        last_modified: header(LAST_MODIFIED.as_str()).and_then(|time| DateTime::parse_from_rfc2822(time).ok()).map(|time| time.with_timezone(&Local)),
+        etag: header(ETAG.as_str()).map(|etag| etag.trim_matches('"').to_string()),
+        sha256,
+        is_collection: false,
+    }
+}
+
+// checksum 为 OC-Checksum 的值，例如 SHA256:<hex>
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_file(client: &Client, file_path: &Path, url: &str, username: &str, password: &str, checksum: Option<&str>, options: &UploadOptions, stage: &Stage) -> Result<u32, UploadError> {
+    if options.strategy != UploadStrategy::Single && fs::metadata(file_path)?.len() > options.chunk_size {
+        return upload_chunked(client, file_path, url, username, password, checksum, options, stage).await;
+    }
+
+    let (_, attempts) = with_retry(&options.retry, url, || async {
+        let file = File::open(file_path)?;
+        // 记录本次尝试已发送的字节，失败重试时从进度中撤回
+        let sent = Arc::new(AtomicU64::new(0));
+        let progress = stage.clone();
+        let counter = sent.clone();
+        let file_stream = reqwest::Body::wrap_stream(
+            tokio_util::codec::FramedRead::new(tokio::fs::File::from_std(file), tokio_util::codec::BytesCodec::new()).inspect_ok(move |chunk| {
+                counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                progress.add_bytes(chunk.len() as u64);
+            }),
+        );
+
+        let mut request = client.put(url).basic_auth(username, Some(password)).body(file_stream);
+        if let Some(checksum) = checksum {
+            request = request.header(OC_CHECKSUM, checksum);
+        }
+        let result = match request.send().await {
+            Ok(res) if res.status().is_success() => Ok(()),
+            Ok(res) => Err(UploadError::status(url, res).await),
+            Err(e) => Err(UploadError::network(url, e)),
+        };
+        if result.is_err() {
+            stage.rewind_bytes(sent.load(Ordering::Relaxed));
+        }
+        result
+    })
+    .await?;
+
+    info!("文件上传成功: {}", file_path.display());
+    Ok(attempts)
+}
+
+// 创建远程目录，405 表示目录已存在
+async fn make_collection(client: &Client, url: &str, username: &str, password: &str, retry: &RetryPolicy) -> Result<u32, UploadError> {
+    let method = Method::from_bytes(b"MKCOL").expect("valid method");
+    let (_, attempts) = with_retry(retry, url, || async {
+        match client.request(method.clone(), url).basic_auth(username, Some(password)).send().await {
+            Ok(res) if res.status().is_success() || res.status() == StatusCode::METHOD_NOT_ALLOWED => Ok(()),
+            Ok(res) => Err(UploadError::status(url, res).await),
+            Err(e) => Err(UploadError::network(url, e)),
+        }
+    })
+    .await?;
+    info!("目录已就绪: {}", url);
+    Ok(attempts)
+}
+
+pub async fn propfind(client: &Client, url: &Url, username: &str, password: &str, depth: Depth, retry: &RetryPolicy) -> Result<Vec<RemoteEntry>, UploadError> {
+    let method = Method::from_bytes(b"PROPFIND").expect("valid method");
+    let (body, _) = with_retry(retry, url.as_str(), || async {
+        let request = client
+            .request(method.clone(), url.clone())
+            .basic_auth(username, Some(password))
+            .header("Depth", depth_header(depth))
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY);
+        match request.send().await {
+            Ok(res) if res.status() == StatusCode::MULTI_STATUS => res.text().await.map_err(|e| UploadError::network(url.as_str(), e)),
+            Ok(res) => Err(UploadError::status(url.as_str(), res).await),
+            Err(e) => Err(UploadError::network(url.as_str(), e)),
+        }
+    })
+    .await?;
+
+    let responses = parse_multistatus(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("无法解析 PROPFIND 响应: {}", e)))?;
+    let base_path = percent_decode(url.path());
+    let mut entries = Vec::new();
+    for response in responses.into_iter().filter(|response| response.ok) {
+        // href 可能是绝对地址，也可能只有路径
+        let href_path = url.join(&response.href).map(|href| percent_decode(href.path())).unwrap_or_else(|_| percent_decode(&response.href));
+        let Some(relative) = href_path.strip_prefix(&base_path) else {
+            continue;
+        };
+        let relative = relative.trim_matches('/');
+        if relative.is_empty() {
+            continue;
+        }
+        entries.push(RemoteEntry {
+            name: relative.rsplit('/').next().unwrap_or(relative).to_string(),
+            path: relative.to_string(),
+            href: response.href,
+            size: response.size,
+            last_modified: response
+                .last_modified
+                .and_then(|time| DateTime::parse_from_rfc2822(&time).ok())
+                .map(|time| time.with_timezone(&Local)),
+            etag: response.etag.map(|etag| etag.trim_matches('"').to_string()),
+            sha256: response.sha256,
+            is_collection: response.is_collection,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+// 只关心 DAV: 命名空间中的元素，propstat 的状态不是 200 时丢弃其中的属性
+fn parse_multistatus(xml: &str) -> Result<Vec<PropResponse>, String> {
+    let mut reader = NsReader::from_str(xml);
+    let mut responses = Vec::new();
+    let mut current: Option<PropResponse> = None;
+    let mut propstat = PropResponse::default();
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut text = String::new();
+
+    loop {
+        let (namespace, event) = reader.read_resolved_event().map_err(|e| e.to_string())?;
+        let is_dav = matches!(namespace, ResolveResult::Bound(ns) if ns == DAV);
+        match event {
+            Event::Start(element) => {
+                // ownCloud 命名空间中的元素加上 oc: 前缀，其他命名空间的元素不处理
+                let name = match namespace {
+                    _ if is_dav => element.local_name().as_ref().to_vec(),
+                    ResolveResult::Bound(ns) if ns == OC => [b"oc:", element.local_name().as_ref()].concat(),
+                    _ => Vec::new(),
+                };
+                match name.as_slice() {
+                    b"response" => current = Some(PropResponse::default()),
+                    b"propstat" => propstat = PropResponse::default(),
+                    b"collection" => propstat.is_collection = true,
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            Event::Empty(element) if is_dav && element.local_name().as_ref() == b"collection" => propstat.is_collection = true,
+            Event::Text(content) => text.push_str(&content.decode().map_err(|e| e.to_string())?),
+            Event::CData(content) => text.push_str(&content.decode().map_err(|e| e.to_string())?),
+            Event::GeneralRef(reference) => push_reference(&mut text, &reference)?,
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                let value = text.trim().to_string();
+                text.clear();
+                let Some(response) = current.as_mut() else {
+                    continue;
+                };
+                match name.as_slice() {
+                    b"href" if path.last().is_some_and(|parent| parent == b"response") => response.href = value,
+                    b"getcontentlength" => propstat.size = value.parse().ok(),
+                    b"getlastmodified" => propstat.last_modified = Some(value),
+                    b"getetag" => propstat.etag = Some(value),
+                    b"oc:checksum" => propstat.sha256 = sha256_from_checksums(&value).or(propstat.sha256.take()),
+                    b"status" if path.last().is_some_and(|parent| parent == b"propstat") => {
+                        propstat.ok = value.split_whitespace().nth(1) == Some("200");
+                    }
+                    b"propstat" if propstat.ok => {
+                        response.ok = true;
+                        response.size = propstat.size.or(response.size);
+                        response.last_modified = propstat.last_modified.take().or(response.last_modified.take());
+                        response.etag = propstat.etag.take().or(response.etag.take());
+                        response.sha256 = propstat.sha256.take().or(response.sha256.take());
+                        response.is_collection |= propstat.is_collection;
+                    }
+                    b"response" => responses.extend(current.take()),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(responses)
+}
+
+// 把字符引用或预定义实体追加到 text，WebDAV 和 S3 的响应共用
+pub(crate) fn push_reference(text: &mut String, reference: &BytesRef) -> Result<(), String> {
+    match reference.resolve_char_ref().map_err(|e| e.to_string())? {
+        Some(c) => text.push(c),
+        None => {
+            let name = reference.decode().map_err(|e| e.to_string())?;
+            text.push_str(quick_xml::escape::resolve_predefined_entity(&name).unwrap_or_default());
+        }
+    }
+    Ok(())
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let high = (bytes[index + 1] as char).to_digit(16);
+            let low = (bytes[index + 2] as char).to_digit(16);
+            if let (Some(high), Some(low)) = (high, low) {
+                decoded.push((high * 16 + low) as u8);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Nextcloud 风格的响应: 命名空间前缀不是 D:，缺少的属性放在 404 的 propstat 中
+    const MULTISTATUS: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:s="http://sabredav.org/ns" xmlns:oc="http://owncloud.org/ns">
+ <d:response>
+  <d:href>/remote.php/dav/files/alice/backup/</d:href>
+  <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype><d:getlastmodified>Sun, 18 Oct 2026 12:00:00 GMT</d:getlastmodified></d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>
+  <d:propstat><d:prop><d:getcontentlength/><oc:checksums/></d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/files/alice/backup/world%202026.zip</d:href>
+  <d:propstat><d:prop>
+   <d:resourcetype/>
+   <d:getcontentlength>5000</d:getcontentlength>
+   <d:getetag>&quot;8f2c&quot;</d:getetag>
+   <oc:checksums><oc:checksum>SHA1:da39 SHA256:ABCDEF</oc:checksum></oc:checksums>
+  </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/files/alice/backup/gone.zip</d:href>
+  <d:propstat><d:prop><d:getcontentlength/></d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>
+ </d:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn parses_nextcloud_multistatus() {
+        let responses = parse_multistatus(MULTISTATUS).unwrap();
+        assert_eq!(responses.len(), 3);
+
+        let dir = &responses[0];
+        assert!(dir.ok && dir.is_collection);
+        assert_eq!(dir.href, "/remote.php/dav/files/alice/backup/");
+        assert_eq!((dir.size, dir.sha256.as_deref()), (None, None));
+
+        let file = &responses[1];
+        assert!(file.ok && !file.is_collection);
+        assert_eq!(file.href, "/remote.php/dav/files/alice/backup/world%202026.zip");
+        assert_eq!(file.size, Some(5000));
+        assert_eq!(file.etag.as_deref(), Some("\"8f2c\""));
+        assert_eq!(file.sha256.as_deref(), Some("abcdef"));
+
+        assert!(!responses[2].ok);
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("/backup/world%202026%E5%A4%87%E4%BB%BD.zip"), "/backup/world 2026备份.zip");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+}